@@ -0,0 +1,90 @@
+//! Parsing of `git blame --porcelain` output for the blame popup.
+
+use std::collections::HashMap;
+
+/// One blamed line of the working tree file.
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub sha: String,
+    pub author: String,
+    /// Author date as YYYY-MM-DD, empty if git didn't give one
+    pub date: String,
+    pub line_number: usize,
+    pub content: String,
+}
+
+impl BlameLine {
+    /// Short SHA, author, date, line number and content on one line.
+    pub fn format(&self, author_width: usize) -> String {
+        let sha = if self.sha.chars().all(|c| c == '0') {
+            // git's placeholder commit for lines that aren't committed yet
+            "uncommit"
+        } else {
+            &self.sha[..self.sha.len().min(8)]
+        };
+        format!(
+            "{sha:<8} {:<author_width$} {:<10} {:>4} │ {}",
+            self.author, self.date, self.line_number, self.content
+        )
+    }
+}
+
+/// Parses `git blame --porcelain`. Each line starts with a "<sha> <orig line>
+/// <final line> [<group size>]" header; commit details like the author follow
+/// only the first time a commit appears, and the content line starts with a tab.
+pub fn parse_porcelain(output: &str) -> Vec<BlameLine> {
+    let mut commits: HashMap<String, (String, String)> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+
+    for line in output.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            let Some((sha, line_number)) = current.take() else {
+                continue;
+            };
+            let (author, date) = commits.get(&sha).cloned().unwrap_or_default();
+            lines.push(BlameLine {
+                sha,
+                author,
+                date,
+                line_number,
+                content: content.to_string(),
+            });
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        match (&current, key) {
+            (None, sha) if sha.len() == 40 && sha.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let line_number = value
+                    .split(' ')
+                    .nth(1)
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(0);
+                commits.entry(sha.to_string()).or_default();
+                current = Some((sha.to_string(), line_number));
+            }
+            (Some((sha, _)), "author") => {
+                if let Some(commit) = commits.get_mut(sha) {
+                    commit.0 = value.to_string();
+                }
+            }
+            (Some((sha, _)), "author-time") => {
+                let date = value
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+                    .map(|time| time.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+                if let Some(commit) = commits.get_mut(sha) {
+                    commit.1 = date;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    lines
+}
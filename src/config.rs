@@ -0,0 +1,50 @@
+//! The config file, `watchhound/config.json` in the user's config directory. It
+//! remembers settings changed in the interface, such as the pane width, for the
+//! next session.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Width of the status pane in percent, as last set with < and > or by
+    /// dragging the border
+    pub split_percent: Option<u16>,
+}
+
+/// Where the config file lives: under `$XDG_CONFIG_HOME` or `~/.config`, or
+/// `%APPDATA%` on Windows. `None` when none of these are set.
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let config_dir = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(config_dir.join("watchhound").join("config.json"))
+}
+
+/// Reads the config file at `path`; a missing file is an empty config.
+pub fn load(path: &Path) -> Result<Config> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes `config` to `path`, creating its directory if needed.
+pub fn save(path: &Path, config: &Config) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(config)? + "\n")?;
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Summary dashboard shown in the diff pane instead of a diff: totals for the
+//! working tree plus activity aggregated from the diff history.
+
+use crate::{DiffEntry, AUTOCOMMIT_ENTRY};
+use chrono::{DateTime, Duration, Utc};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{BarChart, Block, Borders, Paragraph, Sparkline},
+    Frame,
+};
+use std::collections::{HashMap, VecDeque};
+
+/// History entries that don't describe a file change
+const PLACEHOLDER_ENTRY: &str = "Initial State";
+
+/// Files listed in the most-changed chart
+const TOP_FILES: usize = 10;
+
+/// Working tree totals shown at the top of the dashboard.
+pub struct Summary {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+fn file_changes(history: &VecDeque<DiffEntry>) -> impl Iterator<Item = &DiffEntry> {
+    history.iter().filter(|entry| {
+        entry.file_name != PLACEHOLDER_ENTRY && !entry.file_name.starts_with(AUTOCOMMIT_ENTRY)
+    })
+}
+
+/// Added and removed lines in a diff, ignoring the `+++`/`---` file headers.
+fn line_counts(diff: &str) -> (u64, u64) {
+    diff.lines().fold((0, 0), |(added, removed), line| {
+        if line.starts_with('+') && !line.starts_with("+++") {
+            (added + 1, removed)
+        } else if line.starts_with('-') && !line.starts_with("---") {
+            (added, removed + 1)
+        } else {
+            (added, removed)
+        }
+    })
+}
+
+/// Added and removed lines per minute over the last `minutes` minutes, oldest first.
+fn per_minute(
+    history: &VecDeque<DiffEntry>,
+    now: DateTime<Utc>,
+    minutes: usize,
+) -> (Vec<u64>, Vec<u64>) {
+    let mut insertions = vec![0; minutes];
+    let mut deletions = vec![0; minutes];
+    for entry in file_changes(history) {
+        let age = (now - entry.timestamp).num_minutes();
+        if age < 0 || age as usize >= minutes {
+            continue;
+        }
+        let bucket = minutes - 1 - age as usize;
+        let (added, removed) = line_counts(&entry.diff_content);
+        insertions[bucket] += added;
+        deletions[bucket] += removed;
+    }
+    (insertions, deletions)
+}
+
+/// Files with the most history entries, most changed first.
+fn most_changed(history: &VecDeque<DiffEntry>, limit: usize) -> Vec<(String, u64)> {
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    for entry in file_changes(history) {
+        *counts.entry(&entry.file_name).or_default() += 1;
+    }
+    let mut counts: Vec<(String, u64)> = counts
+        .into_iter()
+        .map(|(file, count)| (file.to_string(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    counts
+}
+
+/// "45s", "3m 12s" or "2h 5m".
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.num_seconds().max(0);
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+pub fn render(f: &mut Frame, area: Rect, summary: &Summary, history: &VecDeque<DiffEntry>) {
+    let block = Block::default()
+        .title("Dashboard")
+        .borders(Borders::ALL)
+        .style(Style::default().fg(Color::White));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4),
+            Constraint::Length(5),
+            Constraint::Length(5),
+            Constraint::Min(3),
+        ])
+        .split(inner);
+
+    let now = Utc::now();
+    let changes = file_changes(history).count();
+    let last_change = file_changes(history)
+        .map(|entry| entry.timestamp)
+        .max()
+        .map(|timestamp| format!("{} ago", format_elapsed(now - timestamp)))
+        .unwrap_or_else(|| "none yet".to_string());
+    let summary_text = vec![
+        Line::from(vec![
+            Span::raw(format!("Files changed: {}  ", summary.files)),
+            Span::styled(
+                format!("+{}", summary.insertions),
+                Style::default().fg(Color::Green),
+            ),
+            Span::raw(" "),
+            Span::styled(
+                format!("-{}", summary.deletions),
+                Style::default().fg(Color::Red),
+            ),
+        ]),
+        Line::from(format!("Changes this session: {changes}")),
+        Line::from(format!("Last change: {last_change}")),
+    ];
+    f.render_widget(Paragraph::new(summary_text), rows[0]);
+
+    // One sparkline column per minute, as many minutes as fit
+    let minutes = rows[1].width.saturating_sub(2).max(1) as usize;
+    let (insertions, deletions) = per_minute(history, now, minutes);
+    f.render_widget(
+        Sparkline::default()
+            .block(
+                Block::default()
+                    .title(format!("Lines added per minute (last {minutes}m)"))
+                    .borders(Borders::ALL),
+            )
+            .data(&insertions)
+            .style(Style::default().fg(Color::Green)),
+        rows[1],
+    );
+    f.render_widget(
+        Sparkline::default()
+            .block(
+                Block::default()
+                    .title("Lines removed per minute")
+                    .borders(Borders::ALL),
+            )
+            .data(&deletions)
+            .style(Style::default().fg(Color::Red)),
+        rows[2],
+    );
+
+    let files_block = Block::default()
+        .title("Most changed files")
+        .borders(Borders::ALL);
+    let top_files = most_changed(history, TOP_FILES);
+    if top_files.is_empty() {
+        f.render_widget(
+            Paragraph::new("No file changes seen yet").block(files_block),
+            rows[3],
+        );
+    } else {
+        let data: Vec<(&str, u64)> = top_files
+            .iter()
+            .map(|(file, count)| (file.as_str(), *count))
+            .collect();
+        f.render_widget(
+            BarChart::default()
+                .block(files_block)
+                .direction(Direction::Horizontal)
+                .data(data.as_slice())
+                .bar_width(1)
+                .bar_gap(0)
+                .bar_style(Style::default().fg(Color::Yellow))
+                .value_style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+            rows[3],
+        );
+    }
+}
@@ -0,0 +1,592 @@
+//! Reading unified diffs as git prints them: hunk headers, line numbers,
+//! context folding and where to scroll to show what changed.
+
+use crate::differ;
+use std::collections::HashMap;
+
+/// Start of the line that replaces a folded run of context lines.
+pub const FOLD_MARKER: &str = "⋯ (";
+
+/// Start of the line that replaces the end of a diff cut off at --max-diff-lines.
+pub const TRUNCATION_MARKER: &str = "... diff truncated, ";
+
+/// Start of the line put at the top of a diff that isn't valid UTF-8.
+pub const INVALID_UTF8_MARKER: &str = "⚠ Not valid UTF-8";
+
+/// Lines of `diff_text` without line endings. Besides `\n` and `\r\n`, stray
+/// carriage returns at the end of a line are dropped, so a file with mixed or
+/// doubled CRLF endings doesn't leave them in the rendered text.
+pub fn diff_lines(diff_text: &str) -> impl Iterator<Item = &str> {
+    diff_text.lines().map(|line| line.trim_end_matches('\r'))
+}
+
+/// Line noting that undecodable bytes in a diff were replaced.
+pub fn invalid_utf8_line() -> String {
+    format!("{INVALID_UTF8_MARKER}, undecodable bytes are shown as \u{FFFD}\n")
+}
+
+/// Decodes diff output, starting it with an `INVALID_UTF8_MARKER` line if any
+/// of it isn't valid UTF-8 and had to be replaced.
+pub fn decode_diff(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => invalid_utf8_line() + &String::from_utf8_lossy(bytes),
+    }
+}
+
+/// Parses a hunk header like `@@ -12,5 +12,7 @@` into
+/// (old start, old length, new start, new length).
+pub fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    let ranges = line.strip_prefix("@@ ")?;
+    let ranges = &ranges[..ranges.find(" @@")?];
+    let (old, new) = ranges.split_once(' ')?;
+
+    fn parse_range(range: &str) -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    }
+
+    let (old_start, old_len) = parse_range(old.strip_prefix('-')?)?;
+    let (new_start, new_len) = parse_range(new.strip_prefix('+')?)?;
+    Some((old_start, old_len, new_start, new_len))
+}
+
+/// The `index`th hunk of a diff, from its `@@` header up to the next hunk or
+/// file, trailing newline included.
+pub fn hunk(diff_text: &str, index: usize) -> Option<String> {
+    let mut lines = diff_lines(diff_text)
+        .skip_while(|line| !line.starts_with("@@"))
+        .peekable();
+    for _ in 0..index {
+        lines.next()?;
+        while lines.next_if(|line| !line.starts_with("@@")).is_some() {}
+    }
+    let header = lines.next()?;
+    let mut hunk = format!("{header}\n");
+    for line in lines.take_while(|line| !line.starts_with("@@") && !line.starts_with("diff ")) {
+        hunk.push_str(line);
+        hunk.push('\n');
+    }
+    Some(hunk)
+}
+
+/// Old and new file line numbers for every line of a unified diff. Removed lines
+/// only have an old number, added lines only a new one, context lines both.
+pub fn diff_line_numbers(diff_text: &str) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut numbers = Vec::new();
+    let (mut old_line, mut new_line) = (0, 0);
+    let (mut old_remaining, mut new_remaining) = (0, 0);
+
+    for line in diff_text.lines() {
+        let in_hunk = old_remaining > 0 || new_remaining > 0;
+        if !in_hunk {
+            if let Some((old_start, old_len, new_start, new_len)) = parse_hunk_header(line) {
+                (old_line, new_line) = (old_start, new_start);
+                (old_remaining, new_remaining) = (old_len, new_len);
+            }
+            numbers.push((None, None));
+            continue;
+        }
+
+        if let Some(folded) = fold_marker_lines(line) {
+            // A fold marker stands for that many context lines
+            numbers.push((None, None));
+            old_line += folded;
+            new_line += folded;
+            old_remaining = old_remaining.saturating_sub(folded);
+            new_remaining = new_remaining.saturating_sub(folded);
+        } else if line.starts_with('-') {
+            numbers.push((Some(old_line), None));
+            old_line += 1;
+            old_remaining -= 1;
+        } else if line.starts_with('+') {
+            numbers.push((None, Some(new_line)));
+            new_line += 1;
+            new_remaining -= 1;
+        } else if line.starts_with('\\') {
+            // "\ No newline at end of file"
+            numbers.push((None, None));
+        } else {
+            numbers.push((Some(old_line), Some(new_line)));
+            old_line += 1;
+            new_line += 1;
+            old_remaining = old_remaining.saturating_sub(1);
+            new_remaining = new_remaining.saturating_sub(1);
+        }
+    }
+
+    numbers
+}
+
+/// Cuts `diff` down to its first `max_lines` lines, noting how many were left
+/// out. A `max_lines` of 0 keeps everything.
+pub fn truncate_diff(diff: String, max_lines: usize) -> String {
+    let total = diff.lines().count();
+    if max_lines == 0 || total <= max_lines {
+        return diff;
+    }
+
+    let mut truncated: String = diff
+        .lines()
+        .take(max_lines)
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    truncated.push_str(&format!(
+        "{TRUNCATION_MARKER}{} more lines (press X to load full)\n",
+        total - max_lines
+    ));
+    truncated
+}
+
+/// Runs of unchanged context lines longer than `threshold` that are worth
+/// folding, as (first line index, number of lines). The first and last
+/// `threshold / 2` lines of each run stay visible around the fold.
+pub fn context_folds(diff_text: &str, threshold: usize) -> Vec<(usize, usize)> {
+    if threshold == 0 {
+        return Vec::new();
+    }
+
+    let mut folds = Vec::new();
+    let mut in_hunk = false;
+    let mut run_start = 0;
+    let mut run_len = 0;
+    let mut end_run = |run_start: usize, run_len: usize| {
+        let keep = threshold / 2;
+        if run_len > threshold && run_len - 2 * keep > 1 {
+            folds.push((run_start + keep, run_len - 2 * keep));
+        }
+    };
+
+    for (i, line) in diff_text.lines().enumerate() {
+        if in_hunk && line.starts_with(' ') {
+            if run_len == 0 {
+                run_start = i;
+            }
+            run_len += 1;
+            continue;
+        }
+        end_run(run_start, run_len);
+        run_len = 0;
+        if line.starts_with("@@") {
+            in_hunk = true;
+        } else if line.starts_with("diff ") {
+            in_hunk = false;
+        }
+    }
+    end_run(run_start, run_len);
+
+    folds
+}
+
+/// `diff_text` with each fold's lines replaced by a single marker line.
+pub fn fold_diff(diff_text: &str, folds: &[(usize, usize)]) -> String {
+    let mut folded = String::new();
+    let mut folds = folds.iter().peekable();
+    let mut lines = diff_text.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        if let Some((_, len)) = folds.next_if(|(start, _)| *start == i) {
+            folded.push_str(&format!("{FOLD_MARKER}{len} lines)\n"));
+            // The marker stands in for this line and the rest of the fold
+            lines.nth(len - 2);
+            continue;
+        }
+        folded.push_str(line);
+        folded.push('\n');
+    }
+    folded
+}
+
+/// Number of lines a fold marker line stands for.
+fn fold_marker_lines(line: &str) -> Option<usize> {
+    line.strip_prefix(FOLD_MARKER)?
+        .strip_suffix(" lines)")?
+        .parse()
+        .ok()
+}
+
+/// The conflict marker on a diff line, e.g. `<<<<<<< HEAD`, after the diff's
+/// prefix column (two columns in git's combined diff of an unmerged file).
+pub fn conflict_marker(line: &str) -> Option<&str> {
+    [1, 2].into_iter().find_map(|width| {
+        let prefix = line.get(..width)?;
+        if !prefix.chars().all(|c| matches!(c, '+' | '-' | ' ')) {
+            return None;
+        }
+        let marker = &line[width..];
+        let is_marker = ["<<<<<<<", "|||||||", "=======", ">>>>>>>"]
+            .iter()
+            .any(|kind| {
+                marker.strip_prefix(kind).is_some_and(|rest| {
+                    rest.is_empty() || (rest.starts_with(' ') && !kind.starts_with('='))
+                })
+            });
+        is_marker.then_some(marker)
+    })
+}
+
+/// Word-level segments for removed/added line pairs, keyed by line index. Within
+/// each run of removed lines followed by added lines, the n-th removed line is
+/// compared with the n-th added line; unpaired lines are left out.
+pub fn word_diff_segments(diff_text: &str) -> HashMap<usize, Vec<(String, bool)>> {
+    let lines: Vec<&str> = diff_text.lines().collect();
+    let is_removed = |line: &str| line.starts_with('-') && !line.starts_with("---");
+    let is_added = |line: &str| line.starts_with('+') && !line.starts_with("+++");
+
+    let mut segments = HashMap::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_removed(lines[i]) {
+            i += 1;
+            continue;
+        }
+
+        let removed_start = i;
+        while i < lines.len() && is_removed(lines[i]) {
+            i += 1;
+        }
+        let added_start = i;
+        while i < lines.len() && is_added(lines[i]) {
+            i += 1;
+        }
+
+        let pairs = (added_start - removed_start).min(i - added_start);
+        for offset in 0..pairs {
+            let (old_index, new_index) = (removed_start + offset, added_start + offset);
+            if let Some((old, new)) =
+                differ::word_diff(&lines[old_index][1..], &lines[new_index][1..])
+            {
+                segments.insert(old_index, old);
+                segments.insert(new_index, new);
+            }
+        }
+    }
+
+    segments
+}
+
+/// File path named by a `diff --git a/... b/...` or `+++ b/...` header line.
+pub fn diff_header_path(line: &str) -> Option<&str> {
+    if line.starts_with("diff --git ") {
+        line.rsplit_once(" b/").map(|(_, path)| path)
+    } else {
+        line.strip_prefix("+++ b/")
+    }
+}
+
+/// Whether `line` is git's "Binary files a/x and b/x differ" marker.
+pub fn is_binary_diff_line(line: &str) -> bool {
+    line.starts_with("Binary files ") && line.ends_with(" differ")
+}
+
+/// What the diff pane shows in place of git's binary marker: the size change
+/// when the sizes are known.
+pub fn binary_change_summary(sizes: Option<(u64, u64)>) -> String {
+    match sizes {
+        Some((before, after)) => format!(
+            "Binary file changed: {before} → {after} bytes ({:+} bytes)",
+            after as i64 - before as i64
+        ),
+        None => "Binary file changed (contents not shown)".to_string(),
+    }
+}
+
+/// Replaces the binary marker in `diff` with a summary of the size change.
+pub fn describe_binary_diff(diff: &str, sizes: Option<(u64, u64)>) -> String {
+    diff.lines()
+        .map(|line| {
+            if is_binary_diff_line(line) {
+                format!("{}\n", binary_change_summary(sizes))
+            } else {
+                format!("{line}\n")
+            }
+        })
+        .collect()
+}
+
+/// Whether `line` is an added or removed line rather than a `+++`/`---` header.
+fn is_change_line(line: &str) -> bool {
+    (line.starts_with('+') && !line.starts_with("+++"))
+        || (line.starts_with('-') && !line.starts_with("---"))
+}
+
+/// Index of the first added or removed line among `lines`.
+fn first_change_line<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<usize> {
+    lines.into_iter().position(is_change_line)
+}
+
+/// Scroll position showing the first change in `current_diff` that wasn't in
+/// `previous_diff`, a few lines of context above it.
+pub fn find_first_diff_line(current_diff: &str, previous_diff: &str) -> u16 {
+    let current_lines: Vec<&str> = diff_lines(current_diff).collect();
+    let previous_lines: Vec<&str> = diff_lines(previous_diff).collect();
+
+    // Find the first line that's different between current and previous diff
+    let mut first_different_line = None;
+    for (i, current_line) in current_lines.iter().enumerate() {
+        if i >= previous_lines.len() || current_line != &previous_lines[i] {
+            first_different_line = Some(i);
+            break;
+        }
+    }
+
+    if let Some(diff_start) = first_different_line {
+        // Now find the first actual content change (+ or - line) starting from the
+        // different line, and scroll to show it with context
+        if let Some(offset) = first_change_line(current_lines[diff_start..].iter().copied()) {
+            return ((diff_start + offset) as u16).saturating_sub(3);
+        }
+
+        // If no content changes found, just scroll to the first different line
+        return (diff_start as u16).saturating_sub(2);
+    }
+
+    // If we get here, current diff is same as previous (shouldn't happen)
+    // Fall back to smart scroll
+    smart_scroll_position(current_diff)
+}
+
+/// Scroll position for a diff seen for the first time: its first addition or
+/// deletion with some context, or near the end if nothing changed.
+pub fn smart_scroll_position(diff_content: &str) -> u16 {
+    let lines: Vec<&str> = diff_lines(diff_content).collect();
+
+    if let Some(first_line) = first_change_line(lines.iter().copied()) {
+        // Show the change with some context lines before it
+        (first_line as u16).saturating_sub(3)
+    } else {
+        // If no changes found, look for the end of the diff
+        let total_lines = lines.len() as u16;
+        if total_lines > 10 {
+            total_lines.saturating_sub(8)
+        } else {
+            0
+        }
+    }
+}
+
+/// Row of the history view where an entry following `previous` entries starts,
+/// counting the three separator lines between entries.
+pub fn scroll_position_for_new_diff<'a>(previous: impl IntoIterator<Item = &'a str>) -> u16 {
+    // An unlimited history can outgrow u16, so saturate rather than overflow
+    let mut lines_count = 0u16;
+    for (i, diff) in previous.into_iter().enumerate() {
+        if i > 0 {
+            lines_count = lines_count.saturating_add(3); // For separator lines
+        }
+        let lines = diff.lines().count().min(u16::MAX as usize) as u16;
+        lines_count = lines_count.saturating_add(lines);
+    }
+    lines_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINARY_DIFF: &str = "diff --git a/logo.png b/logo.png\n\
+                               index 1a2b3c4..5d6e7f8 100644\n\
+                               Binary files a/logo.png and b/logo.png differ\n";
+
+    #[test]
+    fn recognizes_the_binary_marker() {
+        assert!(is_binary_diff_line(
+            "Binary files a/logo.png and b/logo.png differ"
+        ));
+        assert!(is_binary_diff_line(
+            "Binary files /dev/null and b/logo.png differ"
+        ));
+        assert!(!is_binary_diff_line("+Binary files are listed here"));
+    }
+
+    #[test]
+    fn describes_a_binary_diff_by_its_size_change() {
+        assert_eq!(
+            describe_binary_diff(BINARY_DIFF, Some((1024, 1536))),
+            "diff --git a/logo.png b/logo.png\n\
+             index 1a2b3c4..5d6e7f8 100644\n\
+             Binary file changed: 1024 → 1536 bytes (+512 bytes)\n"
+        );
+    }
+
+    #[test]
+    fn a_shrinking_binary_file_shows_a_negative_change() {
+        assert_eq!(
+            binary_change_summary(Some((2048, 2000))),
+            "Binary file changed: 2048 → 2000 bytes (-48 bytes)"
+        );
+    }
+
+    #[test]
+    fn unknown_sizes_leave_the_contents_unmentioned() {
+        assert!(describe_binary_diff(BINARY_DIFF, None)
+            .ends_with("Binary file changed (contents not shown)\n"));
+    }
+
+    /// A diff whose first change, the added line, is at index 7.
+    const ADDITION_DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                                 index 1a2b3c4..5d6e7f8 100644\n\
+                                 --- a/src/lib.rs\n\
+                                 +++ b/src/lib.rs\n\
+                                 @@ -1,4 +1,5 @@\n \
+                                 fn main() {\n     \
+                                 setup();\n\
+                                 +    log();\n     \
+                                 run();\n \
+                                 }\n";
+
+    #[test]
+    fn smart_scroll_shows_context_above_the_first_change() {
+        assert_eq!(smart_scroll_position(ADDITION_DIFF), 4);
+    }
+
+    #[test]
+    fn smart_scroll_without_changes_goes_near_the_end() {
+        let context: String = (1..=20).map(|n| format!(" line {n}\n")).collect();
+        assert_eq!(smart_scroll_position(&context), 12);
+        assert_eq!(smart_scroll_position(" one\n two\n"), 0);
+        assert_eq!(smart_scroll_position(""), 0);
+    }
+
+    #[test]
+    fn first_diff_line_skips_changes_already_seen() {
+        let current = format!(
+            "{ADDITION_DIFF}@@ -20,3 +21,4 @@\n \
+             fn helper() {{\n     \
+             work();\n\
+             +    more_work();\n \
+             }}\n"
+        );
+        // The new hunk's added line is at index 13, past the one already shown
+        assert_eq!(find_first_diff_line(&current, ADDITION_DIFF), 10);
+        assert_eq!(find_first_diff_line(ADDITION_DIFF, ""), 4);
+    }
+
+    #[test]
+    fn first_diff_line_without_new_changes_scrolls_to_what_differs() {
+        // Only a context line differs, so there's no change line after it
+        let previous = ADDITION_DIFF.replace("     run();", "     run(1);");
+        assert_eq!(find_first_diff_line(ADDITION_DIFF, &previous), 6);
+        // Nothing differs at all
+        assert_eq!(
+            find_first_diff_line(ADDITION_DIFF, ADDITION_DIFF),
+            smart_scroll_position(ADDITION_DIFF)
+        );
+    }
+
+    #[test]
+    fn history_entries_start_after_the_previous_ones_and_separators() {
+        assert_eq!(scroll_position_for_new_diff([]), 0);
+        assert_eq!(scroll_position_for_new_diff(["a\nb\nc\n"]), 3);
+        assert_eq!(
+            scroll_position_for_new_diff(["a\nb\nc\n", "d\n", "e\nf"]),
+            12
+        );
+    }
+
+    #[test]
+    fn history_scroll_position_saturates() {
+        let long = "x\n".repeat(40_000);
+        assert_eq!(
+            scroll_position_for_new_diff([long.as_str(), long.as_str()]),
+            u16::MAX
+        );
+    }
+
+    #[test]
+    fn smart_scroll_finds_a_deletion_only_change() {
+        let deletion = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                        index 1a2b3c4..5d6e7f8 100644\n\
+                        --- a/src/lib.rs\n\
+                        +++ b/src/lib.rs\n\
+                        @@ -1,5 +1,4 @@\n \
+                        fn main() {\n     \
+                        setup();\n\
+                        -    legacy();\n     \
+                        run();\n \
+                        }\n";
+        assert_eq!(smart_scroll_position(deletion), 4);
+        assert_eq!(find_first_diff_line(deletion, ""), 4);
+    }
+
+    #[test]
+    fn smart_scroll_finds_the_first_change_of_a_mixed_diff() {
+        let mixed = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                     index 1a2b3c4..5d6e7f8 100644\n\
+                     --- a/src/lib.rs\n\
+                     +++ b/src/lib.rs\n\
+                     @@ -1,7 +1,7 @@\n \
+                     fn main() {\n     \
+                     setup();\n     \
+                     configure();\n     \
+                     load();\n\
+                     -    legacy();\n\
+                     +    modern();\n     \
+                     run();\n";
+        // The deletion comes first; the --- and +++ headers don't count
+        assert_eq!(smart_scroll_position(mixed), 6);
+        let added_only = mixed.replace("-    legacy();\n", "");
+        assert_eq!(smart_scroll_position(&added_only), 6);
+    }
+
+    #[test]
+    fn line_endings_are_stripped_from_crlf_diffs() {
+        let lines: Vec<&str> = diff_lines("+one\r\n-two\r\r\n three\n").collect();
+        assert_eq!(lines, ["+one", "-two", " three"]);
+    }
+
+    #[test]
+    fn crlf_diffs_scroll_like_lf_ones() {
+        let crlf = ADDITION_DIFF.replace('\n', "\r\n");
+        let doubled = ADDITION_DIFF.replace('\n', "\r\r\n");
+        for diff in [&crlf, &doubled] {
+            assert_eq!(smart_scroll_position(diff), 4);
+            assert_eq!(find_first_diff_line(diff, ""), 4);
+            // Only the line endings changed, which isn't a new change
+            assert_eq!(find_first_diff_line(diff, ADDITION_DIFF), 4);
+            assert_eq!(hunk(diff, 0), hunk(ADDITION_DIFF, 0));
+            assert_eq!(diff_line_numbers(diff), diff_line_numbers(ADDITION_DIFF));
+        }
+    }
+
+    #[test]
+    fn valid_utf8_is_decoded_unchanged() {
+        assert_eq!(decode_diff("+ääkköset\n".as_bytes()), "+ääkköset\n");
+    }
+
+    #[test]
+    fn invalid_utf8_is_flagged_and_replaced() {
+        let decoded = decode_diff(b"+caf\xe9\n");
+        let mut lines = diff_lines(&decoded);
+        assert!(lines.next().unwrap().starts_with(INVALID_UTF8_MARKER));
+        assert_eq!(lines.next(), Some("+caf\u{FFFD}"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn the_invalid_utf8_line_keeps_changes_and_hunks_in_place() {
+        let bytes = ADDITION_DIFF
+            .replace("log()", "log(\u{1}\u{2})")
+            .into_bytes();
+        let bytes = [&bytes[..], b"+\xff\n"].concat();
+        let decoded = decode_diff(&bytes);
+        let lines: Vec<&str> = diff_lines(&decoded).collect();
+        assert!(lines[0].starts_with(INVALID_UTF8_MARKER));
+
+        // The marker takes a row of its own, so every position is one further
+        // down but still lands on the same line
+        let position = smart_scroll_position(&decoded) as usize;
+        assert_eq!(position, 5);
+        assert_eq!(lines[position + 3], "+    log(\u{1}\u{2});");
+        let position = find_first_diff_line(&decoded, "") as usize;
+        assert_eq!(lines[position + 3], "+    log(\u{1}\u{2});");
+        assert_eq!(find_first_diff_line(&decoded, &decoded), 5);
+
+        assert!(hunk(&decoded, 0).unwrap().starts_with("@@ -1,4 +1,5 @@\n"));
+        let numbers = diff_line_numbers(&decoded);
+        assert_eq!(numbers[0], (None, None));
+        assert_eq!(numbers[8], (None, Some(3)));
+        assert_eq!(numbers[9], (Some(3), Some(4)));
+    }
+}
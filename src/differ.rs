@@ -0,0 +1,204 @@
+//! A small LCS-based differ used where git isn't available to do the work.
+
+/// Number of unchanged lines shown around each change by default, matching git.
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+/// Above this many cells the differ gives up and reports a full rewrite.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Edit script turning `old` into `new`, one op per line (or word).
+pub fn diff_ops<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops = vec![Op::Equal; prefix];
+
+    if old_mid.len() * new_mid.len() > MAX_LCS_CELLS {
+        ops.extend(std::iter::repeat_n(Op::Delete, old_mid.len()));
+        ops.extend(std::iter::repeat_n(Op::Insert, new_mid.len()));
+    } else {
+        // Longest common subsequence table, filled from the end
+        let (n, m) = (old_mid.len(), new_mid.len());
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                ops.push(Op::Equal);
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                ops.push(Op::Delete);
+                i += 1;
+            } else {
+                ops.push(Op::Insert);
+                j += 1;
+            }
+        }
+    }
+
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    ops
+}
+
+/// Renders `@@`-delimited hunks with the usual `+`/`-`/space line prefixes and
+/// `context` unchanged lines around each change.
+pub fn unified_diff(old: &[&str], new: &[&str], context: usize) -> String {
+    let ops = diff_ops(old, new);
+
+    // Position in the old and new files before each op
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for op in &ops {
+        positions.push((o, n));
+        match op {
+            Op::Equal => {
+                o += 1;
+                n += 1;
+            }
+            Op::Delete => o += 1,
+            Op::Insert => n += 1,
+        }
+    }
+    positions.push((o, n));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i] != Op::Equal).collect();
+    let mut output = String::new();
+    let mut index = 0;
+
+    while index < changes.len() {
+        // Extend the hunk while the gap to the next change fits within the shared context
+        let mut last = index;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= context * 2 + 1 {
+            last += 1;
+        }
+
+        let start = changes[index].saturating_sub(context);
+        let end = (changes[last] + context + 1).min(ops.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+
+        for (op, &(o, n)) in ops[start..end].iter().zip(&positions[start..end]) {
+            match op {
+                Op::Equal => output.push_str(&format!(" {}\n", old[o])),
+                Op::Delete => output.push_str(&format!("-{}\n", old[o])),
+                Op::Insert => output.push_str(&format!("+{}\n", new[n])),
+            }
+        }
+
+        index = last + 1;
+    }
+
+    output
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Splits a line into words, runs of whitespace, and single punctuation characters.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let end = if is_word(c) || c.is_whitespace() {
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, next)) = chars.peek() {
+                let same_kind = if is_word(c) {
+                    is_word(next)
+                } else {
+                    next.is_whitespace()
+                };
+                if !same_kind {
+                    break;
+                }
+                end = j + next.len_utf8();
+                chars.next();
+            }
+            end
+        } else {
+            i + c.len_utf8()
+        };
+        tokens.push(&line[i..end]);
+    }
+
+    tokens
+}
+
+/// Word-level comparison of a removed line against the added line replacing it.
+/// Returns each line split into `(text, changed)` segments, or `None` when the
+/// lines have no words in common and whole-line colouring says it better.
+#[allow(clippy::type_complexity)]
+pub fn word_diff(old: &str, new: &str) -> Option<(Vec<(String, bool)>, Vec<(String, bool)>)> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let ops = diff_ops(&old_tokens, &new_tokens);
+
+    let mut old_segments: Vec<(String, bool)> = Vec::new();
+    let mut new_segments: Vec<(String, bool)> = Vec::new();
+    let push =
+        |segments: &mut Vec<(String, bool)>, text: &str, changed: bool| match segments.last_mut() {
+            Some((last, last_changed)) if *last_changed == changed => last.push_str(text),
+            _ => segments.push((text.to_string(), changed)),
+        };
+
+    let (mut o, mut n) = (0, 0);
+    let mut shares_words = false;
+    for op in ops {
+        match op {
+            Op::Equal => {
+                shares_words |= !old_tokens[o].trim().is_empty();
+                push(&mut old_segments, old_tokens[o], false);
+                push(&mut new_segments, new_tokens[n], false);
+                o += 1;
+                n += 1;
+            }
+            Op::Delete => {
+                push(&mut old_segments, old_tokens[o], true);
+                o += 1;
+            }
+            Op::Insert => {
+                push(&mut new_segments, new_tokens[n], true);
+                n += 1;
+            }
+        }
+    }
+
+    shares_words.then_some((old_segments, new_segments))
+}
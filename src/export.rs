@@ -0,0 +1,132 @@
+use crate::DiffEntry;
+use anyhow::Result;
+use ratatui::style::Color;
+use std::{fs, path::Path};
+use watchhound::{diff::diff_lines, render::parse_diff_line};
+
+/// Writes the diff history to `path`, as HTML for `.html`/`.htm` files and as
+/// markdown otherwise.
+pub fn export_history(path: &Path, entries: &[DiffEntry]) -> Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let content = match extension.as_str() {
+        "html" | "htm" => to_html(entries),
+        _ => to_markdown(entries),
+    };
+    fs::write(path, content)?;
+    Ok(())
+}
+
+fn heading(index: usize, entry: &DiffEntry) -> String {
+    format!(
+        "Update {} at {} (File: {})",
+        index + 1,
+        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        entry.file_name
+    )
+}
+
+fn to_markdown(entries: &[DiffEntry]) -> String {
+    let mut markdown = String::from("# WatchHound diff history\n");
+    for (i, entry) in entries.iter().enumerate() {
+        // Use a longer fence than any backtick run in the diff so it can't close early
+        let longest_run = entry
+            .diff_content
+            .split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+
+        markdown.push_str(&format!("\n## {}\n\n", heading(i, entry)));
+        markdown.push_str(&format!("{fence}diff\n"));
+        markdown.push_str(&entry.diff_content);
+        if !entry.diff_content.ends_with('\n') {
+            markdown.push('\n');
+        }
+        markdown.push_str(&format!("{fence}\n"));
+    }
+    markdown
+}
+
+fn to_html(entries: &[DiffEntry]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>WatchHound diff history</title>\n\
+         <style>body { background: #000; color: #fff; font-family: sans-serif; } \
+         pre { font-family: monospace; }</style>\n\
+         </head>\n<body>\n<h1>WatchHound diff history</h1>\n",
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        html.push_str(&format!("<h2>{}</h2>\n<pre>", escape(&heading(i, entry))));
+        for line in diff_lines(&entry.diff_content) {
+            // Colour lines exactly as the diff pane does
+            for span in parse_diff_line(line).spans {
+                let color = span.style.fg.map(css_color).unwrap_or("inherit");
+                html.push_str(&format!(
+                    "<span style=\"color: {color}\">{}</span>",
+                    escape(&span.content)
+                ));
+            }
+            html.push('\n');
+        }
+        html.push_str("</pre>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Colours diff text for a terminal with ANSI escape codes, the same way the diff
+/// pane does. Without `color` the text is returned as is.
+pub fn to_ansi(diff_text: &str, color: bool) -> String {
+    if !color {
+        return diff_text.to_string();
+    }
+    let mut ansi = String::new();
+    for line in diff_lines(diff_text) {
+        for span in parse_diff_line(line).spans {
+            match span.style.fg.and_then(ansi_color) {
+                Some(code) => ansi.push_str(&format!("\x1b[{code}m{}\x1b[0m", span.content)),
+                None => ansi.push_str(&span.content),
+            }
+        }
+        ansi.push('\n');
+    }
+    ansi
+}
+
+/// SGR foreground code for `color`; the terminal's own colour is used otherwise.
+fn ansi_color(color: Color) -> Option<u8> {
+    match color {
+        Color::Cyan => Some(36),
+        Color::Green => Some(32),
+        Color::Red => Some(31),
+        Color::Gray => Some(37),
+        Color::DarkGray => Some(90),
+        Color::Yellow => Some(33),
+        Color::Magenta => Some(35),
+        _ => None,
+    }
+}
+
+fn css_color(color: Color) -> &'static str {
+    match color {
+        Color::Cyan => "#00cdcd",
+        Color::Green => "#00cd00",
+        Color::Red => "#cd0000",
+        Color::Gray => "#bfbfbf",
+        Color::DarkGray => "#7f7f7f",
+        Color::Yellow => "#cdcd00",
+        _ => "inherit",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,57 @@
+//! Subsequence matching for jumping to a file by typing parts of its path.
+
+/// Character indices of `candidate` matching the characters of `query` in order,
+/// ignoring ASCII case, or `None` if it doesn't contain them all.
+pub fn matched_positions(query: &str, candidate: &str) -> Option<Vec<usize>> {
+    let mut positions = Vec::new();
+    let mut chars = candidate.chars().enumerate();
+    for wanted in query.chars() {
+        let (index, _) = chars.find(|(_, c)| c.eq_ignore_ascii_case(&wanted))?;
+        positions.push(index);
+    }
+    Some(positions)
+}
+
+/// How well `query` matches `candidate`, higher being better, or `None` if it
+/// doesn't match. Runs of consecutive characters and matches at the start of a
+/// path component or word, or in the file name, count extra.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let positions = matched_positions(query, candidate)?;
+    let chars: Vec<char> = candidate.chars().collect();
+    let file_name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    let mut score = 0;
+    for (i, &position) in positions.iter().enumerate() {
+        score += 1;
+        if i > 0 && positions[i - 1] + 1 == position {
+            score += 5;
+        }
+        if position == 0 || matches!(chars[position - 1], '/' | '_' | '-' | '.') {
+            score += 3;
+        }
+        if position >= file_name_start {
+            score += 2;
+        }
+    }
+    Some(score)
+}
+
+/// Indices of the `candidates` matching `query`, best match first. Ties go to
+/// the shorter path, then to the original order. An empty query keeps them all
+/// in order.
+pub fn rank(query: &str, candidates: &[String]) -> Vec<usize> {
+    let mut ranked: Vec<(usize, i64)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, candidate)| Some((i, score(query, candidate)?)))
+        .collect();
+    ranked.sort_by(|(a, a_score), (b, b_score)| {
+        b_score
+            .cmp(a_score)
+            .then_with(|| candidates[*a].len().cmp(&candidates[*b].len()))
+            .then_with(|| a.cmp(b))
+    });
+    if query.is_empty() {
+        ranked.sort_by_key(|(i, _)| *i);
+    }
+    ranked.into_iter().map(|(i, _)| i).collect()
+}
@@ -0,0 +1,306 @@
+use ratatui::{
+    style::{Color, Style},
+    text::Span,
+};
+use std::path::Path;
+
+/// Just enough of a language's lexical rules to colour keywords, strings,
+/// comments and numbers.
+#[derive(Debug)]
+pub struct Language {
+    keywords: &'static [&'static str],
+    line_comment: &'static [&'static str],
+    string_quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: &["//"],
+    string_quotes: &['"'],
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+        "try", "while", "with", "yield",
+    ],
+    line_comment: &["#"],
+    string_quotes: &['"', '\''],
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    line_comment: &["//"],
+    string_quotes: &['"', '\'', '`'],
+};
+
+const GO: Language = Language {
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "for",
+        "func",
+        "go",
+        "goto",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    line_comment: &["//"],
+    string_quotes: &['"', '`'],
+};
+
+const C_LIKE: Language = Language {
+    keywords: &[
+        "abstract",
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "catch",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "final",
+        "float",
+        "for",
+        "if",
+        "import",
+        "include",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "null",
+        "nullptr",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "var",
+        "virtual",
+        "void",
+        "volatile",
+        "while",
+    ],
+    line_comment: &["//"],
+    string_quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "return", "then", "while",
+    ],
+    line_comment: &["#"],
+    string_quotes: &['"', '\''],
+};
+
+const POWERSHELL: Language = Language {
+    keywords: &[
+        "begin", "break", "catch", "continue", "do", "else", "elseif", "end", "finally", "for",
+        "foreach", "function", "if", "in", "param", "process", "return", "switch", "throw", "trap",
+        "try", "until", "while",
+    ],
+    line_comment: &["#"],
+    string_quotes: &['"', '\''],
+};
+
+const TOML: Language = Language {
+    keywords: &["true", "false"],
+    line_comment: &["#"],
+    string_quotes: &['"', '\''],
+};
+
+impl Language {
+    /// Picks a language from the file extension, or `None` when it isn't known.
+    pub fn from_path(path: &str) -> Option<&'static Language> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "rs" => Some(&RUST),
+            "py" => Some(&PYTHON),
+            "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => Some(&JAVASCRIPT),
+            "go" => Some(&GO),
+            "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "kt" | "swift" => Some(&C_LIKE),
+            "sh" | "bash" | "zsh" => Some(&SHELL),
+            "ps1" | "psm1" => Some(&POWERSHELL),
+            "toml" => Some(&TOML),
+            _ => None,
+        }
+    }
+
+    /// Splits a line of source code into coloured spans, each layered on `base`.
+    pub fn highlight(&self, code: &str, base: Style) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+        let mut plain = String::new();
+        let chars: Vec<char> = code.chars().collect();
+        let offsets: Vec<usize> = code.char_indices().map(|(offset, _)| offset).collect();
+        let mut i = 0;
+
+        let flush = |plain: &mut String, spans: &mut Vec<Span<'static>>| {
+            if !plain.is_empty() {
+                spans.push(Span::styled(std::mem::take(plain), base));
+            }
+        };
+
+        while i < chars.len() {
+            let rest = &code[offsets[i]..];
+            let c = chars[i];
+
+            if self
+                .line_comment
+                .iter()
+                .any(|marker| rest.starts_with(marker))
+            {
+                flush(&mut plain, &mut spans);
+                spans.push(Span::styled(rest.to_string(), base.fg(Color::DarkGray)));
+                return spans;
+            }
+
+            if self.string_quotes.contains(&c) {
+                flush(&mut plain, &mut spans);
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != c {
+                    // Skip escaped characters so \" doesn't end the string
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                let end = (end + 1).min(chars.len());
+                spans.push(Span::styled(
+                    chars[i..end].iter().collect::<String>(),
+                    base.fg(Color::Yellow),
+                ));
+                i = end;
+                continue;
+            }
+
+            if c.is_ascii_digit() {
+                flush(&mut plain, &mut spans);
+                let end = (i..chars.len())
+                    .find(|&j| {
+                        !(chars[j].is_ascii_alphanumeric() || chars[j] == '.' || chars[j] == '_')
+                    })
+                    .unwrap_or(chars.len());
+                spans.push(Span::styled(
+                    chars[i..end].iter().collect::<String>(),
+                    base.fg(Color::LightCyan),
+                ));
+                i = end;
+                continue;
+            }
+
+            if c.is_alphabetic() || c == '_' {
+                let end = (i..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                    .unwrap_or(chars.len());
+                let word: String = chars[i..end].iter().collect();
+                if self.keywords.contains(&word.as_str()) {
+                    flush(&mut plain, &mut spans);
+                    spans.push(Span::styled(word, base.fg(Color::Magenta)));
+                } else {
+                    plain.push_str(&word);
+                }
+                i = end;
+                continue;
+            }
+
+            plain.push(c);
+            i += 1;
+        }
+
+        flush(&mut plain, &mut spans);
+        spans
+    }
+}
@@ -0,0 +1,98 @@
+//! Inline images for terminals that speak the Kitty or iTerm2 graphics
+//! protocol, used to show changed image files in the diff pane. The escape
+//! sequences are written straight to the terminal, outside ratatui's buffer.
+
+use std::path::Path;
+
+/// Base64 characters per chunk of a Kitty image, the most the protocol allows.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Kitty sequence deleting every image placed on the screen.
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    /// Kitty's graphics protocol, also spoken by Ghostty and WezTerm
+    Kitty,
+    /// iTerm2's inline images, also spoken by WezTerm
+    Iterm2,
+}
+
+impl GraphicsProtocol {
+    /// The protocol the terminal speaks, going by the variables it sets, or
+    /// `None` when there is no sign of either.
+    pub fn detect(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if var("TERM").as_deref() == Some("xterm-kitty") || var("KITTY_WINDOW_ID").is_some() {
+            return Some(Self::Kitty);
+        }
+        match var("TERM_PROGRAM").as_deref() {
+            Some("ghostty") => Some(Self::Kitty),
+            Some("iTerm.app") | Some("WezTerm") => Some(Self::Iterm2),
+            _ => None,
+        }
+    }
+
+    /// Whether the file at `path` is an image this protocol can show, going by
+    /// its extension. Kitty only decodes PNG itself.
+    pub fn supports(self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+            return false;
+        };
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => true,
+            "jpg" | "jpeg" | "gif" => self == Self::Iterm2,
+            _ => false,
+        }
+    }
+
+    /// Escape sequence drawing the image in `data` at the cursor, scaled to fit
+    /// `columns` by `rows` cells. The cursor is left where it was for Kitty.
+    pub fn escape_sequence(self, data: &[u8], columns: u16, rows: u16) -> String {
+        let encoded = base64(data);
+        match self {
+            Self::Kitty => {
+                // The payload goes in chunks, every one but the last with m=1
+                let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+                let mut sequence = String::new();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = u8::from(i + 1 < chunks.len());
+                    let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                    if i == 0 {
+                        sequence.push_str(&format!(
+                            "\x1b_Ga=T,f=100,q=2,C=1,c={columns},r={rows},m={more};{chunk}\x1b\\"
+                        ));
+                    } else {
+                        sequence.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+                    }
+                }
+                sequence
+            }
+            Self::Iterm2 => format!(
+                "\x1b]1337;File=inline=1;size={};width={columns};height={rows};preserveAspectRatio=1:{encoded}\x07",
+                data.len()
+            ),
+        }
+    }
+}
+
+/// Standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,18 @@
+//! Diff parsing and rendering behind the `watchhound` binary, kept free of
+//! terminal and git process handling so it can be used on its own.
+
+pub mod ansi;
+pub mod blame;
+pub mod diff;
+pub mod differ;
+pub mod fuzzy;
+pub mod highlight;
+pub mod inline_image;
+pub mod palette;
+pub mod paths;
+pub mod render;
+pub mod snapshot;
+pub mod stat;
+pub mod text;
+pub mod tree;
+pub mod whitespace;
@@ -2,28 +2,78 @@ use anyhow::Result;
 use chrono::Utc;
 use clap::Parser;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    cursor::MoveTo,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use globset::{Glob, GlobMatcher};
 use notify::{Event as NotifyEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Wrap,
+    },
     Frame, Terminal,
 };
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs, io,
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, BufRead, BufReader, Write},
     path::{Path, PathBuf},
-    process::{exit, Command},
-    sync::{Arc, Mutex},
+    process::exit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant, SystemTime},
 };
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{
+    process::Command,
+    sync::{broadcast, mpsc, Semaphore},
+    time::sleep,
+};
+
+use watchhound::{
+    ansi, blame,
+    diff::{
+        binary_change_summary, conflict_marker, context_folds, decode_diff, describe_binary_diff,
+        diff_line_numbers, diff_lines, find_first_diff_line, fold_diff, hunk, invalid_utf8_line,
+        is_binary_diff_line, parse_hunk_header, scroll_position_for_new_diff,
+        smart_scroll_position, truncate_diff, TRUNCATION_MARKER,
+    },
+    differ, fuzzy,
+    inline_image::{GraphicsProtocol, KITTY_CLEAR},
+    palette::ColorLevel,
+    paths::repo_relative_path,
+    render::{
+        format_diff_text, format_side_by_side, line_number_gutter_width, side_by_side_row_count,
+        side_by_side_rows,
+    },
+    snapshot::Snapshot,
+    stat::{binary_stat_sizes, parse_numstat, parse_stat_summary, stat_bar_spans},
+    text::{truncate_middle, wrapped_line_count, wrapped_rows},
+    tree::{self, TreeRow},
+    whitespace::WhitespaceRules,
+};
+
+mod config;
+mod dashboard;
+mod export;
+mod socket;
+mod state_file;
+
+use socket::ChangeEvent;
+use state_file::StateSnapshot;
 
 #[derive(Parser, Debug)]
 #[command(name = "watchhound")]
@@ -40,18 +90,555 @@ Features:
 - Diff history management with clear functionality
 
 Controls:
-- Left/Right: Navigate between changed files
+- Left/Right: Navigate between changed files (--wrap-navigation goes around at the ends)
+- Shift+Left/Right: Scroll the diff sideways when lines aren't wrapped
 - Up/Down: Scroll up/down through diffs (5 lines at a time)
+- Tab: Switch the scroll keys (Up/Down, PageUp/PageDown, Home/End) between the diff
+  and the status pane; the focused pane has a cyan border
 - Space: Scroll down through diffs (1 line at a time)
+- PageUp/PageDown: Scroll the diff by one screen
+- Home/End: Jump to the top/bottom of the diff
+- 't': Cycle the diff scope: working tree, staged, all changes against HEAD, or
+  everything since WatchHound started (the session), commits included
+- 'B': Reset the session scope's base to the current HEAD
+- 'a' / 'u': Stage (git add) / unstage (git reset) the current file
+- 'C': Toggle the compact layout: the diff pane only, with the previous, current and
+  next file in a line above it
+- 'T': Toggle a directory tree of the changed files in the left pane; Up/Down move
+  through it, Left/Right collapse/expand folders and Enter toggles a folder
+- 'o': Cycle the file order: git order, most recently modified first, largest change first
+- 'f': Filter the changed files by glob (e.g. *.rs, src/**); empty clears it
+- 'S': Show only files with staged changes, then only unstaged ones, then all again
+- 'g' or Ctrl+P: Jump to a changed file by typing parts of its path
+- 'E': Export the diff history to a markdown (.md) or HTML (.html) file
+- Mouse wheel: Scroll the diff; click a file in the status pane to select it
+- '<' / '>': Make the status pane narrower/wider (or drag the border between the panes)
+- '{' / '}': Jump to the previous/next hunk
+- '[' / ']': Jump to the previous/next merge conflict; conflicted files are tagged [CONFLICT]
+- Enter: Expand the highlighted fold of unchanged lines
+- 'X': Load the full diff of a file cut off at --max-diff-lines
+- 'F': Show the whole current file with its changes in place, or only the changed hunks again
+- 'b': Blame the hunk at the top of the diff pane in a popup
+- 'y' / 'Y': Copy the hunk at the top of the diff pane / the current file's path to the clipboard
 - 'r': Manual refresh
 - 'c': Clear diff history
+- 'p': Pin the view to the current file so changes elsewhere don't move it
 - 'h': Toggle history view (current file vs accumulated history)
-- 'q' or Esc: Quit"
+- 'O': List the history view newest first, or oldest first again
+- 'v': Cycle the diff pane between the current file, the history and a summary dashboard
+- 'H': Open a timeline of the diff history; '[' / ']' step through the captured diffs
+  one at a time and Esc closes it
+- 'R': Replay the diff history on the timeline; Space pauses, 'R' or Esc stops
+- 's': Toggle side-by-side diff layout (old on the left, new on the right)
+- 'n': Toggle old/new line numbers in the diff pane
+- 'l': Toggle syntax highlighting based on the file extension
+- 'd': Toggle word-level highlighting within changed lines
+- 'W': Toggle wrapping of long lines in the diff pane
+- 'e': Toggle highlighting of whitespace errors in added lines
+- '+' / '-': Show more/fewer lines of context around changes
+- 'w': Toggle ignoring whitespace-only changes
+- 'm': Toggle rename detection (renamed files shown as old → new)
+- Esc: Close the error popup (errors also close by themselves after --error-timeout)
+- 'q' or Esc: Quit
+
+Staged changes are hidden by default; pass --include-staged (or press 't') to diff
+against HEAD and tag staged files with [S] (staged only) or [S+U] (staged and unstaged).
+
+Exit status with --once: 0 if there are no changes, 1 if there are changes, 2 on
+errors, e.g. for a pre-commit hook that fails on uncommitted changes."
 )]
 struct Args {
-    /// Directory to watch (defaults to current directory). Must be a git repository.
+    /// Directories to watch (defaults to current directory). Each must be a git repository.
     #[arg(default_value = ".")]
+    directories: Vec<PathBuf>,
+
+    /// Also show staged changes (git diff --cached) alongside working-tree changes
+    #[arg(long)]
+    include_staged: bool,
+
+    /// Milliseconds to wait for a file to settle before refreshing (0 disables debouncing)
+    #[arg(long, default_value_t = 1000)]
+    debounce_ms: u64,
+
+    /// Milliseconds a file change waits before refreshing; the wait is dropped
+    /// if a newer change to the same file comes in meanwhile
+    #[arg(long, value_name = "MS", default_value_t = 200)]
+    change_delay_ms: u64,
+
+    /// Watch a directory that is not a git repository, diffing against a snapshot taken at startup
+    #[arg(long)]
+    no_git: bool,
+
+    /// Persist diff history to this JSONL file and reload it on startup
+    #[arg(long, value_name = "PATH")]
+    history_file: Option<PathBuf>,
+
+    /// Number of diff history entries to keep (0 keeps everything)
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    history_limit: usize,
+
+    /// Milliseconds each diff history entry stays up while replaying with R (at
+    /// least 50)
+    #[arg(long, value_name = "MS", default_value_t = 1500)]
+    replay_interval: u64,
+
+    /// Start the replay over from the oldest entry instead of stopping at the end
+    #[arg(long)]
+    replay_loop: bool,
+
+    /// Go from the last changed file to the first with Right, and from the first
+    /// to the last with Left, instead of stopping at the ends of the list
+    #[arg(long)]
+    wrap_navigation: bool,
+
+    /// Lines of context around each change (passes -U<n> to git diff)
+    #[arg(long, value_name = "N", default_value_t = differ::DEFAULT_CONTEXT_LINES)]
+    context: usize,
+
+    /// Ignore whitespace-only changes (passes -w to git diff)
+    #[arg(long)]
+    ignore_whitespace: bool,
+
+    /// Start with rename detection off (passes --no-renames instead of -M to git diff)
+    #[arg(long)]
+    no_renames: bool,
+
+    /// Diff against this commit or branch instead of the index (e.g. main, HEAD~3, a SHA)
+    #[arg(long, value_name = "REV")]
+    base: Option<String>,
+
+    /// Only show changed files matching this glob (e.g. '*.rs', 'src/**', '*.{ts,tsx}')
+    #[arg(long, value_name = "GLOB")]
+    filter: Option<String>,
+
+    /// Ignore watcher events for paths matching this regex, on top of .gitignore
+    /// (repeatable; matched against the path relative to the repository)
+    #[arg(long, value_name = "REGEX")]
+    ignore: Vec<String>,
+
+    /// Show a desktop notification when a watched file changes
+    #[arg(long)]
+    notify: bool,
+
+    /// Ask for confirmation before quitting with diff history that would be lost
+    #[arg(long)]
+    confirm_quit: bool,
+
+    /// Write the diff history to this file on quit (.html for HTML, otherwise markdown)
+    #[arg(long, value_name = "PATH")]
+    export_on_quit: Option<PathBuf>,
+
+    /// Watch without the interface, printing a timestamped line to stdout for every
+    /// change (runs until Ctrl+C)
+    #[arg(long)]
+    watch_only: bool,
+
+    /// Print the diff of every changed file to stdout and exit instead of watching
+    /// (colored unless NO_COLOR is set). Exits with 0 if there are no changes, 1 if
+    /// there are and 2 on errors
+    #[arg(long)]
+    once: bool,
+
+    /// Git executable to run, if `git` on the PATH isn't the right one
+    #[arg(long, value_name = "PATH", default_value = "git")]
+    git_path: PathBuf,
+
+    /// Fold runs of more than this many unchanged context lines (0 disables folding)
+    #[arg(long, value_name = "LINES", default_value_t = 6)]
+    fold_context: usize,
+
+    /// Cut file diffs off after this many lines, to keep huge diffs responsive
+    /// (0 disables the limit; X loads the rest)
+    #[arg(long, value_name = "LINES", default_value_t = 5000)]
+    max_diff_lines: usize,
+
+    /// Whitespace errors highlighted in added lines: a comma separated list of
+    /// trailing-space, space-before-tab and tab-indent, or none
+    #[arg(
+        long,
+        value_name = "RULES",
+        default_value = "trailing-space,space-before-tab"
+    )]
+    whitespace_rules: String,
+
+    /// Colors the terminal can show (auto goes by COLORTERM, TERM and NO_COLOR)
+    #[arg(long, value_enum, value_name = "LEVEL", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Show a log of every git command run, with its exit status and stderr, in a
+    /// pane at the bottom
+    #[arg(long)]
+    debug: bool,
+
+    /// After every change, stage everything and commit it as "wip: <timestamp>"
+    /// (asks for confirmation on startup)
+    #[arg(long)]
+    autocommit: bool,
+
+    /// When started inside a subdirectory of a repository, only show changes
+    /// under that subdirectory (the whole repository is shown otherwise)
+    #[arg(long)]
+    scope_to_cwd: bool,
+
+    /// How file paths are shown in the status pane and the diff pane's title:
+    /// relative to the repository, to the current directory, or absolute
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = PathStyle::Repo)]
+    path_style: PathStyle,
+
+    /// Stream change events as JSON lines to clients of a Unix domain socket at this path
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Watch directories only this many levels below each repository (or listed
+    /// directory), 0 for just the top level, instead of the whole tree
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Watch only the files and directories listed in this file, one per line,
+    /// instead of whole repositories ('-' reads the list from stdin)
+    #[arg(long, value_name = "FILE")]
+    paths_from: Option<PathBuf>,
+
+    /// Follow only this file: nothing else is watched or listed, and the diff
+    /// takes the whole width
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["paths_from", "scope_to_cwd", "no_git"]
+    )]
+    file: Option<PathBuf>,
+
+    /// Keep a JSON description of the changed files and the selection in this file,
+    /// rewritten whenever it changes (for editor integrations)
+    #[arg(long, value_name = "PATH")]
+    json_state: Option<PathBuf>,
+
+    /// Pipe each file's diff through this shell command (e.g. delta or
+    /// diff-so-fancy) and show its colored output instead of the built-in coloring
+    #[arg(long, value_name = "CMD")]
+    diff_command: Option<String>,
+
+    /// Show changed images as text even in terminals that can draw them inline
+    #[arg(long)]
+    no_images: bool,
+
+    /// Seconds error popups stay up before closing by themselves (0 keeps them
+    /// until Esc or the next successful refresh)
+    #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+    error_timeout: u64,
+
+    /// Width of the status pane in percent of the terminal (20-80; < and > change
+    /// it) [default: 40, or the width last set in the interface]
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u16)
+            .range(SPLIT_PERCENT_RANGE.0 as i64..=SPLIT_PERCENT_RANGE.1 as i64)
+    )]
+    split: Option<u16>,
+
+    /// Start with the diff pane taking the whole width and the changed files
+    /// reduced to one line above it, for small terminals (C toggles it)
+    #[arg(long)]
+    compact: bool,
+
+    /// Name shown in the header bar in place of the repository's directory name
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+}
+
+/// How file paths are shown. Git commands and the per-file maps always use the
+/// repo-relative path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum PathStyle {
+    #[default]
+    Repo,
+    Cwd,
+    Absolute,
+}
+
+/// How many colors to draw with, or `auto` to go by the environment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+enum ColorChoice {
+    #[default]
+    Auto,
+    #[value(name = "16")]
+    Basic,
+    #[value(name = "256")]
+    Indexed,
+    Truecolor,
+    None,
+}
+
+/// Where diffs come from.
+#[derive(Debug, Clone, Default)]
+enum DiffMode {
+    #[default]
+    Git,
+    /// Compare against file contents captured at startup instead of shelling out to git
+    Snapshot(Arc<Snapshot>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Options {
+    include_staged: bool,
+    debounce: Duration,
+    /// How long a file change waits for further changes before refreshing
+    change_delay: Duration,
+    mode: DiffMode,
+    history_file: Option<PathBuf>,
+    /// Diff history entries kept, 0 for no limit
+    history_limit: usize,
+    /// How long each history entry is shown while replaying
+    replay_interval: Duration,
+    replay_loop: bool,
+    /// Left and Right go around from one end of the file list to the other
+    wrap_navigation: bool,
+    ignore_whitespace: bool,
+    detect_renames: bool,
+    context_lines: usize,
+    base: Option<String>,
+    filter: Option<GlobMatcher>,
+    /// Watcher events for paths matching any of these are dropped
+    ignore: Vec<Regex>,
+    notify: bool,
+    confirm_quit: bool,
+    export_on_quit: Option<PathBuf>,
+    fold_context: usize,
+    git_path: PathBuf,
+    whitespace_rules: WhitespaceRules,
+    /// Colors of the interface are brought down to this level when drawn
+    color_level: ColorLevel,
+    /// File diffs are truncated after this many lines, 0 for no limit
+    max_diff_lines: usize,
+    /// Commit every change as it's seen
+    autocommit: bool,
+    /// Record git commands for the debug log pane
+    debug: bool,
+    /// Change events for --socket clients
+    events: Option<broadcast::Sender<ChangeEvent>>,
+    /// Levels of subdirectories watched, all of them when `None`
+    max_depth: Option<usize>,
+    /// Absolute paths from --paths-from, watched in place of whole repositories
+    watch_paths: Option<Vec<PathBuf>>,
+    /// Absolute path of the --paths-from file, re-read when it changes; `None`
+    /// when the list came from stdin
+    paths_file: Option<PathBuf>,
+    /// Absolute path of the --json-state file
+    json_state: Option<PathBuf>,
+    /// Shell command that renders diffs in place of the built-in coloring
+    diff_command: Option<String>,
+    path_style: PathStyle,
+    /// Directory WatchHound was started in, for --path-style cwd
+    cwd: PathBuf,
+    /// How the terminal draws inline images, `None` to describe them as text
+    graphics: Option<GraphicsProtocol>,
+    /// How long error popups stay up, zero until dismissed
+    error_timeout: Duration,
+    /// Initial width of the status pane in percent
+    split_percent: u16,
+    /// Where pane widths set in the interface are remembered, `None` to forget them
+    config_file: Option<PathBuf>,
+    compact: bool,
+    /// Log changes to stdout instead of drawing the interface
+    watch_only: bool,
+    /// Print the diff once and exit, so there's nothing to show while loading
+    once: bool,
+}
+
+/// What a text prompt's input is used for once submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    Filter,
+    Export,
+    /// Fuzzy-find a changed file and select it
+    Jump,
+}
+
+#[derive(Debug, Clone)]
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+    /// Highlighted entry of the jump prompt's list of matches
+    selected: usize,
+}
+
+impl Prompt {
+    /// What was entered, without the spaces around it.
+    fn query(&self) -> &str {
+        self.input.trim()
+    }
+
+    /// Indices of the `files` the jump prompt lists, best match first. The list
+    /// drawn, Up/Down and Enter all go by this one ranking.
+    fn jump_matches(&self, files: &[String]) -> Vec<usize> {
+        fuzzy::rank(self.query(), files)
+    }
+}
+
+/// Which changes `git diff` shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DiffScope {
+    /// Working tree against the index (`git diff`)
+    #[default]
+    WorkingTree,
+    /// Index against HEAD (`git diff --cached`)
+    Staged,
+    /// Working tree against HEAD, staged and unstaged together (`git diff HEAD`)
+    All,
+    /// Working tree against HEAD as it was when WatchHound started, commits made
+    /// since included (`git diff <sha>`)
+    Session,
+}
+
+impl DiffScope {
+    fn next(self) -> Self {
+        match self {
+            DiffScope::WorkingTree => DiffScope::Staged,
+            DiffScope::Staged => DiffScope::All,
+            DiffScope::All => DiffScope::Session,
+            DiffScope::Session => DiffScope::WorkingTree,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            DiffScope::WorkingTree => "working tree",
+            DiffScope::Staged => "staged",
+            DiffScope::All => "all",
+            DiffScope::Session => "session",
+        }
+    }
+}
+
+/// Pane the scroll keys apply to, switched with Tab.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Pane {
+    Status,
+    #[default]
+    Diff,
+}
+
+/// Order in which changed files are navigated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SortMode {
+    /// As git lists them, alphabetically with untracked files last
+    #[default]
+    Git,
+    /// Most recently modified first
+    Recent,
+    /// Most inserted and deleted lines first
+    Size,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Git => SortMode::Recent,
+            SortMode::Recent => SortMode::Size,
+            SortMode::Size => SortMode::Git,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Git => "git order",
+            SortMode::Recent => "recent",
+            SortMode::Size => "size",
+        }
+    }
+}
+
+/// Which files the status pane lists by whether their changes are staged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum StatusFilter {
+    #[default]
+    All,
+    /// Files with staged changes
+    Staged,
+    /// Files with changes that aren't staged, untracked ones included
+    Unstaged,
+}
+
+impl StatusFilter {
+    fn next(self) -> Self {
+        match self {
+            StatusFilter::All => StatusFilter::Staged,
+            StatusFilter::Staged => StatusFilter::Unstaged,
+            StatusFilter::Unstaged => StatusFilter::All,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            StatusFilter::All => "all",
+            StatusFilter::Staged => "staged",
+            StatusFilter::Unstaged => "unstaged",
+        }
+    }
+
+    fn matches(self, status: FileStatus) -> bool {
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Staged => matches!(status, FileStatus::Staged | FileStatus::Both),
+            StatusFilter::Unstaged => status != FileStatus::Staged,
+        }
+    }
+}
+
+/// A git command run by WatchHound, for the --debug log pane.
+#[derive(Debug, Clone)]
+struct GitLogEntry {
+    time: chrono::DateTime<Utc>,
     directory: PathBuf,
+    args: String,
+    /// "exit 0", "killed" or why the command couldn't be started
+    status: String,
+    success: bool,
+    elapsed: Duration,
+    stderr: String,
+}
+
+/// `git blame` of a hunk, shown in a scrollable popup.
+#[derive(Debug, Clone)]
+struct BlamePopup {
+    title: String,
+    lines: Vec<String>,
+    scroll: u16,
+}
+
+/// A replay of the diff history: the timeline cursor advances by itself, one
+/// entry per `--replay-interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Replay {
+    /// Tells the timer task of an earlier replay that it's been replaced
+    started: Instant,
+    paused: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileStatus {
+    Unstaged,
+    Staged,
+    Both,
+    Untracked,
+    /// Unmerged during a merge or rebase
+    Conflicted,
+}
+
+/// Marks files deleted from the working tree in the file list and diff title.
+const DELETED_TAG: &str = "[DEL] ";
+
+impl FileStatus {
+    fn tag(&self) -> &'static str {
+        match self {
+            FileStatus::Unstaged => "",
+            FileStatus::Staged => "[S] ",
+            FileStatus::Both => "[S+U] ",
+            FileStatus::Untracked => "[?] ",
+            FileStatus::Conflicted => "[CONFLICT] ",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,7 +646,7 @@ struct FileInfo {
     last_modified: SystemTime,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct DiffEntry {
     timestamp: chrono::DateTime<chrono::Utc>,
     diff_content: String,
@@ -72,12 +659,131 @@ struct AppState {
     git_diff: String,
     changed_files: Vec<String>,
     file_info: HashMap<String, FileInfo>,
+    file_status: HashMap<String, FileStatus>,
     current_file_index: usize,
     scroll_position: u16,
     last_update: Option<chrono::DateTime<Utc>>,
-    error_message: Option<String>,
-    diff_history: Vec<DiffEntry>,
+    /// Error shown in a popup until it's dismissed with Esc, the next successful
+    /// refresh or --error-timeout, with when it was raised
+    error_message: Option<(String, Instant)>,
+    /// Error that stays up until the condition is gone, like a deleted repository
+    persistent_error: Option<String>,
+    diff_history: VecDeque<DiffEntry>,
     show_history: bool,
+    /// List the history view's entries newest first, so new changes are on top
+    history_newest_first: bool,
+    /// Show the summary dashboard in place of the diff
+    show_dashboard: bool,
+    /// History entry picked on the timeline, shown in place of the diff while
+    /// the timeline is open
+    history_cursor: Option<usize>,
+    /// Replay stepping the timeline through the history on a timer
+    replay: Option<Replay>,
+    /// Generation of the newest change to each path still waiting out
+    /// --change-delay-ms; waits for older changes to the same path are dropped
+    pending_changes: HashMap<PathBuf, u64>,
+    /// Generation given to the last change seen
+    change_generation: u64,
+    side_by_side: bool,
+    show_line_numbers: bool,
+    syntax_highlight: bool,
+    word_diff: bool,
+    /// Highlight whitespace errors in added lines
+    show_whitespace: bool,
+    /// Pass -w to git diff; starts from --ignore-whitespace and can be toggled at runtime
+    ignore_whitespace: bool,
+    /// Lines of context in file diffs (-U<n>); starts from --context, changed with +/-
+    context_lines: usize,
+    /// Pass -M to git diff so renames show as one file; toggled at runtime
+    detect_renames: bool,
+    /// Original repo-relative path of each renamed file, keyed by its new file key
+    renames: HashMap<String, String>,
+    /// Tracked files that no longer exist, shown as deletions
+    deleted_files: HashSet<String>,
+    /// Per-file diff output keyed by path, valid while the file's mtime matches
+    diff_cache: HashMap<String, (SystemTime, String)>,
+    /// Bumped whenever cached diffs are dropped, so a prefetch started before
+    /// can tell its diffs are stale
+    diff_cache_generation: u64,
+    /// Diff history entries per file, kept in step with `diff_history`
+    change_counts: HashMap<String, usize>,
+    /// For a current file with both staged and unstaged changes shown against HEAD,
+    /// whether each changed line of `git_diff` is staged, keyed by line index
+    split_markers: HashMap<usize, bool>,
+    /// --diff-command output for the current file: the diff it was made from and
+    /// the command's ANSI colored output
+    external_diff: Option<(String, String)>,
+    /// Repositories without any commits yet, by directory
+    unborn_repos: HashSet<PathBuf>,
+    /// HEAD of each repository when the session started, or since 'B' reset it;
+    /// what the session scope diffs against
+    session_bases: HashMap<PathBuf, String>,
+    /// Files whose full diff was asked for despite --max-diff-lines
+    full_diffs: HashSet<String>,
+    /// Files shown whole, with every unchanged line as context
+    whole_files: HashSet<String>,
+    /// Inner width and height of the diff pane from the last frame, (0, 0) until first render
+    diff_view_size: (u16, u16),
+    /// Status and diff pane areas from the last frame
+    pane_areas: (Rect, Rect),
+    /// Width of the status pane in percent; starts from --split, changed with < and >
+    split_percent: u16,
+    /// Whether the border between the panes is being dragged with the mouse
+    dragging_split: bool,
+    focused_pane: Pane,
+    /// Rows of the status pane scrolled past when the stat doesn't fit
+    stat_scroll: u16,
+    /// Current branch, short HEAD SHA and commit subject, `None` without git
+    head_info: Option<HeadInfo>,
+    /// Active file filter; `changed_files` only holds paths it matches
+    file_filter: Option<GlobMatcher>,
+    /// Every changed file, so the filter can change without re-running git
+    all_changed_files: Vec<String>,
+    /// Text prompt shown over the panes while it is open
+    prompt: Option<Prompt>,
+    /// Short-lived confirmation shown at the start of the status bar
+    status_message: Option<(String, Instant)>,
+    /// Most recent git commands, oldest first (--debug)
+    git_log: VecDeque<GitLogEntry>,
+    /// When each file last triggered a desktop notification
+    last_notified: HashMap<String, Instant>,
+    /// Whether the quit confirmation popup is open
+    confirming_quit: bool,
+    /// When the diff being computed was asked for; the diff pane title shows a
+    /// spinner once that takes longer than `LOADING_INDICATOR_DELAY`
+    loading_since: Option<Instant>,
+    /// Spinner animation frame, advanced on every render while loading
+    spinner_frame: usize,
+    /// Insertions and deletions per file key, parsed from `git_stat`
+    file_stats: HashMap<String, (usize, usize)>,
+    /// Order of `changed_files`
+    sort_mode: SortMode,
+    /// Staged or unstaged files only, applied along with `file_filter`
+    status_filter: StatusFilter,
+    /// Context runs longer than this are folded, 0 to never fold
+    fold_context: usize,
+    /// Folds the user expanded, by the diff line index where they start
+    expanded_folds: HashSet<usize>,
+    /// Changes shown; starts from --include-staged and cycles with 't'
+    diff_scope: DiffScope,
+    /// Wrap long diff lines; when off the diff pane scrolls sideways instead
+    wrap: bool,
+    /// Columns the diff pane is scrolled to the right while lines aren't wrapped
+    h_scroll: u16,
+    /// Files changed, insertions and deletions summed over the stat's summary lines
+    stat_totals: Option<(usize, usize, usize)>,
+    /// Blame popup shown over the panes while it is open
+    blame: Option<BlamePopup>,
+    /// File the view is pinned to; file changes elsewhere don't move the view
+    pinned_file: Option<String>,
+    /// Show the changed files as a directory tree instead of the stat
+    tree_view: bool,
+    /// Diff pane only, with the changed files in one line above it
+    compact: bool,
+    /// Tree directories whose contents are hidden
+    collapsed_dirs: HashSet<String>,
+    /// Directory under the tree cursor; `None` when the cursor is on the current file
+    tree_selected_dir: Option<String>,
 }
 
 impl Default for AppState {
@@ -87,772 +793,4873 @@ impl Default for AppState {
             git_diff: "Welcome to WatchHound!\n\nInitializing git repository monitoring...\n\nThis will show colorized git diffs in real-time.\nDiff history is automatically stored and scrolls to new changes.\n\nPress 'h' to toggle history view, 'c' to clear history, 'r' to refresh, 'q' to quit.".to_string(),
             changed_files: Vec::new(),
             file_info: HashMap::new(),
+            file_status: HashMap::new(),
             current_file_index: 0,
             scroll_position: 0,
             last_update: None,
             error_message: None,
-            diff_history: Vec::new(),
+            persistent_error: None,
+            diff_history: VecDeque::new(),
             show_history: false,
+            history_newest_first: false,
+            show_dashboard: false,
+            history_cursor: None,
+            replay: None,
+            pending_changes: HashMap::new(),
+            change_generation: 0,
+            side_by_side: false,
+            show_line_numbers: false,
+            syntax_highlight: false,
+            word_diff: false,
+            show_whitespace: true,
+            ignore_whitespace: false,
+            context_lines: differ::DEFAULT_CONTEXT_LINES,
+            detect_renames: true,
+            renames: HashMap::new(),
+            deleted_files: HashSet::new(),
+            diff_cache: HashMap::new(),
+            diff_cache_generation: 0,
+            change_counts: HashMap::new(),
+            split_markers: HashMap::new(),
+            external_diff: None,
+            unborn_repos: HashSet::new(),
+            session_bases: HashMap::new(),
+            full_diffs: HashSet::new(),
+            whole_files: HashSet::new(),
+            diff_view_size: (0, 0),
+            pane_areas: (Rect::default(), Rect::default()),
+            split_percent: DEFAULT_SPLIT_PERCENT,
+            dragging_split: false,
+            focused_pane: Pane::default(),
+            stat_scroll: 0,
+            head_info: None,
+            file_filter: None,
+            all_changed_files: Vec::new(),
+            prompt: None,
+            status_message: None,
+            git_log: VecDeque::new(),
+            last_notified: HashMap::new(),
+            confirming_quit: false,
+            loading_since: None,
+            spinner_frame: 0,
+            file_stats: HashMap::new(),
+            sort_mode: SortMode::default(),
+            status_filter: StatusFilter::default(),
+            fold_context: 0,
+            expanded_folds: HashSet::new(),
+            diff_scope: DiffScope::default(),
+            wrap: true,
+            h_scroll: 0,
+            stat_totals: None,
+            blame: None,
+            pinned_file: None,
+            tree_view: false,
+            compact: false,
+            collapsed_dirs: HashSet::new(),
+            tree_selected_dir: None,
         }
     }
 }
 
-struct App {
-    state: Arc<Mutex<AppState>>,
-    should_quit: bool,
-    directory: PathBuf,
-}
+impl AppState {
+    /// Largest scroll offset that still keeps the last diff line visible.
+    fn max_scroll(&self) -> u16 {
+        self.max_scroll_for(self.total_rows())
+    }
 
-impl App {
-    fn new(directory: PathBuf) -> Self {
-        Self {
-            state: Arc::new(Mutex::new(AppState::default())),
-            should_quit: false,
-            directory,
+    /// `max_scroll` for a diff `total_rows` rows long, for when the row count is
+    /// already at hand.
+    fn max_scroll_for(&self, total_rows: usize) -> u16 {
+        let (width, height) = self.diff_view_size;
+        if width == 0 || height == 0 {
+            return u16::MAX;
         }
+        total_rows
+            .saturating_sub(height as usize)
+            .min(u16::MAX as usize) as u16
     }
 
-    fn parse_diff_line(line: &str) -> Line<'static> {
-        let spans = if line.starts_with("@@") {
-            // Context header (cyan)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::Cyan),
-            )]
-        } else if line.starts_with("+++") || line.starts_with("---") {
-            // File headers (white/gray)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::Gray),
-            )]
-        } else if line.starts_with('+') {
-            // Added lines (green)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::Green),
-            )]
-        } else if line.starts_with('-') {
-            // Removed lines (red)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::Red),
-            )]
-        } else if line.starts_with("index ") || line.starts_with("diff --git") {
-            // Git metadata (gray)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::Gray),
-            )]
+    /// Rows the current diff takes up in the diff pane once wrapped.
+    fn total_rows(&self) -> usize {
+        let (width, _) = self.diff_view_size;
+        if let Some(colored) = self.external_diff() {
+            let text = ansi::strip(colored);
+            return if self.wrap {
+                wrapped_line_count(&text, width)
+            } else {
+                text.lines().count()
+            };
+        }
+        let diff = self.display_diff();
+        // Side-by-side columns don't wrap, so count paired rows instead
+        if self.side_by_side {
+            side_by_side_row_count(&side_by_side_rows(&diff))
+        } else if !self.wrap {
+            diff.lines().count()
+        } else if self.show_line_numbers {
+            let numbers = diff_line_numbers(&diff);
+            let gutter = line_number_gutter_width(&numbers) as u16;
+            wrapped_line_count(&diff, width.saturating_sub(gutter))
         } else {
-            // Context lines (white)
-            vec![Span::styled(
-                line.to_string(),
-                Style::default().fg(Color::White),
-            )]
-        };
-
-        Line::from(spans)
+            wrapped_line_count(&diff, width)
+        }
     }
 
-    fn format_diff_text(diff_text: &str) -> Text<'static> {
-        let lines: Vec<Line> = diff_text.lines().map(Self::parse_diff_line).collect();
+    /// Folds of the current diff that haven't been expanded, as (first diff line,
+    /// number of lines).
+    fn visible_folds(&self) -> Vec<(usize, usize)> {
+        let whole_file = self
+            .changed_files
+            .get(self.current_file_index)
+            .is_some_and(|file| self.whole_files.contains(file));
+        if self.history_cursor.is_some() || whole_file {
+            return Vec::new();
+        }
+        context_folds(&self.git_diff, self.fold_context)
+            .into_iter()
+            .filter(|(start, _)| !self.expanded_folds.contains(start))
+            .collect()
+    }
 
-        Text::from(lines)
+    /// --diff-command output to show in place of the current file's diff, if it
+    /// was made from the diff that's current.
+    fn external_diff(&self) -> Option<&str> {
+        match &self.external_diff {
+            Some((diff, colored))
+                if !self.show_history
+                    && self.history_cursor.is_none()
+                    && *diff == self.git_diff =>
+            {
+                Some(colored)
+            }
+            _ => None,
+        }
     }
 
-    fn format_git_stat_with_status(
-        git_stat: &str,
-        file_mod_status: &HashMap<String, bool>,
-    ) -> Text<'static> {
-        let lines: Vec<Line> = git_stat
-            .lines()
-            .map(|line| {
-                if line.contains("|") {
-                    // File change lines with stats
-                    let parts: Vec<&str> = line.split('|').collect();
-                    if parts.len() >= 2 {
-                        let file_part = parts[0].trim().to_string();
-                        let stats_part = parts[1].trim().to_string();
+    /// The diff as shown in the diff pane, with folded context replaced by markers.
+    /// In the history view each update's header also says how long ago it was.
+    fn display_diff(&self) -> Cow<'_, str> {
+        if let Some((_, entry)) = self.timeline_entry() {
+            return Cow::Borrowed(&entry.diff_content);
+        }
+        let folds = self.visible_folds();
+        let diff = if folds.is_empty() {
+            Cow::Borrowed(self.git_diff.as_str())
+        } else {
+            Cow::Owned(fold_diff(&self.git_diff, &folds))
+        };
+        if self.show_history {
+            Cow::Owned(with_update_ages(&diff, &self.diff_history, Utc::now()))
+        } else {
+            diff
+        }
+    }
 
-                        // Check if file was recently modified (within 1 minute)
-                        let is_recent = file_mod_status.get(&file_part).unwrap_or(&false);
-                        let file_color = if *is_recent {
-                            Color::Yellow
-                        } else {
-                            Color::White
-                        };
+    /// The history entry picked on the timeline and its index, if it's open.
+    fn timeline_entry(&self) -> Option<(usize, &DiffEntry)> {
+        let index = self.history_cursor?;
+        self.diff_history.get(index).map(|entry| (index, entry))
+    }
 
-                        let mut spans = vec![
-                            Span::styled(file_part, Style::default().fg(file_color)),
-                            Span::styled(" | ".to_string(), Style::default().fg(Color::Gray)),
-                        ];
-
-                        // Color the stats part
-                        if stats_part.contains('+') && stats_part.contains('-') {
-                            spans
-                                .push(Span::styled(stats_part, Style::default().fg(Color::Yellow)));
-                        } else if stats_part.contains('+') {
-                            spans.push(Span::styled(stats_part, Style::default().fg(Color::Green)));
-                        } else if stats_part.contains('-') {
-                            spans.push(Span::styled(stats_part, Style::default().fg(Color::Red)));
-                        } else {
-                            spans.push(Span::styled(stats_part, Style::default().fg(Color::White)));
-                        }
+    /// Line of the displayed diff that shows line `line` of the raw diff.
+    fn display_line(&self, line: usize) -> usize {
+        let hidden: usize = self
+            .visible_folds()
+            .iter()
+            .take_while(|(start, _)| *start < line)
+            .map(|(start, len)| (line - start).min(len - 1))
+            .sum();
+        line - hidden
+    }
 
-                        Line::from(spans)
-                    } else {
-                        Line::from(vec![Span::styled(
-                            line.to_string(),
-                            Style::default().fg(Color::White),
-                        )])
-                    }
-                } else if line.contains("changed")
-                    || line.contains("insertion")
-                    || line.contains("deletion")
-                {
-                    // Summary line
-                    Line::from(vec![Span::styled(
-                        line.to_string(),
-                        Style::default().fg(Color::Cyan),
-                    )])
-                } else {
-                    Line::from(vec![Span::styled(
-                        line.to_string(),
-                        Style::default().fg(Color::White),
-                    )])
-                }
+    /// The fold Enter expands: the first fold marker within the visible rows, as
+    /// (displayed line, raw diff line it starts at).
+    fn focused_fold(&self) -> Option<(usize, usize)> {
+        let folds = self.visible_folds();
+        if folds.is_empty() {
+            return None;
+        }
+        let (top, height) = (self.scroll_position, self.diff_view_size.1);
+        let bottom = top.saturating_add(height.max(1));
+        let line_rows = self.line_start_rows();
+        folds
+            .iter()
+            .map(|(start, _)| (self.display_line(*start), *start))
+            .find(|(line, _)| {
+                line_rows
+                    .get(*line)
+                    .is_some_and(|row| *row >= top && *row < bottom)
             })
-            .collect();
-
-        Text::from(lines)
     }
 
-    fn render(&mut self, f: &mut Frame) {
-        let chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
-            .split(f.size());
-
-        let state = self.state.lock().unwrap();
-
-        // Pre-compute file modification status to avoid deadlock
-        let file_mod_status: HashMap<String, bool> = state
-            .file_info
+    /// The subset of `files` shown under the active filters.
+    fn filtered_files(&self, files: &[String]) -> Vec<String> {
+        files
             .iter()
-            .map(|(path, info)| {
-                let is_recent = if let Ok(elapsed) = info.last_modified.elapsed() {
-                    elapsed < Duration::from_secs(60)
-                } else {
-                    false
-                };
-                (path.clone(), is_recent)
+            .filter(|file| match &self.file_filter {
+                Some(filter) => filter.is_match(file),
+                None => true,
             })
-            .collect();
-
-        // Left pane - git stat
-        let left_block = Block::default()
-            .title("Git Status")
-            .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White));
-
-        let git_stat_text = if state.git_stat.is_empty() {
-            Text::from("No changes detected")
-        } else {
-            Self::format_git_stat_with_status(&state.git_stat, &file_mod_status)
-        };
-
-        let git_stat_paragraph = Paragraph::new(git_stat_text)
-            .block(left_block)
-            .wrap(Wrap { trim: true });
-
-        f.render_widget(git_stat_paragraph, chunks[0]);
+            .filter(|file| {
+                let status = self
+                    .file_status
+                    .get(*file)
+                    .copied()
+                    .unwrap_or(FileStatus::Unstaged);
+                self.status_filter.matches(status)
+            })
+            .cloned()
+            .collect()
+    }
 
-        // Right pane - git diff
-        let right_title = if !state.changed_files.is_empty() {
-            let current_file = &state.changed_files[state.current_file_index];
-            let is_recent = file_mod_status.get(current_file).unwrap_or(&false);
-            let indicator = if *is_recent { " [RECENT]" } else { "" };
-            format!(
-                "Git Diff - {}{} ({}/{})",
-                current_file,
-                indicator,
-                state.current_file_index + 1,
-                state.changed_files.len()
-            )
-        } else {
-            "Git Diff".to_string()
-        };
+    /// Re-applies the filters to the last git results, staying on the same file
+    /// when it's still shown.
+    fn refilter_changed_files(&mut self) {
+        let current_file = self.changed_files.get(self.current_file_index).cloned();
+        let all_changed_files = std::mem::take(&mut self.all_changed_files);
+        self.changed_files = self.filtered_files(&all_changed_files);
+        self.all_changed_files = all_changed_files;
+        self.sort_changed_files();
+        self.current_file_index = current_file
+            .and_then(|file| self.changed_files.iter().position(|f| *f == file))
+            .unwrap_or(0);
+        self.scroll_position = 0;
+    }
 
-        let right_block = Block::default()
-            .title(right_title)
-            .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White));
+    /// The changed file listed on a line of the stat output, if any.
+    fn stat_line_file(&self, line: &str) -> Option<&String> {
+        let listed = line.split('|').next().unwrap_or_default().trim();
+        if listed.is_empty() || (!line.contains('|') && !self.file_status.contains_key(listed)) {
+            return None;
+        }
+        // Renames are listed as "old => new"; the file is known by its new path
+        let renamed = stat_rename_target(listed);
+        let listed = renamed.as_deref().unwrap_or(listed);
+        // git --stat abbreviates long paths as ".../tail" (after any repository
+        // label), so match on the ends too
+        self.all_changed_files.iter().find(|file| {
+            *file == listed
+                || listed
+                    .split_once("...")
+                    .is_some_and(|(head, tail)| file.starts_with(head) && file.ends_with(tail))
+        })
+    }
 
-        let git_diff_text = if state.git_diff.is_empty() {
-            Text::from("No changes to show")
-        } else {
-            Self::format_diff_text(&state.git_diff)
-        };
+    /// The stat output with lines for filtered-out files removed.
+    fn visible_git_stat(&self) -> String {
+        if self.file_filter.is_none() && self.status_filter == StatusFilter::All {
+            return self.git_stat.clone();
+        }
+        self.git_stat
+            .lines()
+            .filter(|line| match self.stat_line_file(line) {
+                Some(file) => self.changed_files.contains(file),
+                None => true,
+            })
+            .map(|line| format!("{line}\n"))
+            .collect()
+    }
 
-        let git_diff_paragraph = Paragraph::new(git_diff_text)
-            .block(right_block)
-            .wrap(Wrap { trim: true })
-            .scroll((state.scroll_position, 0));
+    /// Drops every cached diff, e.g. after a setting they were taken with changed.
+    fn clear_diff_cache(&mut self) {
+        self.diff_cache.clear();
+        self.diff_cache_generation += 1;
+    }
 
-        f.render_widget(git_diff_paragraph, chunks[1]);
+    /// Drops the cached diff of `file`.
+    fn forget_cached_diff(&mut self, file: &str) {
+        self.diff_cache.remove(file);
+        self.diff_cache_generation += 1;
+    }
 
-        // Show error message if any
-        if let Some(error) = &state.error_message {
-            let error_area = centered_rect(60, 20, f.size());
-            f.render_widget(Clear, error_area);
-            let error_block = Block::default()
-                .title("Error")
-                .borders(Borders::ALL)
-                .style(Style::default().fg(Color::Red));
+    /// Counts the diff history entries of each file again, after the history
+    /// changed.
+    fn recount_changes(&mut self) {
+        let mut counts = HashMap::new();
+        for entry in &self.diff_history {
+            *counts.entry(entry.file_name.clone()).or_default() += 1;
+        }
+        self.change_counts = counts;
+    }
 
-            let error_paragraph = Paragraph::new(error.clone())
-                .block(error_block)
-                .wrap(Wrap { trim: true });
+    /// Replaces the stat output and the per-file counts, and re-parses the totals
+    /// from the stat.
+    fn set_git_stat(&mut self, git_stat: String, file_stats: HashMap<String, (usize, usize)>) {
+        // Several repositories give one summary line each
+        self.stat_totals = git_stat.lines().filter_map(parse_stat_summary).reduce(
+            |(files, insertions, deletions), (f, i, d)| (files + f, insertions + i, deletions + d),
+        );
+        self.git_stat = git_stat;
+        self.file_stats = file_stats;
+    }
 
-            f.render_widget(error_paragraph, error_area);
+    /// Shows the changed file at `index` from the top. A pinned view stays pinned,
+    /// now on that file.
+    fn select_file(&mut self, index: usize) {
+        self.current_file_index = index;
+        self.scroll_position = 0;
+        self.expanded_folds.clear();
+        self.h_scroll = 0;
+        if self.pinned_file.is_some() {
+            self.pinned_file = self.changed_files.get(index).cloned();
         }
+    }
 
-        // Show controls and last update time
-        let controls = "Controls: Left/Right: Navigate files | Space: Scroll down | q: Quit | r: Refresh | [RECENT] = Recently changed";
-        let status_line = if let Some(last_update) = &state.last_update {
-            format!(
-                "{} | Last updated: {}",
-                controls,
-                last_update.format("%H:%M:%S")
-            )
-        } else {
-            controls.to_string()
-        };
-
-        let status_area = Rect {
-            x: 0,
-            y: f.size().height - 1,
-            width: f.size().width,
-            height: 1,
-        };
+    fn tree_rows(&self) -> Vec<TreeRow> {
+        tree::tree_rows(&self.changed_files, &self.file_stats, &self.collapsed_dirs)
+    }
 
-        let status_paragraph = Paragraph::new(status_line).style(Style::default().fg(Color::Gray));
+    /// Tree row under the cursor: the selected directory, or else the current file.
+    fn tree_cursor(&self, rows: &[TreeRow]) -> Option<usize> {
+        let current_file = self.changed_files.get(self.current_file_index);
+        rows.iter().position(|row| match &self.tree_selected_dir {
+            Some(dir) => row.is_dir && row.path == *dir,
+            None => !row.is_dir && Some(&row.path) == current_file,
+        })
+    }
 
-        f.render_widget(status_paragraph, status_area);
+    /// First tree row shown, keeping the cursor on screen.
+    fn tree_scroll(&self, rows: &[TreeRow]) -> usize {
+        let height = self.pane_areas.0.height.saturating_sub(2).max(1) as usize;
+        (self.tree_cursor(rows).unwrap_or(0) + 1).saturating_sub(height)
     }
 
-    fn navigate_to_previous_file(&self) {
-        let mut state = self.state.lock().unwrap();
-        if !state.changed_files.is_empty() && state.current_file_index > 0 {
-            state.current_file_index -= 1;
-            state.scroll_position = 0; // Reset scroll when changing files
+    /// Puts the tree cursor on `row`. Returns true if that selected another file.
+    fn select_tree_row(&mut self, row: &TreeRow) -> bool {
+        if row.is_dir {
+            self.tree_selected_dir = Some(row.path.clone());
+            return false;
+        }
+        self.tree_selected_dir = None;
+        match self.changed_files.iter().position(|file| *file == row.path) {
+            Some(index) if index != self.current_file_index => {
+                self.select_file(index);
+                true
+            }
+            _ => false,
         }
     }
 
-    fn navigate_to_next_file(&self) {
-        let mut state = self.state.lock().unwrap();
-        if !state.changed_files.is_empty()
-            && state.current_file_index < state.changed_files.len() - 1
+    /// Reorders `changed_files` by the sort mode, keeping `current_file_index` on
+    /// the same file.
+    fn sort_changed_files(&mut self) {
+        let current_file = self.changed_files.get(self.current_file_index).cloned();
+        match self.sort_mode {
+            SortMode::Git => {
+                let shown = std::mem::take(&mut self.changed_files);
+                self.changed_files = self
+                    .all_changed_files
+                    .iter()
+                    .filter(|file| shown.contains(file))
+                    .cloned()
+                    .collect();
+            }
+            SortMode::Recent => {
+                let file_info = &self.file_info;
+                // Deleted files have no modification time and sort last
+                self.changed_files.sort_by_key(|file| {
+                    std::cmp::Reverse(file_info.get(file).map(|info| info.last_modified))
+                });
+            }
+            SortMode::Size => {
+                let file_stats = &self.file_stats;
+                self.changed_files.sort_by_key(|file| {
+                    std::cmp::Reverse(
+                        file_stats
+                            .get(file)
+                            .map(|(insertions, deletions)| insertions + deletions)
+                            .unwrap_or(0),
+                    )
+                });
+            }
+        }
+        if let Some(index) =
+            current_file.and_then(|file| self.changed_files.iter().position(|f| *f == file))
         {
-            state.current_file_index += 1;
-            state.scroll_position = 0; // Reset scroll when changing files
+            self.current_file_index = index;
         }
     }
 
-    fn scroll_down(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.scroll_position += 1;
+    /// Replaces `changed_files`, staying on the selected file if it's still changed
+    /// and otherwise on the nearest remaining position. Returns whether the
+    /// selection moved to a different file.
+    fn set_changed_files(&mut self, changed_files: Vec<String>) -> bool {
+        let selected = self.changed_files.get(self.current_file_index).cloned();
+        let previous_index = self.current_file_index;
+        self.changed_files = changed_files;
+        self.sort_changed_files();
+        let restored = selected
+            .as_ref()
+            .and_then(|file| self.changed_files.iter().position(|f| f == file));
+        self.current_file_index = restored
+            .unwrap_or_else(|| previous_index.min(self.changed_files.len().saturating_sub(1)));
+        restored.is_none()
     }
 
-    fn scroll_down_fast(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.scroll_position += 5;
+    fn clamp_scroll(&mut self) {
+        self.scroll_position = self.scroll_position.min(self.max_scroll());
     }
 
-    fn scroll_up_fast(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.scroll_position = state.scroll_position.saturating_sub(5);
+    /// Largest scroll offset of the status pane that still fills it.
+    fn max_stat_scroll(&self) -> u16 {
+        let area = self.pane_areas.0;
+        let (width, height) = (area.width.saturating_sub(2), area.height.saturating_sub(2));
+        if width == 0 || height == 0 {
+            return u16::MAX;
+        }
+        wrapped_line_count(&self.visible_git_stat(), width)
+            .saturating_sub(height as usize)
+            .min(u16::MAX as usize) as u16
     }
 
-    fn add_diff_to_history(&self, diff_content: String, file_name: String) {
-        let mut state = self.state.lock().unwrap();
+    fn set_error(&mut self, message: String) {
+        self.error_message = Some((message, Instant::now()));
+    }
+
+    /// The error to show in the popup, if any. Errors other than persistent ones
+    /// go away after `timeout`, unless that's zero.
+    fn visible_error(&self, timeout: Duration) -> Option<&str> {
+        if let Some(error) = &self.persistent_error {
+            return Some(error);
+        }
+        match &self.error_message {
+            Some((message, raised)) if timeout.is_zero() || raised.elapsed() < timeout => {
+                Some(message)
+            }
+            _ => None,
+        }
+    }
+
+    /// Short SHA of each session base, prefixed with the repository's label when
+    /// there are several.
+    fn session_base_label(&self) -> Option<String> {
+        if self.session_bases.is_empty() {
+            return None;
+        }
+        let mut labels: Vec<String> = self
+            .session_bases
+            .iter()
+            .map(|(directory, sha)| {
+                let short = if sha == EMPTY_TREE {
+                    "(no commits)"
+                } else {
+                    &sha[..sha.len().min(7)]
+                };
+                match self.session_bases.len() {
+                    1 => short.to_string(),
+                    _ => format!(
+                        "{}: {short}",
+                        directory.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                }
+            })
+            .collect();
+        labels.sort();
+        Some(labels.join(", "))
+    }
+
+    /// Scroll offset at which each raw diff line becomes the top row, counting
+    /// rows the way the diff pane lays the lines out: word wrapped beside the
+    /// line number gutter, or paired up in the side-by-side columns.
+    fn line_start_rows(&self) -> Vec<u16> {
+        let diff = self.display_diff();
+        let to_row = |row: usize| row.min(u16::MAX as usize) as u16;
+        if self.side_by_side {
+            return side_by_side_rows(&diff).into_iter().map(to_row).collect();
+        }
+        let mut width = self.diff_view_size.0 as usize;
+        if self.show_line_numbers {
+            width = width.saturating_sub(line_number_gutter_width(&diff_line_numbers(&diff)));
+        }
+        let mut rows = Vec::new();
+        let mut row = 0usize;
+        for line in diff_lines(&diff) {
+            rows.push(to_row(row));
+            row += if width == 0 || !self.wrap {
+                1
+            } else {
+                wrapped_rows(line, width.min(u16::MAX as usize) as u16)
+            };
+        }
+        rows
+    }
+
+    /// Index of the hunk at or above the top of the diff pane, or the first one
+    /// when the pane is scrolled above it. `None` if the diff has no hunks.
+    fn current_hunk_index(&self) -> Option<usize> {
+        let header_rows: Vec<u16> = self
+            .display_diff()
+            .lines()
+            .zip(self.line_start_rows())
+            .filter(|(line, _)| line.starts_with("@@"))
+            .map(|(_, row)| row)
+            .collect();
+        if header_rows.is_empty() {
+            return None;
+        }
+        let top = self.scroll_position.saturating_add(HUNK_CONTEXT_LINES);
+        Some(header_rows.iter().rposition(|row| *row <= top).unwrap_or(0))
+    }
+
+    /// Scroll offsets that put each hunk header near the top of the pane.
+    fn hunk_scroll_targets(&self) -> Vec<u16> {
+        self.scroll_targets(|line| line.starts_with("@@"))
+    }
+
+    /// Scroll offsets that put the `<<<<<<<` line of each conflict near the top of
+    /// the pane.
+    fn conflict_scroll_targets(&self) -> Vec<u16> {
+        self.scroll_targets(|line| {
+            conflict_marker(line).is_some_and(|marker| marker.starts_with('<'))
+        })
+    }
+
+    fn scroll_targets(&self, is_target: impl Fn(&str) -> bool) -> Vec<u16> {
+        self.display_diff()
+            .lines()
+            .zip(self.line_start_rows())
+            .filter(|(line, _)| is_target(line))
+            .map(|(_, row)| row.saturating_sub(HUNK_CONTEXT_LINES))
+            .collect()
+    }
+}
+
+/// Minimum time between two desktop notifications for the same file.
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a confirmation stays in the status bar.
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(5);
+
+/// Start of the file name of the history entries --autocommit records, which
+/// goes on with the commit's short hash.
+const AUTOCOMMIT_ENTRY: &str = "Auto-commit";
+
+/// Default file name offered by the export prompt.
+const DEFAULT_EXPORT_FILE: &str = "watchhound-history.md";
+
+/// How long a diff has to take before the loading spinner appears, so fast
+/// diffs don't flicker it.
+const LOADING_INDICATOR_DELAY: Duration = Duration::from_millis(50);
+
+/// How long the loading message stays up on startup before the first git
+/// commands run. --once has no interface to show it in, so it doesn't wait.
+fn startup_delay(options: &Options) -> Duration {
+    if options.once {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(500)
+    }
+}
+
+/// Frames of the spinner shown while a diff is loading.
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Columns scrolled sideways per Shift+Left/Right while lines aren't wrapped.
+const HORIZONTAL_SCROLL_COLUMNS: i32 = 8;
+
+/// Width of the status pane in percent unless --split says otherwise.
+const DEFAULT_SPLIT_PERCENT: u16 = 40;
+
+/// Narrowest and widest the status pane can be made, in percent.
+const SPLIT_PERCENT_RANGE: (u16, u16) = (20, 80);
+
+/// Percent the pane border moves per < or > press.
+const SPLIT_STEP_PERCENT: i32 = 5;
+
+/// Lines scrolled per mouse wheel notch.
+const MOUSE_SCROLL_LINES: u16 = 3;
+
+/// Lines kept visible above a hunk header when jumping to it.
+const HUNK_CONTEXT_LINES: u16 = 2;
+
+/// Git commands kept for the --debug log pane.
+const GIT_LOG_CAPACITY: usize = 200;
+
+/// Height of the --debug log pane, borders included.
+const GIT_LOG_PANE_HEIGHT: u16 = 10;
+
+/// Height of the timeline bar: its border, the ticks and the picked entry.
+const TIMELINE_PANE_HEIGHT: u16 = 4;
+
+/// Context lines asked of git for a whole-file diff, more than any file has.
+const WHOLE_FILE_CONTEXT: usize = 1_000_000;
+
+/// Files longer than this get a warning when shown whole.
+const WHOLE_FILE_WARN_LINES: usize = 10_000;
+
+/// Image files larger than this are described as text instead of drawn inline.
+const MAX_INLINE_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rows an inline image needs below the diff text to be worth drawing.
+const MIN_INLINE_IMAGE_ROWS: u16 = 3;
+
+/// Shortest step of a replay, so `--replay-interval 0` doesn't keep the timer
+/// task spinning, paused or not.
+const MIN_REPLAY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Diffs fetched at once by the background prefetch
+const PREFETCH_CONCURRENCY: usize = 4;
+
+struct App {
+    handle: AppHandle,
+    should_quit: bool,
+    /// Header bar text: --title, or the names of the watched repositories
+    title: String,
+    /// Image the last render left room for in the diff pane
+    image: Option<ImagePlacement>,
+    /// Image currently drawn on the terminal
+    shown_image: Option<ImagePlacement>,
+}
+
+/// A changed image file and where in the diff pane it goes.
+#[derive(Debug, Clone, PartialEq)]
+struct ImagePlacement {
+    path: PathBuf,
+    /// So the image is drawn again when the file changes
+    modified: Option<SystemTime>,
+    area: Rect,
+}
+
+/// A watched repository.
+#[derive(Debug, Clone)]
+struct Repo {
+    /// Prefix for this repository's files when several are watched
+    label: String,
+    directory: PathBuf,
+    /// Where git keeps HEAD, the index and the state of an operation in
+    /// progress: `.git`, or somewhere else for worktrees and submodules
+    git_dir: PathBuf,
+    /// Where git keeps the refs, shared by all worktrees of a repository
+    common_dir: PathBuf,
+    /// Repo-relative subdirectory (--scope-to-cwd) or file (--file) that git
+    /// output is limited to
+    scope: Option<String>,
+}
+
+impl Repo {
+    /// Pathspec arguments limiting a git command to the scope, if there is one.
+    fn pathspec(&self) -> Vec<&str> {
+        match &self.scope {
+            Some(scope) => vec!["--", scope.as_str()],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// HEAD of the watched repositories.
+#[derive(Debug, Clone)]
+struct HeadInfo {
+    /// For the status bar, see `AppHandle::run_git_head_info`
+    description: String,
+    /// Current branch of each repository, in the order they're watched
+    branches: Vec<String>,
+}
+
+/// Cheap to clone handle on the shared state, moved into spawned tasks to run git
+/// and update the diff in the background.
+#[derive(Clone)]
+struct AppHandle {
+    state: Arc<Mutex<AppState>>,
+    repos: Arc<Vec<Repo>>,
+    options: Arc<Options>,
+}
+
+impl App {
+    fn new(repos: Vec<Repo>, options: Options, title: Option<String>) -> Self {
+        let title = title.unwrap_or_else(|| {
+            repos
+                .iter()
+                .map(|repo| repo.label.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        Self {
+            handle: AppHandle {
+                state: Arc::new(Mutex::new(AppState {
+                    ignore_whitespace: options.ignore_whitespace,
+                    context_lines: options.context_lines,
+                    split_percent: options.split_percent,
+                    compact: options.compact,
+                    detect_renames: options.detect_renames,
+                    fold_context: options.fold_context,
+                    diff_scope: if options.include_staged {
+                        DiffScope::All
+                    } else {
+                        DiffScope::WorkingTree
+                    },
+                    file_filter: options.filter.clone(),
+                    ..AppState::default()
+                })),
+                repos: Arc::new(repos),
+                options: Arc::new(options),
+            },
+            should_quit: false,
+            title,
+            image: None,
+            shown_image: None,
+        }
+    }
+
+    fn format_git_stat_with_status(
+        git_stat: &str,
+        file_mod_status: &HashMap<String, bool>,
+        file_status: &HashMap<String, FileStatus>,
+        deleted_files: &HashSet<String>,
+        change_counts: &HashMap<String, usize>,
+        display_path: impl Fn(&str) -> String,
+    ) -> Text<'static> {
+        let lines: Vec<Line> = git_stat
+            .lines()
+            .map(|line| {
+                if line.contains("|") {
+                    // File change lines with stats
+                    let parts: Vec<&str> = line.split('|').collect();
+                    if parts.len() >= 2 {
+                        let file_part = parts[0].trim().to_string();
+                        let stats_part = parts[1].trim().to_string();
+                        let file_key = stat_rename_target(&file_part).unwrap_or(file_part.clone());
+                        // Renames and names git shortened to ".../name" are left as listed
+                        let file_part = if file_key == file_part {
+                            display_path(&file_key)
+                        } else {
+                            file_part
+                        };
+
+                        // Check if file was recently modified (within 1 minute)
+                        let is_recent = file_mod_status.get(&file_key).unwrap_or(&false);
+                        let status = file_status
+                            .get(&file_key)
+                            .copied()
+                            .unwrap_or(FileStatus::Unstaged);
+                        let file_color = if status == FileStatus::Conflicted {
+                            Color::LightRed
+                        } else if *is_recent {
+                            Color::Yellow
+                        } else if status != FileStatus::Unstaged {
+                            Color::LightGreen
+                        } else {
+                            Color::White
+                        };
+
+                        let mut spans = Vec::new();
+                        if status == FileStatus::Conflicted {
+                            spans.push(Span::styled(
+                                status.tag().to_string(),
+                                Style::default()
+                                    .fg(Color::LightRed)
+                                    .add_modifier(Modifier::BOLD),
+                            ));
+                        } else if status != FileStatus::Unstaged {
+                            // Staged entries get a tag so they stand out from working-tree changes
+                            spans.push(Span::styled(
+                                status.tag().to_string(),
+                                Style::default().fg(Color::LightGreen),
+                            ));
+                        }
+                        let file_style = if deleted_files.contains(&file_key) {
+                            spans.push(Span::styled(
+                                DELETED_TAG.to_string(),
+                                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                            ));
+                            Style::default()
+                                .fg(Color::Red)
+                                .add_modifier(Modifier::CROSSED_OUT)
+                        } else {
+                            Style::default().fg(file_color)
+                        };
+                        spans.push(Span::styled(file_part, file_style));
+                        if let Some(badge) = change_badge(change_counts, &file_key) {
+                            spans.push(badge);
+                        }
+                        spans.push(Span::styled(
+                            " | ".to_string(),
+                            Style::default().fg(Color::Gray),
+                        ));
+
+                        // Color the stats part; binary files get a marker and their
+                        // size change instead of +/- counts
+                        if let Some(mode) = stats_part.strip_prefix("mode ") {
+                            // Only the permissions changed, so there are no counts
+                            spans.push(Span::styled(
+                                "[mode] ".to_string(),
+                                Style::default().fg(Color::Magenta),
+                            ));
+                            spans.push(Span::styled(
+                                mode.to_string(),
+                                Style::default().fg(Color::Magenta),
+                            ));
+                        } else if let Some((before, after)) = binary_stat_sizes(&stats_part) {
+                            spans.push(Span::styled(
+                                "[bin] ".to_string(),
+                                Style::default().fg(Color::Magenta),
+                            ));
+                            spans.push(Span::styled(
+                                format!("{before} → {after} bytes"),
+                                Style::default().fg(Color::Magenta),
+                            ));
+                        } else {
+                            spans.extend(stat_bar_spans(&stats_part));
+                        }
+
+                        Line::from(spans)
+                    } else {
+                        Line::from(vec![Span::styled(
+                            line.to_string(),
+                            Style::default().fg(Color::White),
+                        )])
+                    }
+                } else if file_status.get(line.trim()) == Some(&FileStatus::Untracked) {
+                    // Untracked file listed below the stat
+                    Line::from(vec![
+                        Span::styled(
+                            FileStatus::Untracked.tag().to_string(),
+                            Style::default().fg(Color::LightBlue),
+                        ),
+                        Span::styled(
+                            display_path(line.trim()),
+                            Style::default().fg(Color::LightBlue),
+                        ),
+                    ])
+                } else if line == "Untracked files:" {
+                    Line::from(vec![Span::styled(
+                        line.to_string(),
+                        Style::default().fg(Color::Cyan),
+                    )])
+                } else if line.contains("changed")
+                    || line.contains("insertion")
+                    || line.contains("deletion")
+                {
+                    // Summary line
+                    Line::from(vec![Span::styled(
+                        line.to_string(),
+                        Style::default().fg(Color::Cyan),
+                    )])
+                } else {
+                    Line::from(vec![Span::styled(
+                        line.to_string(),
+                        Style::default().fg(Color::White),
+                    )])
+                }
+            })
+            .collect();
+
+        Text::from(lines)
+    }
+
+    /// Renders the tree view of the changed files, highlighting the cursor row.
+    fn format_file_tree(
+        rows: &[TreeRow],
+        cursor: Option<usize>,
+        file_mod_status: &HashMap<String, bool>,
+        file_status: &HashMap<String, FileStatus>,
+        deleted_files: &HashSet<String>,
+        change_counts: &HashMap<String, usize>,
+    ) -> Text<'static> {
+        let lines: Vec<Line> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut spans = vec![Span::raw("  ".repeat(row.depth))];
+                if row.is_dir {
+                    let marker = if row.collapsed { "▸ " } else { "▾ " };
+                    spans.push(Span::styled(
+                        format!("{marker}{}/", row.name),
+                        Style::default().fg(Color::Cyan),
+                    ));
+                } else {
+                    let status = file_status
+                        .get(&row.path)
+                        .copied()
+                        .unwrap_or(FileStatus::Unstaged);
+                    let file_color = if status == FileStatus::Conflicted {
+                        Color::LightRed
+                    } else if file_mod_status.get(&row.path) == Some(&true) {
+                        Color::Yellow
+                    } else {
+                        match status {
+                            FileStatus::Unstaged => Color::White,
+                            FileStatus::Untracked => Color::LightBlue,
+                            FileStatus::Staged | FileStatus::Both => Color::LightGreen,
+                            FileStatus::Conflicted => Color::LightRed,
+                        }
+                    };
+                    if deleted_files.contains(&row.path) {
+                        spans.push(Span::styled(
+                            format!("  {}{DELETED_TAG}", status.tag()),
+                            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                        ));
+                        spans.push(Span::styled(
+                            row.name.clone(),
+                            Style::default()
+                                .fg(Color::Red)
+                                .add_modifier(Modifier::CROSSED_OUT),
+                        ));
+                    } else {
+                        spans.push(Span::styled(
+                            format!("  {}{}", status.tag(), row.name),
+                            Style::default().fg(file_color),
+                        ));
+                    }
+                    if let Some(badge) = change_badge(change_counts, &row.path) {
+                        spans.push(badge);
+                    }
+                }
+                if row.insertions > 0 {
+                    spans.push(Span::styled(
+                        format!(" +{}", row.insertions),
+                        Style::default().fg(Color::Green),
+                    ));
+                }
+                if row.deletions > 0 {
+                    spans.push(Span::styled(
+                        format!(" -{}", row.deletions),
+                        Style::default().fg(Color::Red),
+                    ));
+                }
+                let mut line = Line::from(spans);
+                if cursor == Some(i) {
+                    line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+                }
+                line
+            })
+            .collect();
+
+        Text::from(lines)
+    }
+
+    /// The newest git commands that fit in `rows` lines, each followed by its
+    /// stderr, for the --debug log pane.
+    fn format_git_log(entries: &VecDeque<GitLogEntry>, rows: usize) -> Text<'static> {
+        let mut lines: Vec<Line> = Vec::new();
+        for entry in entries.iter().rev() {
+            if lines.len() >= rows {
+                break;
+            }
+            let status_color = if entry.success {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            let mut entry_lines = vec![Line::from(vec![
+                Span::styled(
+                    format!("{} ", entry.time.format("%H:%M:%S")),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!("git {} ", entry.args)),
+                Span::styled(
+                    format!("in {} ", entry.directory.display()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!("{} ({}ms)", entry.status, entry.elapsed.as_millis()),
+                    Style::default().fg(status_color),
+                ),
+            ])];
+            entry_lines.extend(entry.stderr.lines().map(|line| {
+                Line::from(Span::styled(
+                    format!("    {line}"),
+                    Style::default().fg(Color::LightRed),
+                ))
+            }));
+            lines.splice(0..0, entry_lines);
+        }
+        let overflow = lines.len().saturating_sub(rows);
+        lines.drain(..overflow);
+        Text::from(lines)
+    }
+
+    /// One tick per history entry with the picked one highlighted, followed by
+    /// that entry's time and file. Ticks scroll to keep the cursor in `width`.
+    fn format_timeline(
+        history: &VecDeque<DiffEntry>,
+        cursor: usize,
+        width: usize,
+    ) -> Text<'static> {
+        let width = width.max(1);
+        let first = cursor
+            .saturating_sub(width / 2)
+            .min(history.len().saturating_sub(width));
+        let ticks: Vec<Span> = (first..history.len().min(first + width))
+            .map(|i| {
+                if i == cursor {
+                    Span::styled(
+                        "┃",
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                } else {
+                    Span::styled("│", Style::default().fg(Color::DarkGray))
+                }
+            })
+            .collect();
+        let label = history
+            .get(cursor)
+            .map(|entry| {
+                format!(
+                    "{} {} ({}/{})",
+                    entry.timestamp.format("%H:%M:%S"),
+                    entry.file_name,
+                    cursor + 1,
+                    history.len()
+                )
+            })
+            .unwrap_or_default();
+        Text::from(vec![Line::from(ticks), Line::from(label)])
+    }
+
+    /// The jump prompt's input followed by the files matching it, best first, with
+    /// the matched characters picked out. The list scrolls to keep the selected
+    /// file within `rows`.
+    fn format_jump_list(prompt: &Prompt, files: &[String], rows: usize) -> Text<'static> {
+        let (query, selected) = (prompt.query(), prompt.selected);
+        let matches = prompt.jump_matches(files);
+        let first = (selected + 1).saturating_sub(rows.max(1));
+        let mut lines = vec![Line::from(format!("> {}_", prompt.input))];
+        lines.extend(
+            matches
+                .iter()
+                .enumerate()
+                .skip(first)
+                .take(rows)
+                .map(|(i, &index)| {
+                    let file = &files[index];
+                    let positions = fuzzy::matched_positions(query, file).unwrap_or_default();
+                    let mut line = Line::from(
+                        file.chars()
+                            .enumerate()
+                            .map(|(position, c)| {
+                                let style = if positions.contains(&position) {
+                                    Style::default()
+                                        .fg(Color::Yellow)
+                                        .add_modifier(Modifier::BOLD)
+                                } else {
+                                    Style::default().fg(Color::White)
+                                };
+                                Span::styled(c.to_string(), style)
+                            })
+                            .collect::<Vec<_>>(),
+                    );
+                    if i == selected {
+                        line.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+                    }
+                    line
+                }),
+        );
+        if matches.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "No matching files",
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+        Text::from(lines)
+    }
+
+    /// The changed files in one line for the compact layout: the previous file,
+    /// the current one with its position and the next file.
+    fn format_compact_header(
+        files: &[String],
+        current: usize,
+        display_path: impl Fn(&str) -> String,
+    ) -> Line<'static> {
+        let Some(file) = files.get(current) else {
+            return Line::from(Span::styled(
+                "No changes detected",
+                Style::default().fg(Color::DarkGray),
+            ));
+        };
+        let mut spans = Vec::new();
+        if let Some(previous) = current.checked_sub(1).and_then(|i| files.get(i)) {
+            spans.push(Span::styled(
+                format!("‹ {} | ", display_path(previous)),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        spans.push(Span::styled(
+            format!("{} ({}/{})", display_path(file), current + 1, files.len()),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+        if let Some(next) = files.get(current + 1) {
+            spans.push(Span::styled(
+                format!(" | {} ›", display_path(next)),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        Line::from(spans)
+    }
+
+    /// Border of a pane, highlighted while the scroll keys apply to it.
+    fn pane_border_style(focused: bool) -> Style {
+        if focused {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default()
+        }
+    }
+
+    /// Where in the diff pane to draw the current file, when it is an image the
+    /// terminal can show and nothing is drawn over the pane.
+    fn image_placement(
+        handle: &AppHandle,
+        state: &AppState,
+        pane: Rect,
+        total_rows: usize,
+    ) -> Option<ImagePlacement> {
+        let protocol = handle.options.graphics?;
+        if state.show_dashboard
+            || state.show_history
+            || state.history_cursor.is_some()
+            || state.prompt.is_some()
+            || state.blame.is_some()
+            || state.confirming_quit
+            || state.visible_error(handle.options.error_timeout).is_some()
+        {
+            return None;
+        }
+        let file = state.changed_files.get(state.current_file_index)?;
+        if state.deleted_files.contains(file) {
+            return None;
+        }
+        let (repo, relative) = handle.resolve_file(file);
+        let path = repo.directory.join(relative);
+        if !protocol.supports(&path) {
+            return None;
+        }
+        let metadata = fs::metadata(&path).ok()?;
+        if !metadata.is_file() || metadata.len() > MAX_INLINE_IMAGE_BYTES {
+            return None;
+        }
+
+        // Below the diff text, which for an image is just the headers and the
+        // size change, with a blank row in between
+        let inner = pane.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        });
+        let text_rows = (total_rows as u16 + 1).saturating_sub(state.scroll_position);
+        let rows = inner.height.saturating_sub(text_rows);
+        if rows < MIN_INLINE_IMAGE_ROWS {
+            return None;
+        }
+        Some(ImagePlacement {
+            path,
+            modified: metadata.modified().ok(),
+            area: Rect {
+                y: inner.y + text_rows,
+                height: rows,
+                ..inner
+            },
+        })
+    }
+
+    /// Replaces the image drawn on the terminal with the one the last render
+    /// made room for.
+    fn draw_image(&mut self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+        let Some(protocol) = self.handle.options.graphics else {
+            return Ok(());
+        };
+        if self.shown_image.take().is_some() {
+            match protocol {
+                GraphicsProtocol::Kitty => {
+                    write!(terminal.backend_mut(), "{KITTY_CLEAR}")?;
+                }
+                // An iTerm2 image replaces the cells it covers without ratatui
+                // knowing, so they are only back after drawing everything again
+                GraphicsProtocol::Iterm2 => {
+                    terminal.clear()?;
+                    terminal.draw(|f| self.render(f))?;
+                }
+            }
+        }
+        if let Some(image) = &self.image {
+            // A file that can't be read, e.g. halfway through being written,
+            // leaves the text description alone until the next change
+            if let Ok(data) = fs::read(&image.path) {
+                let backend = terminal.backend_mut();
+                execute!(backend, MoveTo(image.area.x, image.area.y))?;
+                write!(
+                    backend,
+                    "{}",
+                    protocol.escape_sequence(&data, image.area.width, image.area.height)
+                )?;
+            }
+        }
+        terminal.backend_mut().flush()?;
+        self.shown_image = self.image.clone();
+        Ok(())
+    }
+
+    /// Starts over with a blank screen after the terminal changed size, so
+    /// nothing the terminal left behind while reflowing stays up. The next draw
+    /// lays the panes out again and clamps the scroll positions to their new
+    /// sizes.
+    fn handle_resize(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    ) -> Result<()> {
+        if self.shown_image.take().is_some()
+            && self.handle.options.graphics == Some(GraphicsProtocol::Kitty)
+        {
+            write!(terminal.backend_mut(), "{KITTY_CLEAR}")?;
+        }
+        terminal.autoresize()?;
+        terminal.clear()?;
+        Ok(())
+    }
+
+    fn render(&mut self, f: &mut Frame) {
+        // --debug keeps a log of git commands below the panes, and the timeline
+        // takes a bar above that while it's open
+        let timeline_open = self.handle.state.lock().unwrap().history_cursor.is_some();
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(0),
+                Constraint::Length(if timeline_open {
+                    TIMELINE_PANE_HEIGHT
+                } else {
+                    0
+                }),
+                Constraint::Length(if self.handle.options.debug {
+                    GIT_LOG_PANE_HEIGHT
+                } else {
+                    0
+                }),
+            ])
+            .split(f.size());
+        let header_area = rows[0];
+        let main_area = rows[1];
+        let timeline_area = timeline_open.then_some(rows[2]);
+        let log_area = self.handle.options.debug.then_some(rows[3]);
+
+        let (split_percent, compact) = {
+            let state = self.handle.state.lock().unwrap();
+            (state.split_percent, state.compact)
+        };
+        let chunks = if compact {
+            // The changed files get a line above the diff instead of a pane
+            Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(main_area)
+        } else {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    [
+                        Constraint::Percentage(split_percent),
+                        Constraint::Percentage(100 - split_percent),
+                    ]
+                    .as_ref(),
+                )
+                .split(main_area)
+        };
+
+        let mut state = self.handle.state.lock().unwrap();
+
+        // Remember the pane layout for mouse handling and the diff pane size so
+        // scrolling can be clamped to the content
+        state.pane_areas = if compact {
+            (Rect::default(), chunks[1])
+        } else {
+            (chunks[0], chunks[1])
+        };
+        state.diff_view_size = (
+            chunks[1].width.saturating_sub(2),
+            chunks[1].height.saturating_sub(2),
+        );
+        // Counting rows means laying out the whole diff, so it's done once a frame
+        let total_rows = state.total_rows();
+        state.scroll_position = state.scroll_position.min(state.max_scroll_for(total_rows));
+        state.stat_scroll = state.stat_scroll.min(state.max_stat_scroll());
+
+        // Which repository and branch this is, for telling several instances apart
+        let mut header = vec![Span::styled(
+            format!(" {}", self.title),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        if let Some(head_info) = &state.head_info {
+            header.push(Span::styled(
+                format!(" ({})", head_info.branches.join(", ")),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        f.render_widget(
+            Paragraph::new(Line::from(header)).style(Style::default().fg(Color::White)),
+            header_area,
+        );
+
+        // Pre-compute file modification status to avoid deadlock
+        let file_mod_status: HashMap<String, bool> = state
+            .file_info
+            .iter()
+            .map(|(path, info)| {
+                let is_recent = if let Ok(elapsed) = info.last_modified.elapsed() {
+                    elapsed < Duration::from_secs(60)
+                } else {
+                    false
+                };
+                (path.clone(), is_recent)
+            })
+            .collect();
+
+        // Left pane - git stat, or the directory tree
+        let left_title = if state.tree_view {
+            "Changed Files"
+        } else {
+            "Git Status"
+        };
+        let left_title = match state.status_filter {
+            StatusFilter::All => left_title.to_string(),
+            status_filter => format!("{left_title} [{}]", status_filter.label()),
+        };
+        let left_block = Block::default()
+            .title(left_title)
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .border_style(Self::pane_border_style(state.focused_pane == Pane::Status));
+
+        let git_stat_text = if state.tree_view {
+            let rows = state.tree_rows();
+            if rows.is_empty() {
+                Text::from("No changes detected")
+            } else {
+                let mut text = Self::format_file_tree(
+                    &rows,
+                    state.tree_cursor(&rows),
+                    &file_mod_status,
+                    &state.file_status,
+                    &state.deleted_files,
+                    &state.change_counts,
+                );
+                text.lines.drain(..state.tree_scroll(&rows));
+                text
+            }
+        } else if state.git_stat.is_empty() {
+            Text::from("No changes detected")
+        } else {
+            Self::format_git_stat_with_status(
+                &state.visible_git_stat(),
+                &file_mod_status,
+                &state.file_status,
+                &state.deleted_files,
+                &state.change_counts,
+                |file| self.handle.display_path(file),
+            )
+        };
+
+        // The tree scrolls to its cursor instead
+        let stat_scroll = if state.tree_view {
+            0
+        } else {
+            state.stat_scroll
+        };
+        let git_stat_paragraph = Paragraph::new(git_stat_text)
+            .block(left_block)
+            .wrap(Wrap { trim: true })
+            .scroll((stat_scroll, 0));
+
+        if compact {
+            let header = Self::format_compact_header(
+                &state.changed_files,
+                state.current_file_index,
+                |file| self.handle.display_path(file),
+            );
+            f.render_widget(
+                Paragraph::new(header).alignment(Alignment::Center),
+                chunks[0],
+            );
+        } else {
+            f.render_widget(git_stat_paragraph, chunks[0]);
+        }
+
+        // Right pane - git diff. The title is put together as head, file label and
+        // the rest, so the label can give way when the pane is narrow
+        let (title_head, file_label, right_title) = if !state.changed_files.is_empty() {
+            let current_file = &state.changed_files[state.current_file_index];
+            let is_recent = file_mod_status.get(current_file).unwrap_or(&false);
+            let indicator = if *is_recent { " [RECENT]" } else { "" };
+            let indicator = if state.pinned_file.as_ref() == Some(current_file) {
+                format!("{indicator} [PINNED]")
+            } else {
+                indicator.to_string()
+            };
+            let indicator = if state.whole_files.contains(current_file) {
+                format!("{indicator} [WHOLE FILE]")
+            } else {
+                indicator
+            };
+            let status_tag = state
+                .file_status
+                .get(current_file)
+                .map(|status| status.tag())
+                .unwrap_or("");
+            let status_tag = if state.deleted_files.contains(current_file) {
+                format!("{status_tag}{DELETED_TAG}")
+            } else {
+                status_tag.to_string()
+            };
+            let file_label = match state.renames.get(current_file) {
+                Some(old) => format!(
+                    "{} → {}",
+                    self.handle.display_path(old),
+                    self.handle.display_path(current_file)
+                ),
+                None => self.handle.display_path(current_file),
+            };
+            // Files missing from the stat, like pure renames, go without counts
+            let counts = state
+                .file_stats
+                .get(current_file)
+                .map(|(insertions, deletions)| format!(" (+{insertions}/-{deletions})"))
+                .unwrap_or_default();
+            (
+                format!("Git Diff - {status_tag}"),
+                file_label,
+                format!(
+                    "{}{} ({}/{})",
+                    counts,
+                    indicator,
+                    state.current_file_index + 1,
+                    state.changed_files.len()
+                ),
+            )
+        } else {
+            ("Git Diff".to_string(), String::new(), String::new())
+        };
+        let right_title = match &state.file_filter {
+            Some(filter) => format!("{right_title} [filter: {}]", filter.glob()),
+            None => right_title,
+        };
+        let right_title = match state.sort_mode {
+            SortMode::Git => right_title,
+            sort_mode => format!("{right_title} [sort: {}]", sort_mode.label()),
+        };
+        let right_title = if state
+            .loading_since
+            .is_some_and(|since| since.elapsed() >= LOADING_INDICATOR_DELAY)
+        {
+            state.spinner_frame = state.spinner_frame.wrapping_add(1);
+            let frame = SPINNER_FRAMES[state.spinner_frame % SPINNER_FRAMES.len()];
+            format!("{right_title} {frame} loading")
+        } else {
+            right_title
+        };
+
+        let right_title = match self.handle.options.mode {
+            DiffMode::Git => format!("{right_title} [{}]", state.diff_scope.label()),
+            DiffMode::Snapshot(_) => right_title,
+        };
+
+        let right_title = if !state.show_history && !state.split_markers.is_empty() {
+            format!("{right_title} [▌staged ▌unstaged]")
+        } else {
+            right_title
+        };
+
+        let (title_head, file_label, right_title) = match state.timeline_entry() {
+            Some((index, entry)) => (
+                "Timeline - ".to_string(),
+                entry.file_name.clone(),
+                format!(
+                    " (update {}/{} at {})",
+                    index + 1,
+                    state.diff_history.len(),
+                    entry.timestamp.format("%H:%M:%S")
+                ),
+            ),
+            None => (title_head, file_label, right_title),
+        };
+
+        let title_width = chunks[1].width.saturating_sub(2) as usize;
+        let label_width =
+            title_width.saturating_sub(title_head.chars().count() + right_title.chars().count());
+        let right_title = format!(
+            "{title_head}{}{right_title}",
+            truncate_middle(&file_label, label_width)
+        );
+
+        let right_block = Block::default()
+            .title(right_title)
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .border_style(Self::pane_border_style(state.focused_pane == Pane::Diff));
+
+        let syntax_file = if state.syntax_highlight {
+            Some(
+                state
+                    .changed_files
+                    .get(state.current_file_index)
+                    .map(String::as_str)
+                    .unwrap_or(""),
+            )
+        } else {
+            None
+        };
+
+        let whitespace = state
+            .show_whitespace
+            .then_some(&self.handle.options.whitespace_rules);
+
+        // Staged/unstaged markers, moved to the displayed lines; folds never hide
+        // changed lines
+        let split_markers: HashMap<usize, bool> =
+            if state.show_history || state.history_cursor.is_some() {
+                HashMap::new()
+            } else {
+                state
+                    .split_markers
+                    .iter()
+                    .map(|(line, staged)| (state.display_line(*line), *staged))
+                    .collect()
+            };
+
+        let display_diff = state.display_diff();
+        if state.show_dashboard {
+            let (files, insertions, deletions) =
+                state
+                    .stat_totals
+                    .unwrap_or((state.changed_files.len(), 0, 0));
+            let summary = dashboard::Summary {
+                files,
+                insertions,
+                deletions,
+            };
+            dashboard::render(f, chunks[1], &summary, &state.diff_history);
+        } else if let Some(colored) = state.external_diff() {
+            // --diff-command did the coloring; folds, line numbers and the
+            // side-by-side layout are up to the command
+            let paragraph = Paragraph::new(ansi::to_text(colored)).block(right_block);
+            let paragraph = if state.wrap {
+                paragraph
+                    .wrap(Wrap { trim: false })
+                    .scroll((state.scroll_position, 0))
+            } else {
+                paragraph.scroll((state.scroll_position, state.h_scroll))
+            };
+            f.render_widget(paragraph, chunks[1]);
+        } else if state.side_by_side && !display_diff.is_empty() {
+            // Old version on the left, new version on the right, scrolled together
+            let inner = right_block.inner(chunks[1]);
+            f.render_widget(right_block, chunks[1]);
+
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(inner);
+
+            let (old_text, new_text) = format_side_by_side(
+                &display_diff,
+                state.show_line_numbers,
+                syntax_file,
+                whitespace,
+            );
+            let old_paragraph = Paragraph::new(old_text)
+                .block(
+                    Block::default()
+                        .borders(Borders::RIGHT)
+                        .style(Style::default().fg(Color::Gray)),
+                )
+                .scroll((state.scroll_position, state.h_scroll));
+            let new_paragraph =
+                Paragraph::new(new_text).scroll((state.scroll_position, state.h_scroll));
+
+            f.render_widget(old_paragraph, columns[0]);
+            f.render_widget(new_paragraph, columns[1]);
+        } else {
+            let git_diff_text = if display_diff.is_empty() {
+                Text::from("No changes to show")
+            } else {
+                format_diff_text(
+                    &display_diff,
+                    state.show_line_numbers,
+                    syntax_file,
+                    state.word_diff,
+                    state.focused_fold().map(|(line, _)| line),
+                    whitespace,
+                    &split_markers,
+                )
+            };
+
+            let git_diff_paragraph = Paragraph::new(git_diff_text).block(right_block);
+            let git_diff_paragraph = if state.wrap {
+                git_diff_paragraph
+                    .wrap(Wrap { trim: true })
+                    .scroll((state.scroll_position, 0))
+            } else {
+                git_diff_paragraph.scroll((state.scroll_position, state.h_scroll))
+            };
+
+            f.render_widget(git_diff_paragraph, chunks[1]);
+        }
+
+        // Scrollbar on the diff pane's right border, only when the diff overflows
+        let visible_rows = state.diff_view_size.1 as usize;
+        if total_rows > visible_rows && !state.show_dashboard {
+            let mut scrollbar_state = ScrollbarState::new(total_rows)
+                .viewport_content_length(visible_rows)
+                .position(state.scroll_position as usize);
+            let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None);
+            f.render_stateful_widget(
+                scrollbar,
+                chunks[1].inner(&Margin {
+                    vertical: 1,
+                    horizontal: 0,
+                }),
+                &mut scrollbar_state,
+            );
+        }
+
+        // Images don't go through ratatui's buffer, so the image is only given a
+        // place here and drawn once the frame is out
+        self.image = Self::image_placement(&self.handle, &state, chunks[1], total_rows);
+
+        if let (Some(timeline_area), Some(cursor)) = (timeline_area, state.history_cursor) {
+            let timeline_text = Self::format_timeline(
+                &state.diff_history,
+                cursor,
+                timeline_area.width.saturating_sub(2) as usize,
+            );
+            let title = match state.replay {
+                Some(replay) if replay.paused => "Replay paused (Space to resume, R to stop)",
+                Some(_) => "Replaying (Space to pause, R to stop)",
+                None => "Timeline ([/] to scrub, Esc to close)",
+            };
+            let timeline_paragraph = Paragraph::new(timeline_text).block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(Color::White)),
+            );
+            f.render_widget(timeline_paragraph, timeline_area);
+        }
+
+        if let Some(log_area) = log_area {
+            let log_text =
+                Self::format_git_log(&state.git_log, log_area.height.saturating_sub(2) as usize);
+            let log_paragraph = Paragraph::new(log_text).block(
+                Block::default()
+                    .title("Git commands")
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(Color::White)),
+            );
+            f.render_widget(log_paragraph, log_area);
+        }
+
+        // Show error message if any
+        if let Some(error) = state.visible_error(self.handle.options.error_timeout) {
+            let error_area = centered_rect(60, 20, f.size());
+            f.render_widget(Clear, error_area);
+            let title = if state.persistent_error.is_some() {
+                "Error"
+            } else {
+                "Error (Esc to dismiss)"
+            };
+            let error_block = Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Red));
+
+            let error_paragraph = Paragraph::new(error.to_string())
+                .block(error_block)
+                .wrap(Wrap { trim: true });
+
+            f.render_widget(error_paragraph, error_area);
+        }
+
+        // Show the prompt while it is being edited
+        if let Some(prompt) = &state.prompt {
+            // The jump prompt lists the matching files below its input
+            let (height, title) = match prompt.kind {
+                PromptKind::Filter => (
+                    20,
+                    "Filter files (glob, empty to clear, Enter to apply, Esc to cancel)",
+                ),
+                PromptKind::Export => (
+                    20,
+                    "Export history to (.md or .html, Enter to save, Esc to cancel)",
+                ),
+                PromptKind::Jump => (
+                    60,
+                    "Jump to file (type to filter, Up/Down to choose, Enter to jump, Esc to cancel)",
+                ),
+            };
+            let prompt_area = centered_rect(60, height, f.size());
+            f.render_widget(Clear, prompt_area);
+            let prompt_block = Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Cyan));
+
+            let prompt_paragraph = if prompt.kind == PromptKind::Jump {
+                let rows = prompt_area.height.saturating_sub(3) as usize;
+                Paragraph::new(Self::format_jump_list(prompt, &state.changed_files, rows))
+            } else {
+                Paragraph::new(format!("{}_", prompt.input)).wrap(Wrap { trim: false })
+            };
+
+            f.render_widget(prompt_paragraph.block(prompt_block), prompt_area);
+        }
+
+        // Blame of the current hunk
+        if let Some(blame) = &state.blame {
+            let blame_area = centered_rect(80, 60, f.size());
+            f.render_widget(Clear, blame_area);
+            let blame_block = Block::default()
+                .title(format!("{} (Up/Down to scroll, Esc to close)", blame.title))
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White));
+
+            let blame_text = Text::from(
+                blame
+                    .lines
+                    .iter()
+                    .map(|line| Line::from(line.clone()))
+                    .collect::<Vec<_>>(),
+            );
+            let blame_paragraph = Paragraph::new(blame_text)
+                .block(blame_block)
+                .scroll((blame.scroll, 0));
+
+            f.render_widget(blame_paragraph, blame_area);
+        }
+
+        // Ask before quitting when that would throw away the diff history
+        if state.confirming_quit {
+            let confirm_area = centered_rect(40, 20, f.size());
+            f.render_widget(Clear, confirm_area);
+            let confirm_block = Block::default()
+                .title("Quit")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Yellow));
+
+            let confirm_paragraph = Paragraph::new(format!(
+                "Quit? The diff history ({} entries) will be lost. (y/n)",
+                state.diff_history.len()
+            ))
+            .block(confirm_block)
+            .wrap(Wrap { trim: true });
+
+            f.render_widget(confirm_paragraph, confirm_area);
+        }
+
+        // Show controls and last update time
+        let controls = "Controls: Left/Right: Navigate files | Tab: Switch pane | Space: Scroll down | PgUp/PgDn/Home/End: Page | {/}: Prev/next hunk | [/]: Prev/next conflict | Enter: Expand fold | s: Side-by-side | n: Line numbers | l: Syntax | d: Word diff | e: Whitespace errors | W: Wrap (Shift+Left/Right scrolls) | +/-: Context | w: Ignore whitespace | m: Renames | T: Tree | C: Compact | t: Scope | B: Reset session base | a/u: Stage/unstage | o: Sort | f: Filter | S: Staged/unstaged only | g/Ctrl+P: Jump to file | E: Export | X: Full diff | F: Whole file | </>: Resize panes | Mouse: Scroll/select/resize | O: History order | v: Dashboard | H: Timeline ([/] scrub) | R: Replay | b: Blame | y/Y: Copy hunk/path | p: Pin | q: Quit | r: Refresh | [RECENT] = Recently changed";
+        let mut status_line = if let Some(last_update) = &state.last_update {
+            format!(
+                "{} | Last updated: {}",
+                controls,
+                last_update.format("%H:%M:%S")
+            )
+        } else {
+            controls.to_string()
+        };
+        // Keep the HEAD info and comparison base up front so they survive truncation
+        // on narrow terminals
+        if let Some((files, insertions, deletions)) = state.stat_totals {
+            let plural = if files == 1 { "" } else { "s" };
+            status_line =
+                format!("{files} file{plural} +{insertions} -{deletions} | {status_line}");
+        }
+        if let Some(head_info) = &state.head_info {
+            status_line = format!("{} | {status_line}", head_info.description);
+        }
+        if let Some(base) = &self.handle.options.base {
+            status_line = format!("Base: {base} | {status_line}");
+        }
+        if state.diff_scope == DiffScope::Session {
+            if let Some(label) = state.session_base_label() {
+                status_line = format!("Session base: {label} | {status_line}");
+            }
+        }
+        if let Some((message, shown_at)) = &state.status_message {
+            if shown_at.elapsed() < STATUS_MESSAGE_DURATION {
+                status_line = format!("{message} | {status_line}");
+            }
+        }
+
+        let status_area = Rect {
+            x: 0,
+            y: f.size().height - 1,
+            width: f.size().width,
+            height: 1,
+        };
+
+        let status_paragraph = Paragraph::new(status_line).style(Style::default().fg(Color::Gray));
+
+        f.render_widget(status_paragraph, status_area);
+
+        self.handle.options.color_level.apply(f.buffer_mut());
+    }
+}
+
+impl AppHandle {
+    fn navigate_to_previous_file(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.changed_files.is_empty() {
+            return;
+        }
+        if state.current_file_index > 0 {
+            let index = state.current_file_index - 1;
+            state.select_file(index);
+        } else if self.options.wrap_navigation {
+            let index = state.changed_files.len() - 1;
+            state.select_file(index);
+        }
+    }
+
+    fn navigate_to_next_file(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.changed_files.is_empty() {
+            return;
+        }
+        if state.current_file_index < state.changed_files.len() - 1 {
+            let index = state.current_file_index + 1;
+            state.select_file(index);
+        } else if self.options.wrap_navigation {
+            state.select_file(0);
+        }
+    }
+
+    fn scroll_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = state.scroll_position.saturating_add(1);
+        state.clamp_scroll();
+    }
+
+    fn scroll_down_by(&self, lines: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = state.scroll_position.saturating_add(lines);
+        state.clamp_scroll();
+    }
+
+    fn scroll_up_by(&self, lines: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = state.scroll_position.saturating_sub(lines);
+    }
+
+    /// Selects the changed file listed on the given row of the status pane.
+    /// Returns true if the selection changed.
+    fn select_file_at_status_row(&self, row: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.tree_view {
+            // Clicking a folder toggles it, clicking a file selects it
+            let rows = state.tree_rows();
+            let Some(tree_row) = rows.get(row + state.tree_scroll(&rows)) else {
+                return false;
+            };
+            if tree_row.is_dir && !state.collapsed_dirs.remove(&tree_row.path) {
+                state.collapsed_dirs.insert(tree_row.path.clone());
+            }
+            return state.select_tree_row(tree_row);
+        }
+        let visible_git_stat = state.visible_git_stat();
+        let index = visible_git_stat
+            .lines()
+            .nth(row + state.stat_scroll as usize)
+            .and_then(|line| state.stat_line_file(line))
+            .and_then(|file| state.changed_files.iter().position(|f| f == file));
+        match index {
+            Some(index) if index != state.current_file_index => {
+                state.select_file(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expand_focused_fold(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, start)) = state.focused_fold() {
+            state.expanded_folds.insert(start);
+        }
+    }
+
+    /// Scrolls the diff sideways by `columns`, left for negative values. Only
+    /// applies while lines aren't wrapped.
+    fn scroll_horizontally(&self, columns: i32) {
+        let mut state = self.state.lock().unwrap();
+        if state.wrap && !state.side_by_side {
+            return;
+        }
+        state.h_scroll = (state.h_scroll as i32 + columns).clamp(0, u16::MAX as i32) as u16;
+    }
+
+    /// Moves the border between the panes by `delta` percent.
+    fn resize_split(&self, delta: i32) {
+        let mut state = self.state.lock().unwrap();
+        let (min, max) = SPLIT_PERCENT_RANGE;
+        state.split_percent =
+            (state.split_percent as i32 + delta).clamp(min as i32, max as i32) as u16;
+        self.save_split(&mut state);
+    }
+
+    /// Remembers the current pane width in the config file for the next session.
+    fn save_split(&self, state: &mut AppState) {
+        let Some(config_file) = &self.options.config_file else {
+            return;
+        };
+        let result = config::load(config_file).and_then(|mut config| {
+            config.split_percent = Some(state.split_percent);
+            config::save(config_file, &config)
+        });
+        if let Err(e) = result {
+            state.set_error(format!("Failed to write config file: {e}"));
+        }
+    }
+
+    /// Starts dragging the pane border if `column` is on it. Returns false otherwise.
+    fn start_split_drag(&self, column: u16) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let (status_pane, diff_pane) = state.pane_areas;
+        // The status pane's right border and the diff pane's left one meet here
+        let on_border = column + 1 == status_pane.right() || column == diff_pane.x;
+        state.dragging_split = on_border && status_pane.width > 0;
+        state.dragging_split
+    }
+
+    /// Moves the pane border under the mouse while it's being dragged. Returns
+    /// false when no drag is in progress.
+    fn drag_split(&self, column: u16) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.dragging_split {
+            return false;
+        }
+        let (status_pane, diff_pane) = state.pane_areas;
+        let width = (diff_pane.right() - status_pane.x).max(1) as u32;
+        let percent = ((column.saturating_sub(status_pane.x) as u32 + 1) * 100 / width) as u16;
+        let (min, max) = SPLIT_PERCENT_RANGE;
+        state.split_percent = percent.clamp(min, max);
+        true
+    }
+
+    fn stop_split_drag(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.dragging_split {
+            state.dragging_split = false;
+            self.save_split(&mut state);
+        }
+    }
+
+    fn toggle_wrap(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.wrap = !state.wrap;
+        state.h_scroll = 0;
+        state.clamp_scroll();
+    }
+
+    fn toggle_tree_view(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.tree_view = !state.tree_view;
+        state.tree_selected_dir = None;
+    }
+
+    /// Whether the tree is shown, so the arrow keys move through it.
+    fn is_tree_view(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.tree_view && !state.compact
+    }
+
+    /// Moves the tree cursor by `rows`. Returns true if that selected another file.
+    fn move_tree_cursor(&self, rows: isize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let tree_rows = state.tree_rows();
+        if tree_rows.is_empty() {
+            return false;
+        }
+        let target = match state.tree_cursor(&tree_rows) {
+            Some(cursor) => cursor.saturating_add_signed(rows).min(tree_rows.len() - 1),
+            None => 0,
+        };
+        state.select_tree_row(&tree_rows[target])
+    }
+
+    /// Collapses the folder under the tree cursor, or moves up to the parent folder.
+    fn collapse_tree_node(&self) {
+        let mut state = self.state.lock().unwrap();
+        let rows = state.tree_rows();
+        let Some(row) = state.tree_cursor(&rows).map(|cursor| &rows[cursor]) else {
+            return;
+        };
+        if row.is_dir && !row.collapsed {
+            state.collapsed_dirs.insert(row.path.clone());
+        } else if let Some((parent, _)) = row.path.rsplit_once('/') {
+            state.tree_selected_dir = Some(parent.to_string());
+        }
+    }
+
+    /// Expands the folder under the tree cursor.
+    fn expand_tree_node(&self) {
+        let mut state = self.state.lock().unwrap();
+        let rows = state.tree_rows();
+        if let Some(row) = state.tree_cursor(&rows).map(|cursor| &rows[cursor]) {
+            if row.is_dir {
+                state.collapsed_dirs.remove(&row.path);
+            }
+        }
+    }
+
+    /// Collapses or expands the folder under the tree cursor. Returns false if the
+    /// cursor isn't on a folder.
+    fn toggle_tree_dir(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let rows = state.tree_rows();
+        let Some(row) = state.tree_cursor(&rows).map(|cursor| &rows[cursor]) else {
+            return false;
+        };
+        if !row.is_dir {
+            return false;
+        }
+        if !state.collapsed_dirs.remove(&row.path) {
+            state.collapsed_dirs.insert(row.path.clone());
+        }
+        true
+    }
+
+    fn toggle_focused_pane(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.focused_pane = match state.focused_pane {
+            // The compact layout has no status pane to scroll
+            _ if state.compact => Pane::Diff,
+            Pane::Status => Pane::Diff,
+            Pane::Diff => Pane::Status,
+        };
+    }
+
+    fn toggle_compact(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.compact = !state.compact;
+        state.focused_pane = Pane::Diff;
+    }
+
+    /// Scrolls the status pane for a scroll key while it has focus. Returns false
+    /// if the diff pane has focus or the tree, which has a cursor of its own, is
+    /// shown.
+    fn scroll_status_pane(&self, key: KeyCode) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.focused_pane != Pane::Status || state.tree_view {
+            return false;
+        }
+        let page = state.pane_areas.0.height.saturating_sub(2).max(1);
+        state.stat_scroll = match key {
+            KeyCode::Up => state.stat_scroll.saturating_sub(1),
+            KeyCode::Down => state.stat_scroll.saturating_add(1),
+            KeyCode::PageUp => state.stat_scroll.saturating_sub(page),
+            KeyCode::PageDown => state.stat_scroll.saturating_add(page),
+            KeyCode::Home => 0,
+            KeyCode::End => state.max_stat_scroll(),
+            _ => return false,
+        };
+        state.stat_scroll = state.stat_scroll.min(state.max_stat_scroll());
+        true
+    }
+
+    fn scroll_status_pane_by(&self, lines: i32) {
+        let mut state = self.state.lock().unwrap();
+        if state.tree_view {
+            return;
+        }
+        let scroll = (state.stat_scroll as i32 + lines).max(0) as u16;
+        state.stat_scroll = scroll.min(state.max_stat_scroll());
+    }
+
+    fn scroll_down_fast(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = state.scroll_position.saturating_add(5);
+        state.clamp_scroll();
+    }
+
+    fn scroll_up_fast(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = state.scroll_position.saturating_sub(5);
+    }
+
+    fn page_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        let page = state.diff_view_size.1.max(1);
+        state.scroll_position = state.scroll_position.saturating_add(page);
+        state.clamp_scroll();
+    }
+
+    fn page_up(&self) {
+        let mut state = self.state.lock().unwrap();
+        let page = state.diff_view_size.1.max(1);
+        state.scroll_position = state.scroll_position.saturating_sub(page);
+    }
+
+    fn scroll_to_top(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = 0;
+    }
+
+    fn scroll_to_bottom(&self) {
+        let mut state = self.state.lock().unwrap();
+        // max_scroll is unbounded before the first render, so leave the position alone then
+        if state.diff_view_size.1 > 0 {
+            state.scroll_position = state.max_scroll();
+        }
+    }
+
+    fn jump_to_next_hunk(&self) {
+        self.jump_forward(AppState::hunk_scroll_targets);
+    }
+
+    fn jump_to_previous_hunk(&self) {
+        self.jump_back(AppState::hunk_scroll_targets);
+    }
+
+    fn jump_to_next_conflict(&self) {
+        self.jump_forward(AppState::conflict_scroll_targets);
+    }
+
+    fn jump_to_previous_conflict(&self) {
+        self.jump_back(AppState::conflict_scroll_targets);
+    }
+
+    /// Scrolls to the first of `targets` below the current position.
+    fn jump_forward(&self, targets: fn(&AppState) -> Vec<u16>) {
+        let mut state = self.state.lock().unwrap();
+        let current = state.scroll_position;
+        if let Some(target) = targets(&state).into_iter().find(|&target| target > current) {
+            state.scroll_position = target;
+            state.clamp_scroll();
+        }
+    }
+
+    /// Scrolls to the last of `targets` above the current position.
+    fn jump_back(&self, targets: fn(&AppState) -> Vec<u16>) {
+        let mut state = self.state.lock().unwrap();
+        let current = state.scroll_position;
+        if let Some(target) = targets(&state)
+            .into_iter()
+            .rev()
+            .find(|&target| target < current)
+        {
+            state.scroll_position = target;
+        }
+    }
+
+    fn add_diff_to_history(&self, diff_content: String, file_name: String) {
+        let mut state = self.state.lock().unwrap();
+
+        // Add the new diff entry
+        let diff_entry = DiffEntry {
+            timestamp: chrono::Utc::now(),
+            diff_content,
+            file_name,
+        };
+
+        state.diff_history.push_back(diff_entry);
+
+        // Keep only the most recent entries to prevent memory issues
+        let limit = self.options.history_limit;
+        let evicted = limit > 0 && state.diff_history.len() > limit;
+        if evicted {
+            state.diff_history.pop_front();
+            // Stay on the same entry, or the oldest one left if it rotated out
+            if let Some(cursor) = state.history_cursor.as_mut() {
+                *cursor = cursor.saturating_sub(1);
+            }
+        }
+        state.recount_changes();
+
+        if let Some(history_file) = &self.options.history_file {
+            // Append the new entry, or rewrite the file once old entries rotate out
+            let result = if evicted {
+                write_history_file(history_file, &state.diff_history)
+            } else {
+                append_history_file(history_file, state.diff_history.back().unwrap())
+            };
+            if let Err(e) = result {
+                state.set_error(format!("Failed to write history file: {e}"));
+            }
+        }
+    }
+
+    fn build_accumulated_diff(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut accumulated = String::new();
+
+        // Each header names the entry below it, whichever way they're listed; the
+        // oldest entry, the state at startup, goes without one
+        let entries: Box<dyn Iterator<Item = (usize, &DiffEntry)>> = if state.history_newest_first {
+            Box::new(state.diff_history.iter().enumerate().rev())
+        } else {
+            Box::new(state.diff_history.iter().enumerate())
+        };
+        for (position, (i, entry)) in entries.enumerate() {
+            if position > 0 {
+                accumulated.push_str("\n\n");
+            }
+            if i > 0 {
+                accumulated.push_str(&format!(
+                    "=== Update {} at {} ===",
+                    i + 1,
+                    entry.timestamp.format("%H:%M:%S")
+                ));
+                accumulated.push_str(&format!(" (File: {}) ===\n", entry.file_name));
+            }
+            accumulated.push_str(&entry.diff_content);
+        }
+
+        accumulated
+    }
+
+    /// Diffs of all changed files one after another. Fails on the first file
+    /// git can't diff, rather than printing an incomplete diff.
+    async fn build_full_diff(&self) -> Result<String> {
+        let changed_files = self.state.lock().unwrap().changed_files.clone();
+        let mut full_diff = String::new();
+        for file in &changed_files {
+            match self.run_git_diff_for_file(file).await {
+                Ok(diff) => full_diff.push_str(&diff),
+                Err(e) => return Err(anyhow::anyhow!("Failed to get diff for {file}: {e}")),
+            }
+        }
+        Ok(full_diff)
+    }
+
+    fn auto_scroll_to_new_diff(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.history_newest_first {
+            // The new entry is on top
+            state.scroll_position = 0;
+            return;
+        }
+        // Everything before the last (new) entry
+        let previous = state.diff_history.len().saturating_sub(1);
+        state.scroll_position = scroll_position_for_new_diff(
+            state
+                .diff_history
+                .iter()
+                .take(previous)
+                .map(|entry| entry.diff_content.as_str()),
+        );
+    }
+
+    fn clear_diff_history(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.diff_history.clear();
+        state.recount_changes();
+        state.history_cursor = None;
+        state.git_diff =
+            "Diff history cleared.\n\nMake changes to files to see new diffs here.".to_string();
+        state.scroll_position = 0;
+    }
+
+    fn toggle_side_by_side(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.side_by_side = !state.side_by_side;
+        state.clamp_scroll();
+    }
+
+    fn toggle_line_numbers(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.show_line_numbers = !state.show_line_numbers;
+        state.clamp_scroll();
+    }
+
+    fn toggle_syntax_highlight(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.syntax_highlight = !state.syntax_highlight;
+    }
+
+    fn toggle_ignore_whitespace(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.ignore_whitespace = !state.ignore_whitespace;
+        // Cached diffs were taken with the old setting
+        state.clear_diff_cache();
+    }
+
+    /// Shows `delta` more (or fewer) lines of context around changes.
+    fn adjust_context_lines(&self, delta: isize) {
+        let mut state = self.state.lock().unwrap();
+        let context_lines = state.context_lines.saturating_add_signed(delta);
+        if context_lines == state.context_lines {
+            return;
+        }
+        state.context_lines = context_lines;
+        // Cached diffs were taken with the old setting
+        state.clear_diff_cache();
+        state.status_message = Some((format!("Context: {context_lines} lines"), Instant::now()));
+    }
+
+    fn toggle_detect_renames(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.detect_renames = !state.detect_renames;
+        // Cached diffs were taken with the old setting
+        state.clear_diff_cache();
+    }
+
+    /// Re-runs `git diff --stat` for the status pane without touching the selection.
+    async fn refresh_git_stat(&self) {
+        match self.run_git_stats().await {
+            Ok((git_stat, file_stats)) => {
+                let mut state = self.state.lock().unwrap();
+                state.set_git_stat(git_stat, file_stats);
+            }
+            Err(e) => self.report_git_error(format!("Git stat error: {e}")),
+        }
+    }
+
+    /// Shows a failed git command's error. A repository directory that's gone
+    /// won't fix itself, so that is reported as a persistent error instead.
+    fn report_git_error(&self, message: String) {
+        let mut state = self.state.lock().unwrap();
+        match self.repos.iter().find(|repo| !repo.directory.is_dir()) {
+            Some(repo) => {
+                state.persistent_error = Some(format!(
+                    "Repository {} no longer exists",
+                    repo.directory.display()
+                ));
+            }
+            None => {
+                // Without the interface there's no popup to show it in
+                if self.options.watch_only {
+                    eprintln!("{message}");
+                }
+                state.set_error(message);
+            }
+        }
+    }
+
+    /// Closes the error popup. Returns false if there was none, or only a
+    /// persistent error that can't be dismissed.
+    fn dismiss_error(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.persistent_error.is_some()
+            || state.visible_error(self.options.error_timeout).is_none()
+        {
+            return false;
+        }
+        state.error_message = None;
+        true
+    }
+
+    /// Lifts --max-diff-lines for the current file. Returns false if its diff
+    /// wasn't truncated.
+    fn load_full_diff(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(file) = state.changed_files.get(state.current_file_index).cloned() else {
+            return false;
+        };
+        if !state
+            .git_diff
+            .lines()
+            .any(|line| line.starts_with(TRUNCATION_MARKER))
+        {
+            return false;
+        }
+        state.forget_cached_diff(&file);
+        state.full_diffs.insert(file);
+        true
+    }
+
+    /// Shows the whole current file with its changes in place, or just the hunks
+    /// again. Long files are still cut off by --max-diff-lines.
+    fn toggle_whole_file(&self) {
+        let Some(file) = ({
+            let state = self.state.lock().unwrap();
+            state.changed_files.get(state.current_file_index).cloned()
+        }) else {
+            return;
+        };
+        let (repo, path) = self.resolve_file(&file);
+        let lines = fs::read(repo.directory.join(path))
+            .map(|content| content.iter().filter(|&&byte| byte == b'\n').count())
+            .unwrap_or(0);
+
+        let mut state = self.state.lock().unwrap();
+        state.forget_cached_diff(&file);
+        state.scroll_position = 0;
+        let message = if state.whole_files.remove(&file) {
+            "Showing changed hunks".to_string()
+        } else {
+            state.whole_files.insert(file.clone());
+            if lines > WHOLE_FILE_WARN_LINES {
+                format!("Showing the whole file; {file} has {lines} lines and may be slow")
+            } else {
+                "Showing the whole file".to_string()
+            }
+        };
+        state.status_message = Some((message, Instant::now()));
+    }
+
+    fn toggle_whitespace_errors(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.show_whitespace = !state.show_whitespace;
+    }
+
+    fn toggle_word_diff(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.word_diff = !state.word_diff;
+    }
+
+    fn cycle_diff_scope(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.diff_scope = state.diff_scope.next();
+        // Cached diffs were taken for the old scope
+        state.clear_diff_cache();
+        let message = format!("Showing {} changes", state.diff_scope.label());
+        state.status_message = Some((message, Instant::now()));
+    }
+
+    fn cycle_sort_mode(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.sort_mode = state.sort_mode.next();
+        state.sort_changed_files();
+        let message = format!("Sorting files by {}", state.sort_mode.label());
+        state.status_message = Some((message, Instant::now()));
+    }
+
+    /// Cycles the status pane through all, staged and unstaged files.
+    fn cycle_status_filter(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.status_filter = state.status_filter.next();
+        state.refilter_changed_files();
+        if state.changed_files.is_empty() {
+            state.git_diff = format!(
+                "No {} files.\n\nPress 'S' to show other files.",
+                state.status_filter.label()
+            );
+        }
+        let message = format!("Showing {} files", state.status_filter.label());
+        state.status_message = Some((message, Instant::now()));
+    }
+
+    fn open_prompt(&self, kind: PromptKind, input: String) {
+        self.state.lock().unwrap().prompt = Some(Prompt {
+            kind,
+            input,
+            selected: 0,
+        });
+    }
+
+    fn open_filter_prompt(&self) {
+        let current = self
+            .state
+            .lock()
+            .unwrap()
+            .file_filter
+            .as_ref()
+            .map(|filter| filter.glob().to_string())
+            .unwrap_or_default();
+        self.open_prompt(PromptKind::Filter, current);
+    }
+
+    fn open_export_prompt(&self) {
+        let path = self
+            .options
+            .export_on_quit
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_EXPORT_FILE.to_string());
+        self.open_prompt(PromptKind::Export, path);
+    }
+
+    fn open_jump_prompt(&self) {
+        self.open_prompt(PromptKind::Jump, String::new());
+    }
+
+    fn is_prompt_open(&self) -> bool {
+        self.state.lock().unwrap().prompt.is_some()
+    }
+
+    fn edit_prompt_input(&self, key: KeyCode) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if let Some(prompt) = state.prompt.as_mut() {
+            match key {
+                KeyCode::Char(c) => {
+                    prompt.input.push(c);
+                    prompt.selected = 0;
+                }
+                KeyCode::Backspace => {
+                    prompt.input.pop();
+                    prompt.selected = 0;
+                }
+                KeyCode::Up => prompt.selected = prompt.selected.saturating_sub(1),
+                KeyCode::Down => prompt.selected += 1,
+                _ => {}
+            }
+            if prompt.kind == PromptKind::Jump {
+                let matches = prompt.jump_matches(&state.changed_files).len();
+                prompt.selected = prompt.selected.min(matches.saturating_sub(1));
+            }
+        }
+    }
+
+    /// Adds pasted text to the open prompt, up to the first line break. A paste
+    /// with no prompt open is dropped rather than taken as key presses.
+    fn paste_into_prompt(&self, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(prompt) = state.prompt.as_mut() {
+            let line = text.lines().next().unwrap_or_default();
+            prompt
+                .input
+                .extend(line.chars().filter(|c| !c.is_control()));
+            prompt.selected = 0;
+        }
+    }
+
+    fn cancel_prompt(&self) {
+        self.state.lock().unwrap().prompt = None;
+    }
+
+    /// Closes the prompt and acts on what was entered. Returns true if the current
+    /// file's diff needs reloading.
+    fn submit_prompt(&self) -> bool {
+        let Some(prompt) = self.state.lock().unwrap().prompt.take() else {
+            return false;
+        };
+        let input = prompt.query();
+        match prompt.kind {
+            PromptKind::Filter => self.apply_filter_input(input),
+            PromptKind::Export => {
+                if !input.is_empty() {
+                    self.export_history(Path::new(input));
+                }
+                false
+            }
+            PromptKind::Jump => {
+                let mut state = self.state.lock().unwrap();
+                let matches = prompt.jump_matches(&state.changed_files);
+                match matches.get(prompt.selected) {
+                    Some(&index) if index != state.current_file_index => {
+                        state.select_file(index);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    fn show_status_message(&self, message: String) {
+        self.state.lock().unwrap().status_message = Some((message, Instant::now()));
+    }
+
+    /// Shows `message` in the error popup, or on stderr with --watch-only.
+    fn show_error(&self, message: String) {
+        if self.options.watch_only {
+            eprintln!("{message}");
+        }
+        self.state.lock().unwrap().set_error(message);
+    }
+
+    /// Writes the diff history to `path`, reporting the outcome in the UI.
+    fn export_history(&self, path: &Path) {
+        let entries = Vec::from(self.state.lock().unwrap().diff_history.clone());
+        match export::export_history(path, &entries) {
+            Ok(()) => self.show_status_message(format!(
+                "Exported {} entries to {}",
+                entries.len(),
+                path.display()
+            )),
+            Err(e) => {
+                self.state.lock().unwrap().set_error(format!(
+                    "Failed to export history to {}: {e}",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    /// Sets the file filter from the prompt's input, or clears it when the input is
+    /// empty. Returns true if the visible file list changed.
+    fn apply_filter_input(&self, input: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let filter = if input.is_empty() {
+            None
+        } else {
+            match Glob::new(input) {
+                Ok(glob) => Some(glob.compile_matcher()),
+                Err(e) => {
+                    state.set_error(format!("Invalid filter: {e}"));
+                    return false;
+                }
+            }
+        };
+        state.file_filter = filter;
+        state.refilter_changed_files();
+        if state.changed_files.is_empty() {
+            state.git_diff =
+                "No changed files match the filter.\n\nPress 'f' to change or clear it."
+                    .to_string();
+        }
+        true
+    }
+
+    /// Opens the quit confirmation if `--confirm-quit` is on and quitting would
+    /// lose history that isn't persisted. Returns false if it's fine to quit now.
+    fn request_quit_confirmation(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.confirming_quit = self.options.confirm_quit
+            && self.options.history_file.is_none()
+            && !state.diff_history.is_empty();
+        state.confirming_quit
+    }
+
+    /// File and new-side line range of the hunk at or above the top of the diff
+    /// pane, or a message explaining why there's nothing to blame.
+    fn blame_target(&self) -> std::result::Result<(String, usize, usize), String> {
+        if let DiffMode::Snapshot(_) = &self.options.mode {
+            return Err("Blame needs a git repository".to_string());
+        }
+        let state = self.state.lock().unwrap();
+        if state.show_history || state.history_cursor.is_some() {
+            return Err("Blame isn't available in the history view".to_string());
+        }
+        let Some(file) = state.changed_files.get(state.current_file_index) else {
+            return Err("No file to blame".to_string());
+        };
+        if state.file_status.get(file) == Some(&FileStatus::Untracked) {
+            return Err(format!("{file} is untracked, so there's nothing to blame"));
+        }
+        if state
+            .git_diff
+            .lines()
+            .any(|line| line.starts_with("Binary file"))
+        {
+            return Err(format!("{file} is binary, so there's nothing to blame"));
+        }
+
+        let diff = state.display_diff();
+        let Some((_, _, start, len)) = state.current_hunk_index().and_then(|index| {
+            let header = diff
+                .lines()
+                .filter(|line| line.starts_with("@@"))
+                .nth(index)?;
+            parse_hunk_header(header)
+        }) else {
+            return Err(format!("No hunk to blame in {file}"));
+        };
+        // A hunk that only deletes lines has no new lines; blame where they were
+        let start = start.max(1);
+        Ok((file.clone(), start, start + len.saturating_sub(1)))
+    }
+
+    /// Copies the current file's path, relative to its repository, to the clipboard.
+    async fn copy_file_path(&self) {
+        let Some(file) = ({
+            let state = self.state.lock().unwrap();
+            state.changed_files.get(state.current_file_index).cloned()
+        }) else {
+            return;
+        };
+        let (_, path) = self.resolve_file(&file);
+        let message = match copy_to_clipboard(path).await {
+            Ok(()) => format!("Copied {path}"),
+            Err(e) => format!("Couldn't copy: {e}"),
+        };
+        self.show_status_message(message);
+    }
+
+    /// Copies the hunk at the top of the diff pane to the clipboard.
+    async fn copy_current_hunk(&self) {
+        let hunk = {
+            let state = self.state.lock().unwrap();
+            if state.changed_files.is_empty() {
+                return;
+            }
+            // Folds are only shown, so the hunk comes from the diff they hide lines of
+            let diff = match state.timeline_entry() {
+                Some((_, entry)) => &entry.diff_content,
+                None => &state.git_diff,
+            };
+            state
+                .current_hunk_index()
+                .and_then(|index| hunk(diff, index))
+        };
+        let Some(hunk) = hunk else {
+            self.show_status_message("No hunk to copy".to_string());
+            return;
+        };
+        let header = hunk.lines().next().unwrap_or_default();
+        let message = match copy_to_clipboard(&hunk).await {
+            Ok(()) => format!("Copied {header} ({} lines)", hunk.lines().count()),
+            Err(e) => format!("Couldn't copy: {e}"),
+        };
+        self.show_status_message(message);
+    }
+
+    /// Runs `git blame` for the current hunk and opens the popup with the result.
+    async fn show_blame(&self) {
+        let (file, start, end) = match self.blame_target() {
+            Ok(target) => target,
+            Err(message) => {
+                self.show_status_message(message);
+                return;
+            }
+        };
+        let title = format!("Blame: {file} lines {start}-{end}");
+        self.state.lock().unwrap().blame = Some(BlamePopup {
+            title: title.clone(),
+            lines: vec!["Loading blame...".to_string()],
+            scroll: 0,
+        });
+
+        let (repo, path) = self.resolve_file(&file);
+        let range = format!("{start},{end}");
+        let lines = match self
+            .git(
+                &repo.directory,
+                &["blame", "--porcelain", "-L", &range, "--", path],
+            )
+            .await
+        {
+            Ok(output) if output.status.success() => {
+                let blamed = blame::parse_porcelain(&String::from_utf8_lossy(&output.stdout));
+                let author_width = blamed
+                    .iter()
+                    .map(|line| line.author.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                blamed
+                    .iter()
+                    .map(|line| line.format(author_width))
+                    .collect()
+            }
+            Ok(output) => vec![format!(
+                "Git blame failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )],
+            Err(e) => vec![format!("Git blame failed: {e}")],
+        };
+
+        // Leave it closed if it was dismissed while loading
+        let mut state = self.state.lock().unwrap();
+        if let Some(blame) = state.blame.as_mut() {
+            blame.lines = lines;
+        }
+    }
+
+    fn is_blame_open(&self) -> bool {
+        self.state.lock().unwrap().blame.is_some()
+    }
+
+    fn close_blame(&self) {
+        self.state.lock().unwrap().blame = None;
+    }
+
+    /// Scrolls the blame popup by `lines`, up for negative values.
+    fn scroll_blame(&self, lines: i32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(blame) = state.blame.as_mut() {
+            let last = blame.lines.len().saturating_sub(1) as i32;
+            blame.scroll = (blame.scroll as i32 + lines).clamp(0, last.min(u16::MAX as i32)) as u16;
+        }
+    }
+
+    fn is_quit_confirmation_open(&self) -> bool {
+        self.state.lock().unwrap().confirming_quit
+    }
+
+    fn cancel_quit(&self) {
+        self.state.lock().unwrap().confirming_quit = false;
+    }
+
+    fn toggle_history_view(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.show_history = !state.show_history;
+        state.show_dashboard = false;
+        state.history_cursor = None;
+        state.scroll_position = 0;
+    }
+
+    /// Flips the order of the history view between oldest and newest first,
+    /// showing the newest entry either way.
+    fn toggle_history_order(&self) {
+        let newest_first = {
+            let mut state = self.state.lock().unwrap();
+            state.history_newest_first = !state.history_newest_first;
+            state.history_newest_first
+        };
+        if newest_first {
+            self.state.lock().unwrap().scroll_position = 0;
+        } else {
+            self.auto_scroll_to_new_diff();
+        }
+    }
+
+    /// Cycles the diff pane through the current file, the history and the dashboard.
+    fn cycle_view(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.history_cursor = None;
+        if state.show_dashboard {
+            state.show_dashboard = false;
+            state.show_history = false;
+        } else if state.show_history {
+            state.show_dashboard = true;
+        } else {
+            state.show_history = true;
+        }
+        state.scroll_position = 0;
+    }
+
+    /// Opens the timeline on the newest history entry, or closes it. The timeline
+    /// takes over from the history view and the dashboard.
+    fn toggle_timeline(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.replay = None;
+        if state.history_cursor.take().is_some() {
+            state.scroll_position = 0;
+            return;
+        }
+        if state.diff_history.is_empty() {
+            state.status_message = Some(("No diff history yet".to_string(), Instant::now()));
+            return;
+        }
+        state.history_cursor = Some(state.diff_history.len() - 1);
+        state.show_history = false;
+        state.show_dashboard = false;
+        state.scroll_position = 0;
+    }
+
+    /// Moves the timeline cursor by `delta` entries. Returns false if the timeline
+    /// isn't open.
+    fn move_timeline(&self, delta: isize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(cursor) = state.history_cursor else {
+            return false;
+        };
+        let last = state.diff_history.len().saturating_sub(1);
+        let moved = cursor.saturating_add_signed(delta).min(last);
+        if moved != cursor {
+            state.history_cursor = Some(moved);
+            state.scroll_position = 0;
+        }
+        true
+    }
+
+    /// Closes the timeline, stopping any replay. Returns false if it wasn't open.
+    fn close_timeline(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.replay = None;
+        state.scroll_position = 0;
+        state.history_cursor.take().is_some()
+    }
+
+    /// Replays the diff history on the timeline from the oldest entry, or stops
+    /// the replay that's running.
+    fn toggle_replay(&self) {
+        let started = Instant::now();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.replay.take().is_some() {
+                state.status_message = Some(("Replay stopped".to_string(), started));
+                return;
+            }
+            if state.diff_history.is_empty() {
+                state.status_message = Some(("No diff history yet".to_string(), started));
+                return;
+            }
+            state.history_cursor = Some(0);
+            state.replay = Some(Replay {
+                started,
+                paused: false,
+            });
+            state.show_history = false;
+            state.show_dashboard = false;
+            state.scroll_position = 0;
+        }
+        tokio::spawn(self.clone().run_replay(started));
+    }
+
+    /// Pauses or resumes the replay. Returns false if there's none running.
+    fn toggle_replay_pause(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.history_cursor.is_none() {
+            return false;
+        }
+        match state.replay.as_mut() {
+            Some(replay) => {
+                replay.paused = !replay.paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Advances the timeline of the replay started at `started` until it reaches
+    /// the newest entry, or forever with --replay-loop. Ends early once the replay
+    /// is stopped, replaced or the timeline closed.
+    async fn run_replay(self, started: Instant) {
+        let interval = self.options.replay_interval.max(MIN_REPLAY_INTERVAL);
+        loop {
+            tokio::time::sleep(interval).await;
+            let mut state = self.state.lock().unwrap();
+            let Some(replay) = state.replay.filter(|replay| replay.started == started) else {
+                return;
+            };
+            let Some(cursor) = state.history_cursor else {
+                state.replay = None;
+                return;
+            };
+            if replay.paused {
+                continue;
+            }
+            let next = if cursor + 1 < state.diff_history.len() {
+                cursor + 1
+            } else if self.options.replay_loop {
+                0
+            } else {
+                state.replay = None;
+                state.status_message = Some(("Replay finished".to_string(), Instant::now()));
+                return;
+            };
+            state.history_cursor = Some(next);
+            state.scroll_position = 0;
+        }
+    }
+
+    async fn refresh_display(&self) {
+        let show_history = {
+            let state = self.state.lock().unwrap();
+            state.show_history
+        };
+
+        if show_history {
+            // Show accumulated history
+            let accumulated_diff = self.build_accumulated_diff();
+            let mut state = self.state.lock().unwrap();
+            state.git_diff = if accumulated_diff.is_empty() {
+                "No diff history available.\n\nMake changes to files to see diffs here.\n\nPress 'h' to toggle back to current file view.".to_string()
+            } else {
+                accumulated_diff
+            };
+        } else {
+            // Show current file diff only
+            self.update_current_file_diff().await;
+        }
+    }
+
+    async fn update_current_file_diff(&self) {
+        self.update_current_file_diff_internal(false).await;
+    }
+
+    async fn update_current_file_diff_with_history(&self) {
+        self.update_current_file_diff_internal(true).await;
+    }
+
+    async fn update_current_file_diff_internal(&self, store_in_history: bool) {
+        let current_file = {
+            let state = self.state.lock().unwrap();
+            if state.changed_files.is_empty() {
+                return;
+            }
+            state.changed_files[state.current_file_index].clone()
+        };
+
+        // Reuse the cached diff if the file hasn't been modified since it was taken
+        let (modified, cached_diff) = {
+            let state = self.state.lock().unwrap();
+            let modified = state
+                .file_info
+                .get(&current_file)
+                .map(|info| info.last_modified);
+            let cached_diff = state
+                .diff_cache
+                .get(&current_file)
+                .filter(|(cached_modified, _)| Some(*cached_modified) == modified)
+                .map(|(_, diff)| diff.clone());
+            (modified, cached_diff)
+        };
+
+        let git_diff = if let Some(cached_diff) = cached_diff {
+            cached_diff
+        } else {
+            // Spin in the pane title until git answers, keeping the old diff on screen
+            self.state.lock().unwrap().loading_since = Some(Instant::now());
+            let result = self.run_git_diff_for_file(&current_file).await;
+            self.state.lock().unwrap().loading_since = None;
+
+            match result {
+                Ok(output) => {
+                    let git_diff = diff_or_no_changes(&current_file, output);
+                    if let Some(modified) = modified {
+                        let mut state = self.state.lock().unwrap();
+                        state
+                            .diff_cache
+                            .insert(current_file.clone(), (modified, git_diff.clone()));
+                    }
+                    git_diff
+                }
+                Err(e) => {
+                    format!("Error getting diff for {current_file}: {e}\n\nTry refreshing with 'r' or check if the file still exists.")
+                }
+            }
+        };
+
+        let split_markers = self.split_markers(&current_file, &git_diff).await;
+        let external_diff = self.run_diff_command(&git_diff).await;
+
+        if store_in_history {
+            // Find the previous diff for this file to compare against
+            let previous_diff = {
+                let state = self.state.lock().unwrap();
+                state
+                    .diff_history
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.file_name == current_file)
+                    .map(|entry| entry.diff_content.clone())
+            };
+
+            // Calculate scroll position based on what's actually new
+            let scroll_position = if let Some(ref prev_diff) = previous_diff {
+                // Find the first line that's different from the previous diff
+                find_first_diff_line(&git_diff, prev_diff)
+            } else {
+                // No previous diff, use smart scrolling to find first addition
+                smart_scroll_position(&git_diff)
+            };
+
+            // Store the diff in history
+            self.add_diff_to_history(git_diff.clone(), current_file.clone());
+
+            // Check if we should show history or current file
+            let show_history = {
+                let state = self.state.lock().unwrap();
+                state.show_history
+            };
+
+            if show_history {
+                // Build and set the accumulated diff with auto-scroll for history view
+                self.auto_scroll_to_new_diff();
+                let accumulated_diff = self.build_accumulated_diff();
+                {
+                    let mut state = self.state.lock().unwrap();
+                    state.git_diff = accumulated_diff;
+                }
+            } else {
+                // Show current file diff and scroll to show the first different line
+                {
+                    let mut state = self.state.lock().unwrap();
+                    state.git_diff = git_diff.clone();
+                    state.split_markers = split_markers;
+                    state.external_diff = external_diff;
+                    // Use the calculated scroll position to show the first different line,
+                    // which moves up by however much context above it is folded. The
+                    // timeline keeps its place.
+                    if state.history_cursor.is_none() {
+                        state.scroll_position = state
+                            .display_line(scroll_position as usize)
+                            .min(u16::MAX as usize)
+                            as u16;
+                    }
+                }
+            }
+        } else {
+            // Just show the current diff without storing in history
+            {
+                let mut state = self.state.lock().unwrap();
+                state.git_diff = git_diff;
+                state.split_markers = split_markers;
+                state.external_diff = external_diff;
+            }
+        }
+    }
+
+    /// Renders `diff` with --diff-command, keeping the diff alongside the output.
+    /// `None` without a command, for messages that aren't diffs, or when the command
+    /// fails, which falls back to the built-in coloring.
+    async fn run_diff_command(&self, diff: &str) -> Option<(String, String)> {
+        let command = self.options.diff_command.as_ref()?;
+        if !diff.lines().any(|line| line.starts_with("@@")) {
+            return None;
+        }
+        let width = self.state.lock().unwrap().diff_view_size.0;
+        match run_diff_command(command, diff, width).await {
+            Ok(colored) => Some((diff.to_string(), colored)),
+            Err(e) => {
+                self.show_status_message(format!("--diff-command failed: {e}"));
+                None
+            }
+        }
+    }
+
+    /// Fetches the diffs of all changed files in the background, at most
+    /// `PREFETCH_CONCURRENCY` at a time, so moving between files doesn't wait
+    /// for git. Diffs are only cached if nothing invalidated the cache while
+    /// they were taken.
+    fn spawn_diff_prefetch(&self) {
+        let (files, generation) = {
+            let state = self.state.lock().unwrap();
+            let files: Vec<(String, SystemTime)> = state
+                .changed_files
+                .iter()
+                .filter_map(|file| {
+                    let modified = state.file_info.get(file)?.last_modified;
+                    let cached = state
+                        .diff_cache
+                        .get(file)
+                        .is_some_and(|(cached_modified, _)| *cached_modified == modified);
+                    (!cached).then(|| (file.clone(), modified))
+                })
+                .collect();
+            (files, state.diff_cache_generation)
+        };
+        let permits = Arc::new(Semaphore::new(PREFETCH_CONCURRENCY));
+        for (file, modified) in files {
+            let handle = self.clone();
+            let permits = permits.clone();
+            tokio::spawn(async move {
+                let Ok(_permit) = permits.acquire().await else {
+                    return;
+                };
+                let is_current = |state: &AppState| {
+                    state.diff_cache_generation == generation
+                        && !state.diff_cache.contains_key(&file)
+                        && state.file_info.get(&file).map(|info| info.last_modified)
+                            == Some(modified)
+                };
+                // A reload or a change since the prefetch started makes it moot
+                if !is_current(&handle.state.lock().unwrap()) {
+                    return;
+                }
+                let Ok(output) = handle.run_git_diff_for_file(&file).await else {
+                    return;
+                };
+                let mut state = handle.state.lock().unwrap();
+                if is_current(&state) {
+                    let git_diff = diff_or_no_changes(&file, output);
+                    state.diff_cache.insert(file, (modified, git_diff));
+                }
+            });
+        }
+    }
+
+    /// Loads everything on startup, showing a loading message meanwhile.
+    async fn load_initial_state(&self) -> Result<()> {
+        // Set initial loading state
+        {
+            let mut state = self.state.lock().unwrap();
+            state.git_stat = "WatchHound starting up...\nLoading git status...".to_string();
+            state.git_diff =
+                "Initializing git repository scan...\n\nChecking for changes...".to_string();
+
+            // Restore the previous session's history on first load
+            if state.diff_history.is_empty() {
+                if let Some(history_file) = &self.options.history_file {
+                    match read_history_file(history_file, self.options.history_limit) {
+                        Ok(entries) => {
+                            state.diff_history = entries;
+                            state.recount_changes();
+                        }
+                        Err(e) => state.set_error(format!("Failed to read history file: {e}")),
+                    }
+                }
+            }
+        }
+
+        // Brief delay to show loading state
+        sleep(startup_delay(&self.options)).await;
+
+        self.reload().await
+    }
+
+    /// Refetches the stat, the changed files and the current diff, keeping what's
+    /// on screen until they're in. For refreshes, commits, checkouts and other
+    /// changes that can affect every file.
+    async fn reload(&self) -> Result<()> {
+        // A full reload shouldn't trust diffs cached before it
+        self.state.lock().unwrap().clear_diff_cache();
+
+        // Get git diff --stat
+        let (mut git_stat, file_stats) = match self.run_git_stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                return Err(anyhow::anyhow!("Failed to get git status: {}", e));
+            }
+        };
+        if git_stat.trim().is_empty() {
+            git_stat = "No changes detected in the repository.\n\nMake some changes to files to see diffs here!\n\nTip: Edit a file and the changes will appear automatically.".to_string();
+        }
+
+        // Get all changed files
+        let changed_files = match self.get_changed_files().await {
+            Ok(files) => files,
+            Err(e) => {
+                return Err(anyhow::anyhow!("Failed to get changed files: {}", e));
+            }
+        };
+
+        let head_info = self.run_git_head_info().await.ok().flatten();
+
+        // Update state with initial data
+        {
+            let mut state = self.state.lock().unwrap();
+            state.set_git_stat(git_stat, file_stats);
+            state.head_info = head_info;
+            // Stay on the file that was selected before the refresh
+            if state.set_changed_files(changed_files) {
+                state.scroll_position = 0;
+            }
+            state.last_update = Some(chrono::Utc::now());
+            state.error_message = None;
+            state.persistent_error = None;
+        }
+
+        // Get diff for first file if available
+        let has_files = {
+            let state = self.state.lock().unwrap();
+            !state.changed_files.is_empty()
+        };
+
+        if has_files {
+            self.update_current_file_diff_with_history().await;
+            // --once diffs every file itself and exits before a prefetch would help
+            if !self.options.once {
+                self.spawn_diff_prefetch();
+            }
+        } else {
+            // No files to show diff for
+            let no_changes_message = "No changes to display.\n\nTo see colorized diffs:\n1. Make changes to files\n2. Use 'r' to refresh\n3. Use Left/Right to navigate files\n4. Use Space to scroll\n5. Use 'h' to toggle history view\n6. Use 'c' to clear diff history\n\nRecently changed files will be highlighted!\nDiff history is automatically stored and scrolls to new changes.".to_string();
+
+            // Store the initial message in history
+            self.add_diff_to_history(no_changes_message.clone(), "Initial State".to_string());
+
+            let mut state = self.state.lock().unwrap();
+            state.git_diff = no_changes_message;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_file_change(&self, path: &Path) -> Result<()> {
+        // Wait for the file to settle; if it changes again meanwhile, leave the
+        // refresh to the newer change
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.change_generation += 1;
+            let generation = state.change_generation;
+            state.pending_changes.insert(path.to_path_buf(), generation);
+            generation
+        };
+        if !self.options.change_delay.is_zero() {
+            sleep(self.options.change_delay).await;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.pending_changes.get(path) != Some(&generation) {
+                return Ok(());
+            }
+            state.pending_changes.remove(path);
+        }
+
+        // Clear error message and drop the stale cached diff for the changed file
+        {
+            let mut state = self.state.lock().unwrap();
+            state.error_message = None;
+            match self.file_key_for_path(path) {
+                Some(file) => {
+                    state.forget_cached_diff(&file);
+                }
+                None => state.clear_diff_cache(),
+            }
+        }
+
+        // Run git diff --stat
+        let (git_stat, file_stats) = match self.run_git_stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                self.report_git_error(format!("Git stat error: {e}"));
+                return Ok(());
+            }
+        };
+
+        // Get all changed files
+        let changed_files = match self.get_changed_files().await {
+            Ok(files) => files,
+            Err(e) => {
+                self.report_git_error(format!("Error finding changed files: {e}"));
+                return Ok(());
+            }
+        };
+
+        // A commit or checkout also shows up as a change under the watched directory
+        let head_info = self.run_git_head_info().await.ok().flatten();
+
+        // Find the changed file to display it
+        let changed_file = self
+            .file_key_for_path(path)
+            .filter(|file| changed_files.contains(file));
+
+        // A write that left a file without changes, like an editor saving it
+        // untouched, gets a hint so the missing refresh doesn't look like a bug.
+        // A file that had changes before was reverted instead.
+        let touched_file = self.file_key_for_path(path).filter(|file| {
+            path.is_file()
+                && !changed_files.contains(file)
+                && !self.state.lock().unwrap().all_changed_files.contains(file)
+        });
+        if let Some(file) = touched_file {
+            self.show_status_message(format!("{file} touched (no content change)"));
+        }
+
+        // Update state with new files list
+        let pinned_file = {
+            let mut state = self.state.lock().unwrap();
+            state.set_git_stat(git_stat, file_stats);
+            state.head_info = head_info;
+
+            // A pinned file that no longer has changes can't stay pinned
+            if state
+                .pinned_file
+                .as_ref()
+                .is_some_and(|pinned| !changed_files.contains(pinned))
+            {
+                state.pinned_file = None;
+            }
+
+            // Stay on the pinned file, otherwise follow the change; if the changed
+            // file isn't in the list, stay on the previously selected one
+            state.set_changed_files(changed_files);
+            let followed = state.pinned_file.clone().or_else(|| changed_file.clone());
+            if let Some(index) =
+                followed.and_then(|file| state.changed_files.iter().position(|f| *f == file))
+            {
+                state.current_file_index = index;
+            }
+            // Don't reset scroll position here - let auto-scroll handle it
+
+            state.last_update = Some(Utc::now());
+            state.persistent_error = None;
+            state.pinned_file.clone()
+        };
+
+        if self.options.notify {
+            if let Some(file) = &changed_file {
+                self.notify_file_change(file);
+            }
+        }
+
+        if self.options.watch_only {
+            self.log_change(path, changed_file.as_deref());
+        }
+
+        if let (Some(events), Some(file)) = (&self.options.events, &changed_file) {
+            let (insertions, deletions) = self
+                .state
+                .lock()
+                .unwrap()
+                .file_stats
+                .get(file)
+                .copied()
+                .unwrap_or_default();
+            // Sending only fails when no client is connected
+            let _ = events.send(ChangeEvent {
+                timestamp: Utc::now(),
+                file: file.clone(),
+                insertions,
+                deletions,
+            });
+        }
 
-        // Add the new diff entry
-        let diff_entry = DiffEntry {
-            timestamp: chrono::Utc::now(),
-            diff_content,
-            file_name,
+        // Get diff for current file - store in history since this is a real file change
+        let has_changes = !self.state.lock().unwrap().changed_files.is_empty();
+        if let Some(pinned_file) = pinned_file {
+            self.record_change_while_pinned(&pinned_file, changed_file.as_deref())
+                .await;
+        } else if has_changes {
+            self.update_current_file_diff_with_history().await;
+        }
+
+        if self.options.autocommit && has_changes {
+            if let Err(e) = self.autocommit().await {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .set_error(format!("Auto-commit failed: {e}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// --watch-only: prints a line for a change to `path`, with the changed
+    /// file's counts and the totals of the whole stat, e.g.
+    /// `2024-05-01 12:00:00 src/main.rs +3 -1 (2 files changed, +5 -1)`.
+    fn log_change(&self, path: &Path, changed_file: Option<&str>) {
+        let state = self.state.lock().unwrap();
+        let file = match changed_file {
+            Some(file) => {
+                let (insertions, deletions) =
+                    state.file_stats.get(file).copied().unwrap_or_default();
+                format!("{file} +{insertions} -{deletions}")
+            }
+            // Changes that were undone leave the file out of the stat
+            None => match self.file_key_for_path(path) {
+                Some(file) => format!("{file} unchanged"),
+                None => path.display().to_string(),
+            },
+        };
+        let summary = match state.stat_totals {
+            Some((files, insertions, deletions)) => {
+                let plural = if files == 1 { "" } else { "s" };
+                format!("{files} file{plural} changed, +{insertions} -{deletions}")
+            }
+            None => "no changes".to_string(),
         };
+        println!(
+            "{} {file} ({summary})",
+            Utc::now().format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    /// --autocommit: stages everything in each repository and commits it as
+    /// "wip: <timestamp>", skipping repositories with nothing to commit.
+    async fn autocommit(&self) -> Result<()> {
+        for repo in self.repos.iter() {
+            let mut add_args = vec!["add", "-A"];
+            add_args.extend(repo.pathspec());
+            let output = self.git(&repo.directory, &add_args).await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "git add failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+
+            // Exits with 0 when nothing is staged, which would make the commit fail
+            let staged = self
+                .git(&repo.directory, &["diff", "--cached", "--quiet"])
+                .await?;
+            if staged.status.success() {
+                continue;
+            }
 
-        state.diff_history.push(diff_entry);
+            let message = format!("wip: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+            let output = self
+                .git(&repo.directory, &["commit", "-q", "-m", &message])
+                .await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "git commit failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
 
-        // Keep only the last 50 diff entries to prevent memory issues
-        if state.diff_history.len() > 50 {
-            state.diff_history.remove(0);
+            let sha = self
+                .git(&repo.directory, &["rev-parse", "--short", "HEAD"])
+                .await?;
+            let sha = String::from_utf8_lossy(&sha.stdout).trim().to_string();
+            let label = if self.repos.len() > 1 {
+                format!("{}: ", repo.label)
+            } else {
+                String::new()
+            };
+            let summary = format!("{label}Auto-committed {sha} \"{message}\"");
+
+            // Keep the commit in the history too, where it outlasts the status message
+            let stat = self
+                .git(&repo.directory, &["show", "--stat", "--format=", "HEAD"])
+                .await?;
+            self.add_diff_to_history(
+                format!("{summary}\n\n{}", String::from_utf8_lossy(&stat.stdout)),
+                format!("{AUTOCOMMIT_ENTRY} {label}{sha}"),
+            );
+            self.show_status_message(summary);
         }
+        Ok(())
     }
 
-    fn find_first_diff_line(&self, current_diff: &str, previous_diff: &str) -> u16 {
-        let current_lines: Vec<&str> = current_diff.lines().collect();
-        let previous_lines: Vec<&str> = previous_diff.lines().collect();
+    /// Stages (`git add`) or unstages (`git reset`) the current file, then reloads
+    /// the file list so its new status shows. A renamed file takes its old path along.
+    async fn stage_current_file(&self, stage: bool) -> Result<()> {
+        if let DiffMode::Snapshot(_) = &self.options.mode {
+            self.show_status_message("Staging needs git".to_string());
+            return Ok(());
+        }
 
-        // Find the first line that's different between current and previous diff
-        let mut first_different_line = None;
-        for (i, current_line) in current_lines.iter().enumerate() {
-            if i >= previous_lines.len() || current_line != &previous_lines[i] {
-                first_different_line = Some(i);
-                break;
+        let (file, old_path, status, unborn) = {
+            let state = self.state.lock().unwrap();
+            let Some(file) = state.changed_files.get(state.current_file_index).cloned() else {
+                return Ok(());
+            };
+            let (repo, _) = self.resolve_file(&file);
+            (
+                file.clone(),
+                state.renames.get(&file).cloned(),
+                state.file_status.get(&file).copied(),
+                state.unborn_repos.contains(&repo.directory),
+            )
+        };
+        if !stage && status == Some(FileStatus::Untracked) {
+            self.show_status_message(format!("{file} is not staged"));
+            return Ok(());
+        }
+
+        let (repo, path) = self.resolve_file(&file);
+        let mut args = if stage {
+            vec!["add", "-A", "--"]
+        } else if unborn {
+            // Nothing to reset to before the first commit
+            vec!["rm", "--cached", "-q", "--"]
+        } else {
+            vec!["reset", "-q", "HEAD", "--"]
+        };
+        args.push(path);
+        if let Some(old_path) = &old_path {
+            args.push(old_path);
+        }
+        let output = self.git(&repo.directory, &args).await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        self.reload().await?;
+        self.show_status_message(if stage {
+            format!("Staged {file}")
+        } else {
+            format!("Unstaged {file}")
+        });
+        Ok(())
+    }
+
+    /// Stores a file change in the history without moving the pinned view: the
+    /// pinned file's diff is refreshed in place, other files only go to history.
+    async fn record_change_while_pinned(&self, pinned_file: &str, changed_file: Option<&str>) {
+        let scroll_position = self.state.lock().unwrap().scroll_position;
+        match changed_file {
+            Some(file) if file != pinned_file => {
+                let diff = match self.run_git_diff_for_file(file).await {
+                    Ok(diff) => diff,
+                    Err(e) => format!("Error getting diff for {file}: {e}"),
+                };
+                self.add_diff_to_history(diff, file.to_string());
+                if self.state.lock().unwrap().show_history {
+                    let accumulated_diff = self.build_accumulated_diff();
+                    self.state.lock().unwrap().git_diff = accumulated_diff;
+                }
             }
+            Some(_) => self.update_current_file_diff_with_history().await,
+            None => self.update_current_file_diff().await,
         }
 
-        if let Some(diff_start) = first_different_line {
-            // Now find the first actual content change (+ or - line) starting from the different line
-            for (i, line) in current_lines.iter().enumerate().skip(diff_start) {
-                if (line.starts_with('+') && !line.starts_with("+++"))
-                    || (line.starts_with('-') && !line.starts_with("---"))
-                {
-                    // Found the first addition or deletion, scroll to show it with context
-                    return (i as u16).saturating_sub(3);
-                } else if line.starts_with("@@") {
-                    // Found a hunk header, look for content changes after it
-                    for (j, content_line) in current_lines.iter().enumerate().skip(i + 1) {
-                        if (content_line.starts_with('+') && !content_line.starts_with("+++"))
-                            || (content_line.starts_with('-') && !content_line.starts_with("---"))
-                        {
-                            return (j as u16).saturating_sub(3);
-                        }
-                    }
+        let mut state = self.state.lock().unwrap();
+        state.scroll_position = scroll_position;
+        state.clamp_scroll();
+    }
+
+    fn toggle_pin(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pinned_file = match state.pinned_file {
+            Some(_) => None,
+            None => state.changed_files.get(state.current_file_index).cloned(),
+        };
+        let message = match &state.pinned_file {
+            Some(file) => format!("Pinned to {file}"),
+            None => "Unpinned, following changes".to_string(),
+        };
+        state.status_message = Some((message, Instant::now()));
+    }
+
+    /// Sends a desktop notification for a changed file, at most once per
+    /// `NOTIFY_INTERVAL` for each file.
+    fn notify_file_change(&self, file: &str) {
+        let body = {
+            let mut state = self.state.lock().unwrap();
+            let file = file.to_string();
+
+            let now = Instant::now();
+            if state
+                .last_notified
+                .get(&file)
+                .is_some_and(|last| now.duration_since(*last) < NOTIFY_INTERVAL)
+            {
+                return;
+            }
+            state.last_notified.insert(file.clone(), now);
+
+            match state.file_stats.get(&file) {
+                Some((insertions, deletions)) => {
+                    format!("{file} changed (+{insertions} -{deletions})")
                 }
+                None => format!("{file} changed"),
             }
+        };
 
-            // If no content changes found, just scroll to the first different line
-            return (diff_start as u16).saturating_sub(2);
-        }
+        tokio::spawn(send_desktop_notification("WatchHound", body));
+    }
 
-        // If we get here, current diff is same as previous (shouldn't happen)
-        // Fall back to smart scroll
-        self.calculate_smart_scroll_position(current_diff)
+    /// Arguments selecting what `git diff` compares, following the diff scope:
+    /// the working tree against the index, the index against HEAD, the working
+    /// tree against HEAD, or the working tree against `session_base`, which
+    /// callers get from `session_base`. `--base` takes the place of the index and
+    /// HEAD. Whitespace and rename options follow their runtime toggles.
+    fn diff_base_args<'a>(&'a self, repo: &Repo, session_base: Option<&'a str>) -> Vec<&'a str> {
+        let state = self.state.lock().unwrap();
+        // Before the first commit there's no HEAD to diff against, and the working
+        // tree usually matches the index, so show what's staged against the empty tree
+        let unborn = state.unborn_repos.contains(&repo.directory);
+        let mut args = vec!["diff"];
+        if state.diff_scope == DiffScope::Staged
+            || (unborn && state.diff_scope == DiffScope::WorkingTree)
+        {
+            args.push("--cached");
+        }
+        if let Some(session_base) = session_base {
+            args.push(session_base);
+        } else if let Some(base) = &self.options.base {
+            args.push(base.as_str());
+        } else if unborn {
+            args.push(EMPTY_TREE);
+        } else if state.diff_scope == DiffScope::All {
+            args.push("HEAD");
+        }
+        if state.ignore_whitespace {
+            args.push("-w");
+        }
+        // git may default to rename detection through diff.renames, so turning it off
+        // has to be explicit
+        args.push(if state.detect_renames {
+            "-M"
+        } else {
+            "--no-renames"
+        });
+        args
     }
 
-    fn build_accumulated_diff(&self) -> String {
+    /// The commit the session scope diffs `repo` against, `None` in other scopes.
+    fn session_base(&self, repo: &Repo) -> Option<String> {
         let state = self.state.lock().unwrap();
-        let mut accumulated = String::new();
+        if state.diff_scope != DiffScope::Session {
+            return None;
+        }
+        state.session_bases.get(&repo.directory).cloned()
+    }
 
-        for (i, entry) in state.diff_history.iter().enumerate() {
-            if i > 0 {
-                accumulated.push_str("\n\n");
-                accumulated.push_str(&format!(
-                    "=== Update {} at {} ===",
-                    i + 1,
-                    entry.timestamp.format("%H:%M:%S")
+    /// Records HEAD of every repository as the base of the session scope; the
+    /// empty tree for repositories without commits.
+    async fn record_session_bases(&self) {
+        let mut session_bases = HashMap::new();
+        for repo in self.repos.iter() {
+            let head = self
+                .git(
+                    &repo.directory,
+                    &["rev-parse", "--verify", "--quiet", "HEAD"],
+                )
+                .await;
+            let base = match head {
+                Ok(output) if output.status.success() => {
+                    String::from_utf8_lossy(&output.stdout).trim().to_string()
+                }
+                _ => EMPTY_TREE.to_string(),
+            };
+            session_bases.insert(repo.directory.clone(), base);
+        }
+        self.state.lock().unwrap().session_bases = session_bases;
+    }
+
+    /// Moves the session scope's base up to the current HEAD.
+    async fn reset_session_bases(&self) -> Result<()> {
+        self.record_session_bases().await;
+        self.state.lock().unwrap().clear_diff_cache();
+        self.reload().await?;
+        let message = match self.state.lock().unwrap().session_base_label() {
+            Some(label) => format!("Session base reset to {label}"),
+            None => "Session base reset".to_string(),
+        };
+        self.show_status_message(message);
+        Ok(())
+    }
+
+    /// Runs git in `directory`. While another git process, like an IDE's, holds a
+    /// lock the command needs, it's retried a few times before its failure is
+    /// passed on.
+    async fn git(&self, directory: &Path, args: &[&str]) -> Result<std::process::Output> {
+        let mut attempts = 1;
+        let mut delay = LOCK_RETRY_DELAY;
+        loop {
+            let started = Instant::now();
+            let result = run_git(&self.options.git_path, directory, args).await;
+            if self.options.debug {
+                self.log_git_command(directory, args, &result, started.elapsed());
+            }
+            let locked = matches!(
+                &result,
+                Ok(output) if !output.status.success() && is_lock_error(&output.stderr)
+            );
+            if !locked || attempts == LOCK_ATTEMPTS {
+                return result;
+            }
+            attempts += 1;
+            sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    fn log_git_command(
+        &self,
+        directory: &Path,
+        args: &[&str],
+        result: &Result<std::process::Output>,
+        elapsed: Duration,
+    ) {
+        let (status, success, stderr) = match result {
+            Ok(output) => (
+                match output.status.code() {
+                    Some(code) => format!("exit {code}"),
+                    None => "killed".to_string(),
+                },
+                output.status.success(),
+                String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            ),
+            Err(e) => (format!("failed to start: {e}"), false, String::new()),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.git_log.len() >= GIT_LOG_CAPACITY {
+            state.git_log.pop_front();
+        }
+        state.git_log.push_back(GitLogEntry {
+            time: Utc::now(),
+            directory: directory.to_path_buf(),
+            args: args.join(" "),
+            status,
+            success,
+            elapsed,
+            stderr,
+        });
+    }
+
+    /// Key used for `path` in `changed_files` and the per-file maps: the
+    /// repo-relative path, prefixed with the repository's label when several
+    /// repositories are watched.
+    fn file_key(&self, repo: &Repo, path: &str) -> String {
+        if self.repos.len() > 1 {
+            format!("{}/{path}", repo.label)
+        } else {
+            path.to_string()
+        }
+    }
+
+    /// How `file` is shown in the status pane and the diff pane's title.
+    fn display_path(&self, file: &str) -> String {
+        if self.options.path_style == PathStyle::Repo {
+            return file.to_string();
+        }
+        let (repo, path) = self.resolve_file(file);
+        let absolute = self.options.cwd.join(&repo.directory).join(path);
+        match self.options.path_style {
+            PathStyle::Cwd => path_relative_to(&self.options.cwd, &absolute),
+            _ => absolute.display().to_string(),
+        }
+    }
+
+    /// Staged/unstaged split of `diff`, the current diff of `file`, for files with
+    /// both kinds of changes shown against HEAD; empty for any other file.
+    async fn split_markers(&self, file: &str, diff: &str) -> HashMap<usize, bool> {
+        let ignore_whitespace = {
+            let state = self.state.lock().unwrap();
+            let eligible = matches!(self.options.mode, DiffMode::Git)
+                && self.options.base.is_none()
+                && state.diff_scope == DiffScope::All
+                && state.file_status.get(file) == Some(&FileStatus::Both)
+                && !state.renames.contains_key(file);
+            if !eligible {
+                return HashMap::new();
+            }
+            state.ignore_whitespace
+        };
+
+        let (repo, path) = self.resolve_file(file);
+        let diff_args = |cached: bool| {
+            let mut args = vec!["diff"];
+            if cached {
+                args.push("--cached");
+            }
+            if ignore_whitespace {
+                args.push("-w");
+            }
+            args.extend(["--", path]);
+            args
+        };
+        let staged = self.git(&repo.directory, &diff_args(true)).await;
+        let unstaged = self.git(&repo.directory, &diff_args(false)).await;
+        match (staged, unstaged) {
+            (Ok(staged), Ok(unstaged)) if staged.status.success() && unstaged.status.success() => {
+                split_staged_lines(
+                    diff,
+                    &String::from_utf8_lossy(&staged.stdout),
+                    &String::from_utf8_lossy(&unstaged.stdout),
+                )
+            }
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Splits a file key back into its repository and repo-relative path.
+    fn resolve_file<'a>(&self, file: &'a str) -> (&Repo, &'a str) {
+        if self.repos.len() > 1 {
+            if let Some((label, path)) = file.split_once('/') {
+                if let Some(repo) = self.repos.iter().find(|repo| repo.label == label) {
+                    return (repo, path);
+                }
+            }
+        }
+        (&self.repos[0], file)
+    }
+
+    /// File key for a path reported by the watcher.
+    fn file_key_for_path(&self, path: &Path) -> Option<String> {
+        self.repos.iter().find_map(|repo| {
+            repo_relative_path(&repo.directory, path).map(|relative| self.file_key(repo, &relative))
+        })
+    }
+
+    /// `git diff --stat` for the status pane, along with the exact insertions and
+    /// deletions of each file, which the stat's bar only shows scaled down.
+    async fn run_git_stats(&self) -> Result<(String, HashMap<String, (usize, usize)>)> {
+        let stat = self.run_git_diff_stat().await?;
+        let file_stats = self.run_git_numstat().await?;
+        Ok((stat, file_stats))
+    }
+
+    async fn run_git_diff_stat(&self) -> Result<String> {
+        if let DiffMode::Snapshot(snapshot) = &self.options.mode {
+            return Ok(snapshot.diff_stat()?);
+        }
+
+        // The stat comes first on every refresh, so notice a first commit (or a
+        // fresh `git init`) here
+        self.update_unborn_repos().await;
+
+        if self.repos.len() == 1 {
+            return self.run_git_diff_stat_in(&self.repos[0]).await;
+        }
+
+        // Group the stat by repository, each under a header line
+        let mut stat = String::new();
+        for repo in self.repos.iter() {
+            let repo_stat = self.run_git_diff_stat_in(repo).await?;
+            if repo_stat.is_empty() {
+                continue;
+            }
+            if !stat.is_empty() {
+                stat.push('\n');
+            }
+            stat.push_str(&format!("[{}] {}\n", repo.label, repo.directory.display()));
+            stat.push_str(&repo_stat);
+        }
+        Ok(stat)
+    }
+
+    /// Insertions and deletions of each changed file by key, from
+    /// `git diff --numstat`.
+    async fn run_git_numstat(&self) -> Result<HashMap<String, (usize, usize)>> {
+        if let DiffMode::Snapshot(snapshot) = &self.options.mode {
+            return Ok(snapshot.file_changes()?);
+        }
+
+        let mut file_stats = HashMap::new();
+        for repo in self.repos.iter() {
+            let session_base = self.session_base(repo);
+            let mut args = self.diff_base_args(repo, session_base.as_deref());
+            args.extend(["--numstat", "-z"]);
+            args.extend(repo.pathspec());
+            let output = self.git(&repo.directory, &args).await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "Git command failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
                 ));
-                accumulated.push_str(&format!(" (File: {}) ===\n", entry.file_name));
             }
-            accumulated.push_str(&entry.diff_content);
+            for (path, changes) in parse_numstat(&String::from_utf8_lossy(&output.stdout)) {
+                file_stats.insert(self.file_key(repo, &path), changes);
+            }
+        }
+        Ok(file_stats)
+    }
+
+    /// Records which repositories have no commits yet.
+    async fn update_unborn_repos(&self) {
+        let mut unborn_repos = HashSet::new();
+        for repo in self.repos.iter() {
+            let head = self
+                .git(
+                    &repo.directory,
+                    &["rev-parse", "--verify", "--quiet", "HEAD"],
+                )
+                .await;
+            if head.is_ok_and(|output| !output.status.success()) {
+                unborn_repos.insert(repo.directory.clone());
+            }
+        }
+        self.state.lock().unwrap().unborn_repos = unborn_repos;
+    }
+
+    async fn run_git_diff_stat_in(&self, repo: &Repo) -> Result<String> {
+        let session_base = self.session_base(repo);
+        let mut args = self.diff_base_args(repo, session_base.as_deref());
+        // --summary lists mode changes, which the stat alone shows as "| 0"
+        args.extend(["--stat", "--summary"]);
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        // Name files by their keys so the status pane matches `changed_files`
+        let mut stat: String = mark_mode_changes(&String::from_utf8_lossy(&output.stdout))
+            .lines()
+            .map(unmerged_stat_line)
+            .map(|line| match line.split_once('|') {
+                Some((file, rest)) if self.repos.len() > 1 => {
+                    let file = file.trim_start();
+                    // Prefix both sides of a plain "old => new" rename
+                    let file = match file.split_once(" => ") {
+                        Some((old, new)) if !file.contains('{') => {
+                            format!(
+                                "{} => {}",
+                                self.file_key(repo, old),
+                                self.file_key(repo, new)
+                            )
+                        }
+                        _ => self.file_key(repo, file),
+                    };
+                    format!(" {file}|{rest}\n")
+                }
+                _ => format!("{line}\n"),
+            })
+            .collect();
+
+        // git diff --stat never mentions untracked files, so list them separately
+        let untracked = if self.state.lock().unwrap().diff_scope == DiffScope::Staged {
+            Vec::new()
+        } else {
+            self.get_untracked_files(repo).await?
+        };
+        if !untracked.is_empty() {
+            if !stat.is_empty() {
+                stat.push('\n');
+            }
+            stat.push_str("Untracked files:\n");
+            for file in untracked {
+                stat.push_str(&format!(" {}\n", self.file_key(repo, &file)));
+            }
+        }
+
+        Ok(stat)
+    }
+
+    /// Diff for one file, with git's "Binary files ... differ" line replaced by the
+    /// size change from the stat.
+    async fn run_git_diff_for_file(&self, file: &str) -> Result<String> {
+        let diff = self.run_raw_diff_for_file(file).await?;
+        if !diff.lines().any(is_binary_diff_line) {
+            if self.state.lock().unwrap().full_diffs.contains(file) {
+                return Ok(diff);
+            }
+            return Ok(truncate_diff(diff, self.options.max_diff_lines));
+        }
+
+        let sizes = {
+            let state = self.state.lock().unwrap();
+            state
+                .git_stat
+                .lines()
+                .find(|line| state.stat_line_file(line).map(String::as_str) == Some(file))
+                .and_then(|line| line.split_once('|'))
+                .and_then(|(_, stats)| binary_stat_sizes(stats))
+        };
+        Ok(describe_binary_diff(&diff, sizes))
+    }
+
+    async fn run_raw_diff_for_file(&self, file: &str) -> Result<String> {
+        let context_lines = {
+            let state = self.state.lock().unwrap();
+            if state.whole_files.contains(file) {
+                WHOLE_FILE_CONTEXT
+            } else {
+                state.context_lines
+            }
+        };
+        if let DiffMode::Snapshot(snapshot) = &self.options.mode {
+            return Ok(snapshot.diff_file(file, context_lines));
+        }
+
+        let is_untracked = {
+            let state = self.state.lock().unwrap();
+            state.file_status.get(file) == Some(&FileStatus::Untracked)
+        };
+        let (repo, path) = self.resolve_file(file);
+        if is_untracked {
+            return synthesize_untracked_diff(&repo.directory, path);
+        }
+
+        let renamed_from = self.state.lock().unwrap().renames.get(file).cloned();
+        let context_arg = format!("-U{context_lines}");
+        let session_base = self.session_base(repo);
+        let mut args = self.diff_base_args(repo, session_base.as_deref());
+        args.extend([context_arg.as_str(), "--", path]);
+        // Without the old path in the pathspec git can't pair the rename up
+        if let Some(old) = &renamed_from {
+            args.push(old);
+        }
+        let output = self.git(&repo.directory, &args).await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        Ok(decode_diff(&output.stdout))
+    }
+
+    /// Changed files matching the active filter. The full list is kept in
+    /// `all_changed_files`.
+    async fn get_changed_files(&self) -> Result<Vec<String>> {
+        let files = self.get_all_changed_files().await?;
+        let mut state = self.state.lock().unwrap();
+        let filtered = state.filtered_files(&files);
+        state.all_changed_files = files;
+        Ok(filtered)
+    }
+
+    async fn get_all_changed_files(&self) -> Result<Vec<String>> {
+        if let DiffMode::Snapshot(snapshot) = &self.options.mode {
+            let files = snapshot.changed_files()?;
+            let deleted = files
+                .iter()
+                .filter(|file| !self.repos[0].directory.join(file).exists())
+                .cloned()
+                .collect();
+            self.update_file_times(&files, &deleted);
+            let mut state = self.state.lock().unwrap();
+            state.file_status = files
+                .iter()
+                .map(|file| (file.clone(), FileStatus::Unstaged))
+                .collect();
+            state.deleted_files = deleted;
+            return Ok(files);
+        }
+
+        let mut files = Vec::new();
+        let mut file_status = HashMap::new();
+        let mut renames = HashMap::new();
+        let mut deleted = HashSet::new();
+        for repo in self.repos.iter() {
+            let (repo_files, repo_status) = self.get_changed_files_in(repo).await?;
+            files.extend(repo_files.iter().map(|file| self.file_key(repo, file)));
+            file_status.extend(
+                repo_status
+                    .into_iter()
+                    .map(|(file, status)| (self.file_key(repo, &file), status)),
+            );
+            renames.extend(
+                self.get_renames_in(repo)
+                    .await?
+                    .into_iter()
+                    .map(|(new, old)| (self.file_key(repo, &new), old)),
+            );
+            deleted.extend(
+                self.get_deleted_files_in(repo)
+                    .await?
+                    .iter()
+                    .map(|file| self.file_key(repo, file)),
+            );
+        }
+
+        // Update file modification times
+        self.update_file_times(&files, &deleted);
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.file_status = file_status;
+            state.renames = renames;
+            state.deleted_files = deleted;
+        }
+
+        Ok(files)
+    }
+
+    /// Repo-relative changed files in one repository, with their status.
+    async fn get_changed_files_in(
+        &self,
+        repo: &Repo,
+    ) -> Result<(Vec<String>, HashMap<String, FileStatus>)> {
+        let (detect_renames, scope) = {
+            let state = self.state.lock().unwrap();
+            (state.detect_renames, state.diff_scope)
+        };
+        let rename_arg = if detect_renames { "-M" } else { "--no-renames" };
+
+        let mut files = if scope == DiffScope::Staged {
+            Vec::new()
+        } else {
+            self.get_unstaged_files(repo, rename_arg).await?
+        };
+
+        let mut file_status: HashMap<String, FileStatus> = files
+            .iter()
+            .map(|file| (file.clone(), FileStatus::Unstaged))
+            .collect();
+
+        // Without commits, staged files are all there is to show
+        let unborn = self
+            .state
+            .lock()
+            .unwrap()
+            .unborn_repos
+            .contains(&repo.directory);
+        let include_staged = match scope {
+            DiffScope::Staged | DiffScope::All => true,
+            DiffScope::WorkingTree => unborn,
+            // Diffing the working tree against the session base covers the index too
+            DiffScope::Session => false,
+        };
+        if include_staged {
+            for file in self.get_staged_files(repo, rename_arg).await? {
+                match file_status.get(&file) {
+                    Some(_) => {
+                        file_status.insert(file, FileStatus::Both);
+                    }
+                    None => {
+                        file_status.insert(file.clone(), FileStatus::Staged);
+                        files.push(file);
+                    }
+                }
+            }
+            // Keep the merged list in the same path order git uses
+            files.sort();
+        }
+
+        // Unmerged files show up in both lists above; flag them whatever the scope
+        for file in self.get_conflicted_files(repo).await? {
+            if !file_status.contains_key(&file) {
+                files.push(file.clone());
+            }
+            file_status.insert(file, FileStatus::Conflicted);
+        }
+
+        // Untracked files go last, after everything git already knows about. They
+        // can't be staged, so the staged scope leaves them out.
+        if scope != DiffScope::Staged {
+            for file in self.get_untracked_files(repo).await? {
+                if !file_status.contains_key(&file) {
+                    file_status.insert(file.clone(), FileStatus::Untracked);
+                    files.push(file);
+                }
+            }
         }
 
-        accumulated
+        Ok((files, file_status))
     }
 
-    fn calculate_scroll_position_for_new_diff(&self) -> u16 {
-        let state = self.state.lock().unwrap();
-        if state.diff_history.len() <= 1 {
-            return 0;
+    /// Files with working-tree changes, against the session base in the session
+    /// scope or `--base` when given.
+    async fn get_unstaged_files(&self, repo: &Repo, rename_arg: &str) -> Result<Vec<String>> {
+        let session_base = self.session_base(repo);
+        let mut args = vec!["diff"];
+        if let Some(base) = session_base.as_ref().or(self.options.base.as_ref()) {
+            args.push(base.as_str());
         }
+        args.extend([rename_arg, "--name-only"]);
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
 
-        // Calculate lines in all previous diffs
-        let mut lines_count = 0u16;
-        for (i, entry) in state.diff_history.iter().enumerate() {
-            if i == state.diff_history.len() - 1 {
-                break; // Don't count the last (new) entry
-            }
-
-            if i > 0 {
-                lines_count += 3; // For separator lines
-            }
-            lines_count += entry.diff_content.lines().count() as u16;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff --name-only failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
         }
 
-        lines_count
+        // Unmerged files are listed once per conflicting side
+        let files = String::from_utf8_lossy(&output.stdout);
+        let mut files: Vec<String> = files.trim().lines().map(|s| s.to_string()).collect();
+        files.dedup();
+        Ok(files)
     }
 
-    fn auto_scroll_to_new_diff(&self) {
-        let scroll_position = self.calculate_scroll_position_for_new_diff();
-        let mut state = self.state.lock().unwrap();
-        state.scroll_position = scroll_position;
-    }
+    /// Files with unresolved merge conflicts.
+    async fn get_conflicted_files(&self, repo: &Repo) -> Result<Vec<String>> {
+        let mut args = vec!["diff", "--name-only", "--diff-filter=U"];
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff --diff-filter=U failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
 
-    fn calculate_smart_scroll_position(&self, diff_content: &str) -> u16 {
-        let lines: Vec<&str> = diff_content.lines().collect();
-        let mut first_addition_line = None;
+        let files = String::from_utf8_lossy(&output.stdout);
+        let mut files: Vec<String> = files.trim().lines().map(|s| s.to_string()).collect();
+        files.dedup();
+        Ok(files)
+    }
 
-        // Find the first line that contains an addition (starts with +)
-        for (i, line) in lines.iter().enumerate() {
-            if line.starts_with('+') && !line.starts_with("+++") {
-                first_addition_line = Some(i as u16);
-                break; // Found the first addition, stop looking
-            }
+    /// Describes HEAD as "branch @ sha subject" for the status bar, or `None` when
+    /// not watching a git repository. With several repositories each one is
+    /// described in turn, prefixed with its label.
+    async fn run_git_head_info(&self) -> Result<Option<HeadInfo>> {
+        if let DiffMode::Snapshot(_) = &self.options.mode {
+            return Ok(None);
         }
 
-        // If we found additions, scroll to show them (with some context)
-        if let Some(first_line) = first_addition_line {
-            // Show the addition with some context lines before it
-            first_line.saturating_sub(3)
-        } else {
-            // If no additions found, look for the end of the diff
-            let total_lines = lines.len() as u16;
-            if total_lines > 10 {
-                total_lines.saturating_sub(8)
+        let mut descriptions = Vec::new();
+        let mut branches = Vec::new();
+        for repo in self.repos.iter() {
+            let (branch, head_info) = self.run_git_head_info_in(&repo.directory).await?;
+            if self.repos.len() == 1 {
+                descriptions.push(head_info);
             } else {
-                0
+                descriptions.push(format!("{}: {head_info}", repo.label));
             }
+            branches.push(branch);
         }
+        Ok(Some(HeadInfo {
+            description: descriptions.join(" | "),
+            branches,
+        }))
     }
 
-    fn clear_diff_history(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.diff_history.clear();
-        state.git_diff =
-            "Diff history cleared.\n\nMake changes to files to see new diffs here.".to_string();
-        state.scroll_position = 0;
-    }
-
-    fn toggle_history_view(&self) {
-        let mut state = self.state.lock().unwrap();
-        state.show_history = !state.show_history;
-        state.scroll_position = 0;
-    }
-
-    async fn refresh_display(&self) {
-        let show_history = {
-            let state = self.state.lock().unwrap();
-            state.show_history
+    /// Describes HEAD of the repository in `directory` as "branch @ sha subject",
+    /// with the commits ahead of and behind its upstream, the number of stashes
+    /// and any merge, rebase or similar operation left in progress after the
+    /// branch, e.g. "main ↑3 ↓1 stash:2 [REBASING] @ ...". The branch name comes
+    /// first on its own.
+    async fn run_git_head_info_in(&self, directory: &Path) -> Result<(String, String)> {
+        let branch = self
+            .git(directory, &["rev-parse", "--abbrev-ref", "HEAD"])
+            .await?;
+        // rev-parse fails before the first commit and prints "HEAD" when detached
+        let branch = match String::from_utf8_lossy(&branch.stdout).trim() {
+            _ if !branch.status.success() => "(no branch)".to_string(),
+            "HEAD" => "(detached)".to_string(),
+            name => name.to_string(),
         };
 
-        if show_history {
-            // Show accumulated history
-            let accumulated_diff = self.build_accumulated_diff();
-            let mut state = self.state.lock().unwrap();
-            state.git_diff = if accumulated_diff.is_empty() {
-                "No diff history available.\n\nMake changes to files to see diffs here.\n\nPress 'h' to toggle back to current file view.".to_string()
-            } else {
-                accumulated_diff
-            };
+        let commit = self
+            .git(directory, &["log", "-1", "--pretty=%h %s"])
+            .await?;
+        let commit = String::from_utf8_lossy(&commit.stdout).trim().to_string();
+        let commit = if commit.is_empty() {
+            "(no commits)".to_string()
         } else {
-            // Show current file diff only
-            self.update_current_file_diff().await;
-        }
-    }
+            commit
+        };
 
-    async fn update_current_file_diff(&self) {
-        self.update_current_file_diff_internal(false).await;
-    }
+        let mut activity = String::new();
+        // Fails without an upstream, which just leaves the counts out
+        let counts = self
+            .git(
+                directory,
+                &["rev-list", "--left-right", "--count", "@{upstream}...HEAD"],
+            )
+            .await?;
+        if counts.status.success() {
+            let counts = String::from_utf8_lossy(&counts.stdout);
+            if let Some((behind, ahead)) = counts.trim().split_once('\t') {
+                if ahead != "0" {
+                    activity.push_str(&format!(" ↑{ahead}"));
+                }
+                if behind != "0" {
+                    activity.push_str(&format!(" ↓{behind}"));
+                }
+            }
+        }
+        let stashes = self.git(directory, &["stash", "list"]).await?;
+        let stashes = String::from_utf8_lossy(&stashes.stdout).lines().count();
+        if stashes > 0 {
+            activity.push_str(&format!(" stash:{stashes}"));
+        }
+        // Worktrees and submodules keep their git directory elsewhere
+        let git_dir = self.git(directory, &["rev-parse", "--git-dir"]).await?;
+        let git_dir = directory.join(String::from_utf8_lossy(&git_dir.stdout).trim());
+        if let Some(operation) = operation_in_progress(&git_dir) {
+            activity.push_str(&format!(" [{operation}]"));
+        }
 
-    async fn update_current_file_diff_with_history(&self) {
-        self.update_current_file_diff_internal(true).await;
+        let description = format!("{branch}{activity} @ {commit}");
+        Ok((branch, description))
     }
 
-    async fn update_current_file_diff_internal(&self, store_in_history: bool) {
-        let current_file = {
-            let state = self.state.lock().unwrap();
-            if state.changed_files.is_empty() {
-                return;
-            }
-            state.changed_files[state.current_file_index].clone()
-        };
-
-        // Show loading state (but don't store this in history)
-        {
-            let mut state = self.state.lock().unwrap();
-            state.git_diff = format!("Loading diff for {current_file}...");
+    /// Files with staged changes, against `--base` when given.
+    async fn get_staged_files(&self, repo: &Repo, rename_arg: &str) -> Result<Vec<String>> {
+        let mut args = vec!["diff", "--cached"];
+        if let Some(base) = &self.options.base {
+            args.push(base.as_str());
         }
+        args.extend([rename_arg, "--name-only"]);
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
 
-        // Brief delay to show loading state
-        sleep(Duration::from_millis(100)).await;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff --cached --name-only failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
 
-        let git_diff = match self.run_git_diff_for_file(&current_file).await {
-            Ok(output) => {
-                if output.trim().is_empty() {
-                    format!("No changes in {current_file}\n\nThis file may have been staged or the changes may be minimal.")
-                } else {
-                    output
-                }
-            }
-            Err(e) => {
-                format!("Error getting diff for {current_file}: {e}\n\nTry refreshing with 'r' or check if the file still exists.")
-            }
-        };
+        let files = String::from_utf8_lossy(&output.stdout);
+        Ok(files.trim().lines().map(|s| s.to_string()).collect())
+    }
 
-        if store_in_history {
-            // Find the previous diff for this file to compare against
-            let previous_diff = {
-                let state = self.state.lock().unwrap();
-                state
-                    .diff_history
-                    .iter()
-                    .rev()
-                    .find(|entry| entry.file_name == current_file)
-                    .map(|entry| entry.diff_content.clone())
-            };
+    /// Renamed files in one repository, mapping each new path to its old one. Empty
+    /// while rename detection is off.
+    async fn get_renames_in(&self, repo: &Repo) -> Result<HashMap<String, String>> {
+        if !self.state.lock().unwrap().detect_renames {
+            return Ok(HashMap::new());
+        }
+        let session_base = self.session_base(repo);
+        let mut args = self.diff_base_args(repo, session_base.as_deref());
+        args.extend(["--name-status", "--diff-filter=R"]);
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
 
-            // Calculate scroll position based on what's actually new
-            let scroll_position = if let Some(ref prev_diff) = previous_diff {
-                // Find the first line that's different from the previous diff
-                self.find_first_diff_line(&git_diff, prev_diff)
-            } else {
-                // No previous diff, use smart scrolling to find first addition
-                self.calculate_smart_scroll_position(&git_diff)
-            };
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff --name-status failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
 
-            // Store the diff in history
-            self.add_diff_to_history(git_diff.clone(), current_file.clone());
+        // Lines look like "R096<TAB>old<TAB>new"
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t').skip(1);
+                let old = fields.next()?;
+                let new = fields.next()?;
+                Some((new.to_string(), old.to_string()))
+            })
+            .collect())
+    }
 
-            // Check if we should show history or current file
-            let show_history = {
-                let state = self.state.lock().unwrap();
-                state.show_history
-            };
+    /// Files deleted in one repository, in whatever the diff pane is comparing.
+    async fn get_deleted_files_in(&self, repo: &Repo) -> Result<Vec<String>> {
+        let session_base = self.session_base(repo);
+        let mut args = self.diff_base_args(repo, session_base.as_deref());
+        args.extend(["--name-only", "--diff-filter=D"]);
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
 
-            if show_history {
-                // Build and set the accumulated diff with auto-scroll for history view
-                self.auto_scroll_to_new_diff();
-                let accumulated_diff = self.build_accumulated_diff();
-                {
-                    let mut state = self.state.lock().unwrap();
-                    state.git_diff = accumulated_diff;
-                }
-            } else {
-                // Show current file diff and scroll to show the first different line
-                {
-                    let mut state = self.state.lock().unwrap();
-                    state.git_diff = git_diff.clone();
-                    // Use the calculated scroll position to show the first different line
-                    state.scroll_position = scroll_position;
-                }
-            }
-        } else {
-            // Just show the current diff without storing in history
-            {
-                let mut state = self.state.lock().unwrap();
-                state.git_diff = git_diff;
-            }
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git diff --diff-filter=D failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
         }
+
+        let files = String::from_utf8_lossy(&output.stdout);
+        Ok(files.trim().lines().map(|s| s.to_string()).collect())
     }
 
-    async fn load_initial_state(&self) -> Result<()> {
-        // Set initial loading state
-        {
-            let mut state = self.state.lock().unwrap();
-            state.git_stat = "WatchHound starting up...\nLoading git status...".to_string();
-            state.git_diff =
-                "Initializing git repository scan...\n\nChecking for changes...".to_string();
+    async fn get_untracked_files(&self, repo: &Repo) -> Result<Vec<String>> {
+        let mut args = vec!["ls-files", "--others", "--exclude-standard"];
+        args.extend(repo.pathspec());
+        let output = self.git(&repo.directory, &args).await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Git ls-files --others failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
         }
 
-        // Brief delay to show loading state
-        sleep(Duration::from_millis(500)).await;
+        let files = String::from_utf8_lossy(&output.stdout);
+        Ok(files.trim().lines().map(|s| s.to_string()).collect())
+    }
 
-        // Get initial git diff --stat
-        let git_stat = match self.run_git_diff_stat().await {
-            Ok(output) => {
-                if output.trim().is_empty() {
-                    "No changes detected in the repository.\n\nMake some changes to files to see diffs here!\n\nTip: Edit a file and the changes will appear automatically.".to_string()
-                } else {
-                    output
-                }
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Failed to get git status: {}", e));
-            }
-        };
+    /// Records the modification time of each changed file. Deleted files have
+    /// none, and dropping their old one keeps a diff cached before the deletion
+    /// from being reused.
+    fn update_file_times(&self, files: &[String], deleted: &HashSet<String>) {
+        let mut state = self.state.lock().unwrap();
 
-        // Get all changed files
-        let changed_files = match self.get_changed_files().await {
-            Ok(files) => files,
-            Err(e) => {
-                return Err(anyhow::anyhow!("Failed to get changed files: {}", e));
+        for file in files {
+            if deleted.contains(file) {
+                state.file_info.remove(file);
+                continue;
+            }
+            let (repo, path) = self.resolve_file(file);
+            let file_path = repo.directory.join(path);
+            if let Ok(metadata) = fs::metadata(&file_path) {
+                if let Ok(modified) = metadata.modified() {
+                    let file_info = FileInfo {
+                        last_modified: modified,
+                    };
+                    state.file_info.insert(file.clone(), file_info);
+                }
             }
-        };
-
-        // Update state with initial data
-        {
-            let mut state = self.state.lock().unwrap();
-            state.git_stat = git_stat;
-            state.changed_files = changed_files;
-            state.current_file_index = 0;
-            state.scroll_position = 0;
-            state.last_update = Some(chrono::Utc::now());
-            state.error_message = None;
         }
+    }
+}
 
-        // Get diff for first file if available
-        let has_files = {
-            let state = self.state.lock().unwrap();
-            !state.changed_files.is_empty()
-        };
-
-        if has_files {
-            self.update_current_file_diff_with_history().await;
-        } else {
-            // No files to show diff for
-            let no_changes_message = "No changes to display.\n\nTo see colorized diffs:\n1. Make changes to files\n2. Use 'r' to refresh\n3. Use Left/Right to navigate files\n4. Use Space to scroll\n5. Use 'h' to toggle history view\n6. Use 'c' to clear diff history\n\nRecently changed files will be highlighted!\nDiff history is automatically stored and scrolls to new changes.".to_string();
+/// Message for when the git executable can't be started.
+fn git_not_found_message(git: &Path) -> String {
+    if git == Path::new("git") {
+        "git executable not found — is Git installed and on your PATH?".to_string()
+    } else {
+        format!(
+            "git executable not found at {} — check --git-path",
+            git.display()
+        )
+    }
+}
 
-            // Store the initial message in history
-            self.add_diff_to_history(no_changes_message.clone(), "Initial State".to_string());
+/// Top level of the git repository containing `directory`, and `directory`'s
+/// path inside it ("" at the top level). `None` if git can't tell.
+fn git_toplevel(git: &Path, directory: &Path) -> Option<(PathBuf, String)> {
+    let output = std::process::Command::new(git)
+        .args(["rev-parse", "--show-toplevel", "--show-prefix"])
+        .current_dir(directory)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let toplevel = PathBuf::from(lines.next()?);
+    let prefix = lines.next().unwrap_or("").trim_end_matches('/').to_string();
+    Some((toplevel, prefix))
+}
 
-            let mut state = self.state.lock().unwrap();
-            state.git_diff = no_changes_message;
-        }
+/// Git directory and common directory of the repository at `directory`,
+/// which are the same `.git` unless it's a worktree or submodule. `None` if
+/// git can't tell.
+fn git_dirs(git: &Path, directory: &Path) -> Option<(PathBuf, PathBuf)> {
+    let output = std::process::Command::new(git)
+        .args(["rev-parse", "--git-dir", "--git-common-dir"])
+        .current_dir(directory)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    // Either may be relative to `directory`
+    let mut resolve = || {
+        let path = directory.join(lines.next()?);
+        Some(fs::canonicalize(&path).unwrap_or(path))
+    };
+    Some((resolve()?, resolve()?))
+}
 
-        Ok(())
+/// Whether `directory` is inside a git working tree. A `.git` entry answers it
+/// quickly; otherwise git is asked, which also covers subdirectories of a
+/// repository and worktrees or submodules whose `.git` points elsewhere.
+fn is_git_work_tree(git: &Path, directory: &Path) -> bool {
+    if directory.join(".git").exists() {
+        return true;
     }
+    std::process::Command::new(git)
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .current_dir(directory)
+        .output()
+        .is_ok_and(|output| {
+            output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "true"
+        })
+}
 
-    async fn handle_file_change(&self, path: &Path) -> Result<()> {
-        // Wait 1 second before processing
-        sleep(Duration::from_secs(1)).await;
-
-        // Clear error message
-        {
-            let mut state = self.state.lock().unwrap();
-            state.error_message = None;
-        }
+/// Times a git command is run while it keeps finding a lock taken.
+const LOCK_ATTEMPTS: u32 = 4;
 
-        // Run git diff --stat
-        let git_stat = match self.run_git_diff_stat().await {
-            Ok(output) => output,
-            Err(e) => {
-                let mut state = self.state.lock().unwrap();
-                state.error_message = Some(format!("Git stat error: {e}"));
-                return Ok(());
-            }
-        };
+/// Wait before the first retry of a command that found a lock taken, doubled
+/// for each further one.
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(100);
 
-        // Get all changed files
-        let changed_files = match self.get_changed_files().await {
-            Ok(files) => files,
-            Err(e) => {
-                let mut state = self.state.lock().unwrap();
-                state.error_message = Some(format!("Error finding changed files: {e}"));
-                return Ok(());
-            }
-        };
+/// Whether git failed because another git process holds a lock file, like
+/// "Unable to create '.../.git/index.lock': File exists".
+fn is_lock_error(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr.contains(".lock': File exists") || stderr.contains("Another git process")
+}
 
-        // Update state with new files list
-        {
-            let mut state = self.state.lock().unwrap();
-            state.git_stat = git_stat;
+/// Runs `git` with `args` in `directory`, turning a missing executable into a
+/// clear error instead of a bare "No such file or directory".
+async fn run_git(git: &Path, directory: &Path, args: &[&str]) -> Result<std::process::Output> {
+    match Command::new(git)
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .await
+    {
+        Ok(output) => Ok(output),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(anyhow::anyhow!(git_not_found_message(git)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-            // Find the index of the changed file to display it
-            let changed_file_path = path.to_string_lossy().to_string();
-            let mut new_index = 0;
+/// Runs `command` through the shell with `diff` on stdin and returns its stdout.
+/// COLUMNS is set to `width` so commands that lay out columns fit the pane.
+async fn run_diff_command(command: &str, diff: &str, width: u16) -> Result<String> {
+    use std::process::Stdio;
+    use tokio::io::AsyncWriteExt;
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if width > 0 {
+        child = child.env("COLUMNS", width.to_string());
+    }
+    let mut child = child.spawn()?;
+
+    // Write from a separate task so a command that prints before reading all of
+    // its input can't deadlock against us
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = diff.to_string();
+    let writer = tokio::spawn(async move { stdin.write_all(input.as_bytes()).await });
+    let output = child.wait_with_output().await?;
+    // The command may exit without reading everything, which is fine
+    let _ = writer.await;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(if stderr.trim().is_empty() {
+            anyhow::anyhow!("{command} exited with {}", output.status)
+        } else {
+            anyhow::anyhow!("{}", stderr.trim())
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
 
-            if !changed_files.is_empty() {
-                // Try to find the exact file that changed
-                if let Some(index) = changed_files.iter().position(|f| f == &changed_file_path) {
-                    new_index = index;
-                } else {
-                    // If exact match not found, try to find files that end with the same relative path
-                    // This handles cases where the watcher might give absolute paths but git gives relative paths
-                    let changed_file_name = path.file_name().unwrap_or_default().to_string_lossy();
-                    if let Some(index) = changed_files.iter().position(|f| {
-                        Path::new(f)
-                            .file_name()
-                            .unwrap_or_default()
-                            .to_string_lossy()
-                            == changed_file_name
-                    }) {
-                        new_index = index;
-                    }
-                    // If still not found, default to 0 (first file)
-                }
+/// Builds an all-additions diff for a file git does not track yet, so it renders
+/// like any other new file.
+fn synthesize_untracked_diff(directory: &Path, file: &str) -> Result<String> {
+    let bytes = fs::read(directory.join(file))?;
 
-                state.current_file_index = new_index;
-                // Don't reset scroll position here - let auto-scroll handle it
-            }
+    let mut diff = format!(
+        "diff --git a/{file} b/{file}\nnew file (untracked)\n--- /dev/null\n+++ b/{file}\n"
+    );
+    if std::str::from_utf8(&bytes).is_err() {
+        diff.insert_str(0, &invalid_utf8_line());
+    }
 
-            state.changed_files = changed_files;
-            state.last_update = Some(Utc::now());
-        }
+    if bytes.is_empty() {
+        diff.push_str("(new empty file)\n");
+        return Ok(diff);
+    }
 
-        // Get diff for current file - store in history since this is a real file change
-        if !{
-            let state = self.state.lock().unwrap();
-            state.changed_files.is_empty()
-        } {
-            self.update_current_file_diff_with_history().await;
-        }
+    if bytes.contains(&0) {
+        diff.push_str(&binary_change_summary(Some((0, bytes.len() as u64))));
+        diff.push('\n');
+        return Ok(diff);
+    }
 
-        Ok(())
+    let content = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = content.lines().collect();
+    diff.push_str(&format!("@@ -0,0 +1,{} @@\n", lines.len()));
+    for line in lines {
+        diff.push('+');
+        diff.push_str(line);
+        diff.push('\n');
     }
 
-    async fn run_git_diff_stat(&self) -> Result<String> {
-        let output = Command::new("git")
-            .args(["diff", "--stat"])
-            .current_dir(&self.directory)
-            .output()?;
+    Ok(diff)
+}
 
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Git command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
+/// Files and directories git keeps in its directory while an operation is
+/// stopped halfway, with how the status bar names it.
+const OPERATIONS_IN_PROGRESS: [(&str, &str); 6] = [
+    ("rebase-merge", "REBASING"),
+    ("rebase-apply", "REBASING"),
+    ("MERGE_HEAD", "MERGING"),
+    ("CHERRY_PICK_HEAD", "CHERRY-PICKING"),
+    ("REVERT_HEAD", "REVERTING"),
+    ("BISECT_LOG", "BISECTING"),
+];
+
+/// The rebase, merge or similar operation left in progress in `git_dir`, if any.
+fn operation_in_progress(git_dir: &Path) -> Option<&'static str> {
+    OPERATIONS_IN_PROGRESS
+        .iter()
+        .find(|(marker, _)| git_dir.join(marker).exists())
+        .map(|(_, operation)| *operation)
+}
 
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// Hash of git's empty tree, diffed against in repositories without commits.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// git's stat of an unmerged file runs the "file | Unmerged" entry into the file's
+/// real stat line; keeps just the latter.
+fn unmerged_stat_line(line: &str) -> Cow<'_, str> {
+    match line.split_once(" | Unmerged ") {
+        Some((_, rest)) if rest.contains('|') => format!(" {rest}").into(),
+        _ => line.into(),
     }
+}
 
-    async fn run_git_diff_for_file(&self, file: &str) -> Result<String> {
-        let output = Command::new("git")
-            .args(["diff", file])
-            .current_dir(&self.directory)
-            .output()?;
+/// Adds "(3m 12s ago)" after the time in each `=== Update N at HH:MM:SS ===`
+/// header of the history view. Ages are worked out from the history entries
+/// every time, since they keep growing.
+fn with_update_ages(
+    diff: &str,
+    history: &VecDeque<DiffEntry>,
+    now: chrono::DateTime<Utc>,
+) -> String {
+    diff.lines()
+        .map(|line| {
+            let entry = line
+                .strip_prefix("=== Update ")
+                .and_then(|rest| rest.split_once(' '))
+                .and_then(|(number, _)| number.parse::<usize>().ok())
+                .and_then(|number| history.get(number.checked_sub(1)?));
+            match (entry, line.split_once(" ===")) {
+                (Some(entry), Some((header, rest))) => format!(
+                    "{header} ({} ago) ==={rest}\n",
+                    dashboard::format_elapsed(now - entry.timestamp)
+                ),
+                _ => format!("{line}\n"),
+            }
+        })
+        .collect()
+}
 
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Git diff failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+/// Takes the output of `git diff --stat --summary` and drops the summary's
+/// create/delete/rename/mode lines, turning the "file | 0" stat line of a file
+/// whose mode is all that changed into "file | mode 100644 → 100755".
+fn mark_mode_changes(output: &str) -> String {
+    let mut mode_changes = HashMap::new();
+    let mut stat = Vec::new();
+    for line in output.lines() {
+        if line.contains('|') {
+            stat.push(line.to_string());
+        } else if let Some(change) = line.strip_prefix(" mode change ") {
+            // "100644 => 100755 path"
+            let mut parts = change.splitn(4, ' ');
+            if let (Some(old), Some("=>"), Some(new), Some(file)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            {
+                mode_changes.insert(file.to_string(), format!("{old} → {new}"));
+            }
+        } else if ![
+            " create mode ",
+            " delete mode ",
+            " rename ",
+            " copy ",
+            " rewrite ",
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+        {
+            stat.push(line.to_string());
         }
+    }
+
+    stat.iter()
+        .map(|line| {
+            let mode_only = line.split_once('|').and_then(|(file, changes)| {
+                let mode = mode_changes.get(file.trim())?;
+                (changes.trim() == "0").then(|| format!("{file}| mode {mode}"))
+            });
+            mode_only.unwrap_or_else(|| line.clone()) + "\n"
+        })
+        .collect()
+}
+
+/// Tells the changed lines of `combined` (HEAD against the working tree) apart
+/// using the `staged` (HEAD against the index) and `unstaged` (index against the
+/// working tree) diffs of the same file: an added line is unstaged if the unstaged
+/// diff adds it, a removed line is staged if the staged diff removes it. Returns
+/// whether each changed line is staged, keyed by line index.
+fn split_staged_lines(combined: &str, staged: &str, unstaged: &str) -> HashMap<usize, bool> {
+    let is_added = |line: &str| line.starts_with('+') && !line.starts_with("+++");
+    let is_removed = |line: &str| line.starts_with('-') && !line.starts_with("---");
+
+    let removed_by_staged: HashSet<usize> = staged
+        .lines()
+        .zip(diff_line_numbers(staged))
+        .filter(|(line, _)| is_removed(line))
+        .filter_map(|(_, (old, _))| old)
+        .collect();
+    let added_by_unstaged: HashSet<usize> = unstaged
+        .lines()
+        .zip(diff_line_numbers(unstaged))
+        .filter(|(line, _)| is_added(line))
+        .filter_map(|(_, (_, new))| new)
+        .collect();
+
+    combined
+        .lines()
+        .zip(diff_line_numbers(combined))
+        .enumerate()
+        .filter_map(|(i, (line, (old, new)))| {
+            if is_added(line) {
+                Some((i, !added_by_unstaged.contains(&new?)))
+            } else if is_removed(line) {
+                Some((i, removed_by_staged.contains(&old?)))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
 
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// `output` of git diff for `file`, or a note that there's nothing to show.
+fn diff_or_no_changes(file: &str, output: String) -> String {
+    if output.trim().is_empty() {
+        format!(
+            "No changes in {file}\n\nThis file may have been staged or the changes may be minimal."
+        )
+    } else {
+        output
     }
+}
 
-    async fn get_changed_files(&self) -> Result<Vec<String>> {
-        let output = Command::new("git")
-            .args(["diff", "--name-only"])
-            .current_dir(&self.directory)
-            .output()?;
+/// `path` relative to the directory `base`, going up with `..` where they part.
+fn path_relative_to(base: &Path, path: &Path) -> String {
+    let base: Vec<_> = base.components().collect();
+    let path: Vec<_> = path.components().collect();
+    let common = base
+        .iter()
+        .zip(&path)
+        .take_while(|(base, path)| base == path)
+        .count();
+    let mut parts = vec!["..".to_string(); base.len() - common];
+    parts.extend(
+        path[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
 
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Git diff --name-only failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
+/// New path of a file listed by `git diff --stat` as a rename, either
+/// `old => new` or with the changed part in braces, `src/{old => new}/lib.rs`.
+fn stat_rename_target(listed: &str) -> Option<String> {
+    if let Some((head, rest)) = listed.split_once('{') {
+        let (renamed, tail) = rest.split_once('}')?;
+        let (_, new) = renamed.split_once(" => ")?;
+        // An empty side leaves a doubled separator, e.g. "src/{old => }/lib.rs"
+        return Some(format!("{head}{new}{tail}").replace("//", "/"));
+    }
+    listed.split_once(" => ").map(|(_, new)| new.to_string())
+}
 
-        let files = String::from_utf8_lossy(&output.stdout);
-        let files: Vec<String> = files.trim().lines().map(|s| s.to_string()).collect();
+/// " ×5" after a file that changed several times this session, to point out
+/// the files worked on the most.
+fn change_badge(change_counts: &HashMap<String, usize>, file: &str) -> Option<Span<'static>> {
+    let count = *change_counts.get(file)?;
+    (count > 1).then(|| Span::styled(format!(" ×{count}"), Style::default().fg(Color::LightCyan)))
+}
 
-        // Update file modification times
-        self.update_file_times(&files);
+/// Shows a desktop notification through the platform's notification service:
+/// D-Bus on Linux and the BSDs, Notification Center on macOS and toasts on
+/// Windows. Nothing is shown when that fails, e.g. with no notification daemon.
+async fn send_desktop_notification(summary: &'static str, body: String) {
+    // Talking to the notification service blocks
+    let _ = tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .summary(summary)
+            .body(&body)
+            .show()
+            .is_ok()
+    })
+    .await;
+}
 
-        Ok(files)
+/// Clipboard commands for the platform, as (program, arguments), tried in order
+/// until one of them works.
+fn clipboard_commands() -> &'static [(&'static str, &'static [&'static str])] {
+    if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
     }
+}
 
-    fn update_file_times(&self, files: &[String]) {
-        let mut state = self.state.lock().unwrap();
+/// Puts `text` on the system clipboard through the first clipboard command that
+/// works.
+async fn copy_to_clipboard(text: &str) -> Result<()> {
+    use std::process::Stdio;
+    use tokio::io::AsyncWriteExt;
+
+    for (program, args) in clipboard_commands() {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        if child.wait().await?.success() {
+            return Ok(());
+        }
+    }
+    let programs: Vec<&str> = clipboard_commands()
+        .iter()
+        .map(|(program, _)| *program)
+        .collect();
+    Err(anyhow::anyhow!(
+        "no clipboard command worked (tried {})",
+        programs.join(", ")
+    ))
+}
 
-        for file in files {
-            let file_path = self.directory.join(file);
-            if let Ok(metadata) = fs::metadata(&file_path) {
-                if let Ok(modified) = metadata.modified() {
-                    let file_info = FileInfo {
-                        last_modified: modified,
-                    };
-                    state.file_info.insert(file.clone(), file_info);
-                }
-            }
+/// Labels each directory with its name, numbering repeated names so labels stay
+/// unique.
+fn repos_for_directories(directories: &[PathBuf]) -> Vec<Repo> {
+    let mut repos: Vec<Repo> = Vec::new();
+    for directory in directories {
+        let name = fs::canonicalize(directory)
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "repo".to_string());
+        let mut label = name.clone();
+        let mut suffix = 2;
+        while repos.iter().any(|repo| repo.label == label) {
+            label = format!("{name}-{suffix}");
+            suffix += 1;
         }
+        repos.push(Repo {
+            label,
+            directory: directory.clone(),
+            git_dir: directory.join(".git"),
+            common_dir: directory.join(".git"),
+            scope: None,
+        });
     }
+    repos
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -875,107 +5682,944 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-async fn setup_file_watcher(directory: PathBuf, app_state: Arc<Mutex<AppState>>) -> Result<()> {
-    let (tx, mut rx) = mpsc::channel(100);
-    let mut debounce_map: HashMap<PathBuf, Instant> = HashMap::new();
+/// Loads the last `limit` entries (all of them for 0) of a JSONL history file, or
+/// nothing if it doesn't exist yet.
+fn read_history_file(path: &Path, limit: usize) -> Result<VecDeque<DiffEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<VecDeque<DiffEntry>, _>>()?;
+    if limit > 0 {
+        let excess = entries.len().saturating_sub(limit);
+        entries.drain(..excess);
+    }
+    Ok(entries)
+}
+
+fn append_history_file(path: &Path, entry: &DiffEntry) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+fn write_history_file(path: &Path, entries: &VecDeque<DiffEntry>) -> Result<()> {
+    let mut content = String::new();
+    for entry in entries {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Number of watcher events between sweeps of stale debounce entries.
+const DEBOUNCE_PRUNE_EVERY: usize = 100;
+
+fn prune_debounce_map(
+    debounce_map: &mut HashMap<PathBuf, Instant>,
+    now: Instant,
+    max_age: Duration,
+) {
+    debounce_map.retain(|_, last_time| now.duration_since(*last_time) <= max_age);
+}
+
+/// What a watcher event path inside a repository refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchedPath {
+    /// A file in the working tree
+    WorkingTree,
+    /// HEAD, the index, a branch or remote-tracking ref, the stash or the state of
+    /// an operation in progress, which change on commit, checkout, reset, fetch,
+    /// stash, merge and the like
+    GitOperation,
+    /// Anything else in the git directories, like objects and logs
+    GitInternal,
+}
+
+impl WatchedPath {
+    /// What `path` is to `repo`, or `None` if it's neither in the working tree
+    /// nor in the repository's git directories.
+    fn classify(repo: &Repo, path: &Path) -> Option<Self> {
+        let git_path = repo_relative_path(&repo.git_dir, path);
+        let common_path = repo_relative_path(&repo.common_dir, path);
+        if git_path.is_none() && common_path.is_none() {
+            return repo_relative_path(&repo.directory, path).map(|_| WatchedPath::WorkingTree);
+        }
+        let is_state = git_path.as_deref().is_some_and(|git_path| {
+            git_path == "HEAD"
+                || git_path == "index"
+                || OPERATIONS_IN_PROGRESS.iter().any(|(marker, _)| {
+                    git_path
+                        .strip_prefix(marker)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+                })
+        });
+        let is_ref = common_path.as_deref().is_some_and(|common_path| {
+            common_path.starts_with("refs/heads/")
+                || common_path.starts_with("refs/remotes/")
+                || common_path == "refs/stash"
+        });
+        Some(if is_state || is_ref {
+            WatchedPath::GitOperation
+        } else {
+            WatchedPath::GitInternal
+        })
+    }
+}
+
+/// Whether `path` matches any of the --ignore patterns. Paths inside a watched
+/// repository are matched relative to it, anything else by its full path.
+fn is_ignored_path(ignore: &[Regex], repos: &[Repo], path: &Path) -> bool {
+    if ignore.is_empty() {
+        return false;
+    }
+    let relative = repos
+        .iter()
+        .find_map(|repo| repo_relative_path(&repo.directory, path))
+        .unwrap_or_else(|| path.to_string_lossy().into_owned());
+    ignore.iter().any(|pattern| pattern.is_match(&relative))
+}
+
+/// How often the --json-state file is checked for changes.
+const STATE_FILE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Rewrites the --json-state file whenever the snapshot of the state changes.
+async fn write_state_file(handle: AppHandle, path: PathBuf) {
+    let mut written: Option<StateSnapshot> = None;
+    loop {
+        let snapshot = StateSnapshot::of(&handle.state.lock().unwrap());
+        if written.as_ref() != Some(&snapshot) {
+            match state_file::write(&path, &snapshot) {
+                Ok(()) => written = Some(snapshot),
+                Err(e) => {
+                    // Try again next time, but don't repeat the message every tick
+                    if written.is_some() {
+                        handle.show_status_message(format!(
+                            "Failed to write {}: {e}",
+                            path.display()
+                        ));
+                    }
+                    written = None;
+                }
+            }
+        }
+        sleep(STATE_FILE_INTERVAL).await;
+    }
+}
+
+/// Watcher events queued before further ones are dropped and coalesced into a
+/// full reload.
+const WATCHER_CHANNEL_CAPACITY: usize = 100;
+
+/// Shortest wait before the reload that makes up for dropped watcher events.
+const OVERFLOW_RELOAD_DELAY: Duration = Duration::from_millis(500);
+
+/// Passes a watcher event on to the watcher loop, counting it in `dropped`
+/// instead when the channel is full.
+fn forward_watcher_event(
+    tx: &mpsc::Sender<NotifyEvent>,
+    dropped: &AtomicUsize,
+    event: NotifyEvent,
+) {
+    // Blocking here would stall the watcher thread during a burst like a
+    // checkout; dropped events are made up for by a full reload instead
+    match tx.try_send(event) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(e) => eprintln!("Error sending file event: {e}"),
+    }
+}
+
+/// Adds `dropped` events to the burst started within the last `delay`, or
+/// starts a new burst. Returns whether the burst is new, and so needs a
+/// reload, and how many events it has dropped in all.
+fn coalesce_dropped_events(
+    overflow: &mut Option<(Instant, usize)>,
+    dropped: usize,
+    now: Instant,
+    delay: Duration,
+) -> (bool, usize) {
+    let (new_burst, started, total) = match *overflow {
+        Some((started, total)) if now.duration_since(started) < delay => {
+            (false, started, total + dropped)
+        }
+        _ => (true, now, dropped),
+    };
+    *overflow = Some((started, total));
+    (new_burst, total)
+}
 
+async fn setup_file_watcher(handle: AppHandle) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel(WATCHER_CHANNEL_CAPACITY);
+    let mut debounce_map: HashMap<PathBuf, Instant> = HashMap::new();
+    // Git operations touch several files at once, so they're debounced per repository
+    let mut git_debounce_map: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut events_since_prune = 0usize;
+    // Events the watcher thread dropped because the channel was full
+    let dropped_events = Arc::new(AtomicUsize::new(0));
+    // When the last reload for dropped events was started, and how many were
+    // dropped since
+    let mut overflow: Option<(Instant, usize)> = None;
+
+    let dropped = dropped_events.clone();
     let mut watcher = RecommendedWatcher::new(
         move |res: Result<NotifyEvent, notify::Error>| {
             if let Ok(event) = res {
-                if let Err(e) = tx.blocking_send(event) {
-                    eprintln!("Error sending file event: {e}");
-                }
+                forward_watcher_event(&tx, &dropped, event);
             }
         },
         notify::Config::default(),
     )?;
 
-    watcher.watch(&directory, RecursiveMode::Recursive)?;
-
-    // Create app instance for handling file changes
-    let app = App::new(directory);
-    let app_state_clone = app_state.clone();
+    // --paths-from paths that are being watched, and all the listed ones
+    let mut watched_paths = HashSet::new();
+    let mut listed_paths = handle.options.watch_paths.clone();
+    let max_depth = handle.options.max_depth;
+    match (&listed_paths, max_depth) {
+        // The recursive watch covers .git/HEAD, .git/index and .git/refs/heads/ too,
+        // unless the git directory is elsewhere
+        (None, None) => {
+            for repo in handle.repos.iter() {
+                watcher
+                    .watch(&repo.directory, RecursiveMode::Recursive)
+                    .map_err(watch_error)?;
+            }
+            watch_git_metadata(&mut watcher, &handle, true)?;
+        }
+        (None, Some(depth)) => {
+            for repo in handle.repos.iter() {
+                watch_directory_tree(&mut watcher, &repo.directory, depth)?;
+            }
+            watch_git_metadata(&mut watcher, &handle, false)?;
+        }
+        (Some(paths), _) => {
+            let missing = watch_listed_paths(&mut watcher, paths, max_depth, &mut watched_paths)?;
+            if missing > 0 {
+                handle.show_status_message(format!("{missing} listed paths don't exist"));
+            }
+            watch_git_metadata(&mut watcher, &handle, false)?;
+        }
+    }
+    // Editors often save by replacing the file, so the list is watched through
+    // its directory
+    if let Some(parent) = handle.options.paths_file.as_deref().and_then(Path::parent) {
+        watcher
+            .watch(parent, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+    }
 
     while let Some(event) = rx.recv().await {
+        let dropped = dropped_events.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let delay = handle.options.debounce.max(OVERFLOW_RELOAD_DELAY);
+            let (new_burst, total) =
+                coalesce_dropped_events(&mut overflow, dropped, Instant::now(), delay);
+            if new_burst {
+                // Reload everything once the burst has settled
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    if let Err(e) = handle.reload().await {
+                        eprintln!("Error refreshing after dropped file events: {e}");
+                    }
+                });
+            }
+            handle.show_status_message(format!("{total} file events coalesced"));
+        }
+
         if let Some(path) = event.paths.first() {
             let path_clone = path.clone();
             let now = Instant::now();
 
-            // Debounce: only process if it's been more than 1 second since last event for this path
-            if let Some(last_time) = debounce_map.get(&path_clone) {
-                if now.duration_since(*last_time) < Duration::from_secs(1) {
-                    continue;
+            // Directories created within --max-depth need watches of their own
+            if let (Some(max_depth), notify::EventKind::Create(_)) = (max_depth, event.kind) {
+                let roots = listed_paths.as_ref().map_or_else(
+                    || handle.repos.iter().map(|repo| &repo.directory).collect(),
+                    |paths| paths.iter().collect::<Vec<_>>(),
+                );
+                let depth = roots
+                    .iter()
+                    .filter_map(|root| path.strip_prefix(root).ok())
+                    .filter(|relative| !relative.starts_with(".git"))
+                    .map(|relative| relative.components().count())
+                    .min();
+                if let Some(depth) = depth.filter(|depth| *depth <= max_depth && path.is_dir()) {
+                    if let Err(e) = watch_directory_tree(&mut watcher, path, max_depth - depth) {
+                        handle.show_error(format!("File watcher error: {e}"));
+                    }
+                }
+            }
+
+            if handle.options.paths_file.as_ref() == Some(path) {
+                let reread =
+                    fs::File::open(path).and_then(|file| read_path_list(BufReader::new(file)));
+                match reread {
+                    Ok(paths) => {
+                        for removed in watched_paths
+                            .iter()
+                            .filter(|watched| !paths.contains(watched))
+                        {
+                            let _ = watcher.unwatch(removed);
+                        }
+                        watched_paths.retain(|watched| paths.contains(watched));
+                        match watch_listed_paths(
+                            &mut watcher,
+                            &paths,
+                            max_depth,
+                            &mut watched_paths,
+                        ) {
+                            Ok(missing) => {
+                                let mut message = format!("Watching {} listed paths", paths.len());
+                                if missing > 0 {
+                                    message.push_str(&format!(" ({missing} don't exist)"));
+                                }
+                                handle.show_status_message(message);
+                            }
+                            Err(e) => handle.show_error(format!("File watcher error: {e}")),
+                        }
+                        listed_paths = Some(paths);
+                    }
+                    Err(e) => handle.show_error(format!("Failed to re-read --paths-from: {e}")),
+                }
+                continue;
+            }
+
+            // --ignore patterns; gitignored files are dropped later since git doesn't list them
+            if is_ignored_path(&handle.options.ignore, &handle.repos, path)
+                || handle
+                    .options
+                    .json_state
+                    .as_ref()
+                    .is_some_and(|state_path| state_file::is_state_file(state_path, path))
+            {
+                continue;
+            }
+
+            if let DiffMode::Git = handle.options.mode {
+                let watched = handle.repos.iter().find_map(|repo| {
+                    Some((repo.directory.clone(), WatchedPath::classify(repo, path)?))
+                });
+                match watched {
+                    Some((_, WatchedPath::GitInternal)) => continue,
+                    Some((directory, WatchedPath::GitOperation)) => {
+                        // A commit, checkout or reset: reload everything once it settles
+                        if git_debounce_map.get(&directory).is_some_and(|last_time| {
+                            now.duration_since(*last_time) < handle.options.debounce
+                        }) {
+                            continue;
+                        }
+                        git_debounce_map.insert(directory, now);
+                        let handle = handle.clone();
+                        tokio::spawn(async move {
+                            if !handle.options.debounce.is_zero() {
+                                sleep(handle.options.debounce).await;
+                            }
+                            if let Err(e) = handle.reload().await {
+                                eprintln!("Error refreshing after git operation: {e}");
+                            }
+                        });
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            // With --paths-from, changes anywhere else don't count
+            if listed_paths
+                .as_ref()
+                .is_some_and(|paths| !paths.iter().any(|listed| path.starts_with(listed)))
+            {
+                continue;
+            }
+
+            // Debounce: skip events arriving within the debounce interval for the same path
+            if !handle.options.debounce.is_zero() {
+                if let Some(last_time) = debounce_map.get(&path_clone) {
+                    if now.duration_since(*last_time) < handle.options.debounce {
+                        continue;
+                    }
+                }
+            }
+
+            debounce_map.insert(path_clone.clone(), now);
+
+            // Periodically forget paths that have been quiet for a while so the map doesn't grow forever
+            events_since_prune += 1;
+            if events_since_prune >= DEBOUNCE_PRUNE_EVERY {
+                events_since_prune = 0;
+                prune_debounce_map(&mut debounce_map, now, handle.options.debounce * 10);
+            }
+
+            // Handle the file change; if a newer change to the same path gets
+            // through during --change-delay-ms, only that one refreshes
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle.handle_file_change(&path_clone).await {
+                    eprintln!("Error handling file change: {e}");
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a --paths-from list: one path per line, skipping blank lines and `#`
+/// comments. Relative paths are taken from the current directory.
+fn read_path_list(reader: impl BufRead) -> io::Result<Vec<PathBuf>> {
+    let current_dir = std::env::current_dir()?;
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            paths.push(current_dir.join(line));
+        }
+    }
+    Ok(paths)
+}
+
+/// Watches the --paths-from paths that aren't watched yet, directories
+/// recursively down to `max_depth`, and adds them to `watched`. Returns how
+/// many don't exist.
+fn watch_listed_paths(
+    watcher: &mut RecommendedWatcher,
+    paths: &[PathBuf],
+    max_depth: Option<usize>,
+    watched: &mut HashSet<PathBuf>,
+) -> Result<usize> {
+    let mut missing = 0;
+    for path in paths {
+        if watched.contains(path) {
+            continue;
+        }
+        if !path.exists() {
+            missing += 1;
+            continue;
+        }
+        match max_depth {
+            Some(depth) if path.is_dir() => watch_directory_tree(watcher, path, depth)?,
+            _ if path.is_dir() => watcher
+                .watch(path, RecursiveMode::Recursive)
+                .map_err(watch_error)?,
+            _ => watcher
+                .watch(path, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?,
+        }
+        watched.insert(path.clone());
+    }
+    Ok(missing)
+}
+
+/// Watches `directory` and its subdirectories down to `depth` levels below it,
+/// one directory at a time, leaving out .git.
+fn watch_directory_tree(
+    watcher: &mut RecommendedWatcher,
+    directory: &Path,
+    depth: usize,
+) -> Result<()> {
+    watcher
+        .watch(directory, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+    if depth == 0 {
+        return Ok(());
+    }
+    // Directories can vanish while they're walked; whatever is left still gets watched
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        // The entry's own type, so symlinked directories aren't followed
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) && entry.file_name() != ".git" {
+            watch_directory_tree(watcher, &entry.path(), depth - 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Watches what changes in the git directories on a commit, checkout or the
+/// like. With `recursive`, the repositories are watched recursively already,
+/// so only git directories outside them, as worktrees and submodules have,
+/// are added.
+fn watch_git_metadata(
+    watcher: &mut RecommendedWatcher,
+    handle: &AppHandle,
+    recursive: bool,
+) -> Result<()> {
+    if let DiffMode::Snapshot(_) = handle.options.mode {
+        return Ok(());
+    }
+    for repo in handle.repos.iter() {
+        let directory = fs::canonicalize(&repo.directory).unwrap_or(repo.directory.clone());
+        let watched = |path: &Path| recursive && path.starts_with(&directory);
+        if repo.git_dir.is_dir() && !watched(&repo.git_dir) {
+            watcher
+                .watch(&repo.git_dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+        let refs = repo.common_dir.join("refs");
+        if refs.is_dir() && !watched(&refs) {
+            watcher
+                .watch(&refs, RecursiveMode::Recursive)
+                .map_err(watch_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// A watcher error, with advice when the system ran out of watches.
+fn watch_error(error: notify::Error) -> anyhow::Error {
+    match error.kind {
+        notify::ErrorKind::MaxFilesWatch => anyhow::anyhow!(
+            "inotify watch limit reached. Raise it with `sudo sysctl fs.inotify.max_user_watches=524288` \
+             (add it to /etc/sysctl.conf to keep it), or watch less with --max-depth or --paths-from"
+        ),
+        _ => error.into(),
+    }
+}
+
+/// Writes --export-on-quit and removes the --socket file when WatchHound exits.
+fn finish_session(handle: &AppHandle, socket: Option<&Path>) {
+    if let Some(path) = &handle.options.export_on_quit {
+        let entries = Vec::from(handle.state.lock().unwrap().diff_history.clone());
+        if let Err(e) = export::export_history(path, &entries) {
+            eprintln!("Failed to export history to {}: {e}", path.display());
+        }
+    }
+
+    if let Some(path) = socket {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Exit codes of --once, so scripts and pre-commit hooks can check for changes.
+const ONCE_EXIT_NO_CHANGES: i32 = 0;
+const ONCE_EXIT_CHANGES: i32 = 1;
+const ONCE_EXIT_ERROR: i32 = 2;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    // Scripts running --once tell errors apart from "there are changes"
+    let error_code = if args.once { ONCE_EXIT_ERROR } else { 1 };
+
+    // Make sure git can be run at all, so a missing install isn't reported as a
+    // cryptic error from inside the TUI
+    if !args.no_git {
+        if let Err(e) = std::process::Command::new(&args.git_path)
+            .arg("--version")
+            .output()
+        {
+            if e.kind() == io::ErrorKind::NotFound {
+                eprintln!("Error: {}", git_not_found_message(&args.git_path));
+            } else {
+                eprintln!("Error: Failed to run {}: {e}", args.git_path.display());
+            }
+            exit(error_code);
+        }
+    }
+
+    // Verify the directories exist and are git repositories BEFORE setting up terminal
+    for directory in &args.directories {
+        if !directory.exists() {
+            eprintln!("Error: Directory does not exist: {directory:?}");
+            eprintln!("Please specify a valid directory path.");
+            eprintln!("   Example: watchhound /path/to/your/git/repo");
+            eprintln!("   Or run from within a git repository: watchhound");
+            exit(error_code);
+        }
+
+        if !args.no_git && !is_git_work_tree(&args.git_path, directory) {
+            eprintln!("Error: Directory is not a git repository: {directory:?}");
+            eprintln!("Please navigate to a git repository or initialize one:");
+            eprintln!("   git init");
+            eprintln!("   git add .");
+            eprintln!("   git commit -m \"Initial commit\"");
+            eprintln!("Or watch it without git: watchhound --no-git <directory>");
+            exit(error_code);
+        }
+
+        if let Some(base) = &args.base {
+            let verified = std::process::Command::new(&args.git_path)
+                .args([
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{base}^{{commit}}"),
+                ])
+                .current_dir(directory)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false);
+            if !verified {
+                eprintln!("Error: --base {base:?} does not resolve to a commit in {directory:?}");
+                eprintln!(
+                    "Use a branch name, tag, or commit SHA, e.g. --base main or --base HEAD~1"
+                );
+                exit(error_code);
+            }
+        }
+    }
+
+    if args.no_git && args.directories.len() > 1 {
+        eprintln!("Error: --no-git watches a single directory");
+        exit(error_code);
+    }
+
+    if args.history_limit == 0 {
+        eprintln!(
+            "Warning: --history-limit 0 keeps every diff in memory for as long as WatchHound runs"
+        );
+    }
+
+    let filter = match args.filter.as_deref().map(Glob::new).transpose() {
+        Ok(filter) => filter.map(|glob| glob.compile_matcher()),
+        Err(e) => {
+            eprintln!("Error: Invalid --filter: {e}");
+            exit(error_code);
+        }
+    };
+
+    let ignore = match args
+        .ignore
+        .iter()
+        .map(|pattern| Regex::new(pattern))
+        .collect()
+    {
+        Ok(ignore) => ignore,
+        Err(e) => {
+            eprintln!("Error: Invalid --ignore pattern: {e}");
+            exit(error_code);
+        }
+    };
+
+    let color_level = match args.color {
+        ColorChoice::Auto => ColorLevel::detect(|name| std::env::var(name).ok()),
+        ColorChoice::Basic => ColorLevel::Basic,
+        ColorChoice::Indexed => ColorLevel::Indexed,
+        ColorChoice::Truecolor => ColorLevel::TrueColor,
+        ColorChoice::None => ColorLevel::None,
+    };
+
+    let whitespace_rules = match WhitespaceRules::parse(&args.whitespace_rules) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Error: Invalid --whitespace-rules: {e}");
+            exit(error_code);
+        }
+    };
+
+    if args.autocommit && args.no_git {
+        eprintln!("Error: --autocommit needs git and can't be combined with --no-git");
+        exit(error_code);
+    }
+
+    if args.watch_only && args.once {
+        eprintln!("Error: --watch-only and --once can't be combined");
+        exit(error_code);
+    }
+
+    let paths_file = args
+        .paths_from
+        .as_ref()
+        .filter(|source| source.as_os_str() != "-")
+        .map(|file| std::env::current_dir().unwrap_or_default().join(file));
+    let watch_paths = match (&args.paths_from, &paths_file) {
+        (None, _) => None,
+        (Some(_), None) => Some(read_path_list(io::stdin().lock())),
+        (Some(_), Some(file)) => {
+            Some(fs::File::open(file).and_then(|file| read_path_list(BufReader::new(file))))
+        }
+    };
+    let watch_paths = match watch_paths.transpose() {
+        Ok(Some(paths)) if paths.is_empty() => {
+            eprintln!("Error: --paths-from lists no paths to watch");
+            exit(error_code);
+        }
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("Error: Failed to read --paths-from: {e}");
+            exit(error_code);
+        }
+    };
+
+    // --file watches just that file, and limits git to it further down
+    let followed_file = args
+        .file
+        .as_ref()
+        .map(|file| std::env::current_dir().unwrap_or_default().join(file));
+    if followed_file.is_some() && args.directories.len() > 1 {
+        eprintln!("Error: --file follows a file in a single repository");
+        exit(error_code);
+    }
+    let watch_paths = watch_paths.or_else(|| followed_file.clone().map(|file| vec![file]));
+
+    let mode = if args.no_git {
+        match Snapshot::capture(&args.directories[0]) {
+            Ok(snapshot) => DiffMode::Snapshot(Arc::new(snapshot)),
+            Err(e) => {
+                eprintln!("Error: Failed to snapshot {:?}: {e}", args.directories[0]);
+                exit(error_code);
+            }
+        }
+    } else {
+        DiffMode::Git
+    };
+
+    // The pane width set in the last session, unless --split overrides it
+    let config_file = config::default_path();
+    let config = match config_file
+        .as_deref()
+        .map(|path| (path, config::load(path)))
+    {
+        Some((_, Ok(config))) => config,
+        Some((path, Err(e))) => {
+            eprintln!("Ignoring config file {}: {e}", path.display());
+            config::Config::default()
+        }
+        None => config::Config::default(),
+    };
+    let (min_split, max_split) = SPLIT_PERCENT_RANGE;
+    let split_percent = args
+        .split
+        .or(config.split_percent)
+        .map_or(DEFAULT_SPLIT_PERCENT, |percent| {
+            percent.clamp(min_split, max_split)
+        });
+
+    // Create app
+    let options = Options {
+        include_staged: args.include_staged,
+        debounce: Duration::from_millis(args.debounce_ms),
+        change_delay: Duration::from_millis(args.change_delay_ms),
+        mode,
+        // --once prints the diff and leaves no history behind
+        history_file: if args.once {
+            None
+        } else {
+            args.history_file.clone()
+        },
+        history_limit: args.history_limit,
+        replay_interval: Duration::from_millis(args.replay_interval),
+        replay_loop: args.replay_loop,
+        wrap_navigation: args.wrap_navigation,
+        ignore_whitespace: args.ignore_whitespace,
+        context_lines: args.context,
+        detect_renames: !args.no_renames,
+        base: args.base.clone(),
+        filter,
+        ignore,
+        notify: args.notify,
+        confirm_quit: args.confirm_quit,
+        export_on_quit: args.export_on_quit.clone(),
+        fold_context: args.fold_context,
+        git_path: args.git_path.clone(),
+        whitespace_rules,
+        color_level,
+        // Printed diffs aren't rendered, so --once always shows them in full
+        max_diff_lines: if args.once { 0 } else { args.max_diff_lines },
+        autocommit: args.autocommit,
+        debug: args.debug,
+        events: args.socket.as_ref().map(|_| socket::channel()),
+        // Watcher events carry absolute paths, and the state file may be among them
+        max_depth: args.max_depth,
+        watch_paths,
+        paths_file,
+        json_state: args
+            .json_state
+            .as_ref()
+            .map(|path| std::env::current_dir().unwrap_or_default().join(path)),
+        diff_command: args.diff_command.clone(),
+        path_style: args.path_style,
+        cwd: std::env::current_dir()
+            .and_then(fs::canonicalize)
+            .unwrap_or_default(),
+        graphics: if args.no_images {
+            None
+        } else {
+            GraphicsProtocol::detect(|name| std::env::var(name).ok())
+        },
+        error_timeout: Duration::from_secs(args.error_timeout),
+        split_percent,
+        config_file,
+        compact: args.compact || followed_file.is_some(),
+        watch_only: args.watch_only,
+        once: args.once,
+    };
+    // Run git and watch from the top of each repository even when started in a
+    // subdirectory, which --scope-to-cwd then limits the changes to
+    let mut directories = args.directories.clone();
+    let mut scopes = vec![None; directories.len()];
+    if !args.no_git {
+        for (directory, scope) in directories.iter_mut().zip(scopes.iter_mut()) {
+            if let Some((toplevel, subdirectory)) = git_toplevel(&args.git_path, directory) {
+                if args.scope_to_cwd && !subdirectory.is_empty() {
+                    *scope = Some(subdirectory);
                 }
+                *directory = toplevel;
             }
+        }
+    }
+    if let Some(file) = &followed_file {
+        match repo_relative_path(&directories[0], file) {
+            Some(relative) if !relative.is_empty() && !relative.starts_with("..") => {
+                scopes[0] = Some(relative);
+            }
+            _ => {
+                eprintln!(
+                    "Error: --file {} is not inside the repository at {}",
+                    file.display(),
+                    directories[0].display()
+                );
+                exit(error_code);
+            }
+        }
+    }
+    let mut repos = repos_for_directories(&directories);
+    for (repo, scope) in repos.iter_mut().zip(scopes) {
+        repo.scope = scope;
+        if !args.no_git {
+            if let Some((git_dir, common_dir)) = git_dirs(&args.git_path, &repo.directory) {
+                repo.git_dir = git_dir;
+                repo.common_dir = common_dir;
+            }
+        }
+    }
 
-            debounce_map.insert(path_clone.clone(), now);
-
-            // Handle the file change
-            let mut app_clone = App::new(app.directory.clone());
-            app_clone.state = app_state_clone.clone();
+    let mut app = App::new(repos, options, args.title.clone());
+    let handle = app.handle.clone();
 
-            tokio::spawn(async move {
-                if let Err(e) = app_clone.handle_file_change(&path_clone).await {
-                    eprintln!("Error handling file change: {e}");
+    if args.once {
+        // Print and exit without touching the terminal
+        if let Err(e) = handle.load_initial_state().await {
+            eprintln!("Error loading initial state: {e}");
+            exit(error_code);
+        }
+        let color = handle.options.color_level != ColorLevel::None;
+        let full_diff = match handle.build_full_diff().await {
+            Ok(full_diff) => full_diff,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                exit(error_code);
+            }
+        };
+        if let Some(command) = &args.diff_command {
+            match run_diff_command(command, &full_diff, 0).await {
+                Ok(colored) => print!("{colored}"),
+                Err(e) => {
+                    eprintln!("Error: --diff-command failed: {e}");
+                    exit(error_code);
                 }
-            });
+            }
+        } else {
+            print!("{}", export::to_ansi(&full_diff, color));
         }
+        let has_changes = !handle.state.lock().unwrap().changed_files.is_empty();
+        exit(if has_changes {
+            ONCE_EXIT_CHANGES
+        } else {
+            ONCE_EXIT_NO_CHANGES
+        });
     }
 
-    Ok(())
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+    if let (Some(path), Some(events)) = (&args.socket, &handle.options.events) {
+        if let Err(e) = socket::serve(path, events) {
+            eprintln!("Error: Failed to listen on {}: {e}", path.display());
+            exit(1);
+        }
+    }
 
-    // Verify the directory exists and is a git repository BEFORE setting up terminal
-    if !args.directory.exists() {
-        eprintln!("Error: Directory does not exist: {:?}", args.directory);
-        eprintln!("Please specify a valid directory path.");
-        eprintln!("   Example: watchhound /path/to/your/git/repo");
-        eprintln!("   Or run from within a git repository: watchhound");
-        exit(1);
+    if args.autocommit {
+        // Committing on every save is easy to turn on by accident, so ask first
+        eprintln!("--autocommit will run `git add -A` and commit after every change in:");
+        for repo in handle.repos.iter() {
+            eprintln!("   {}", repo.directory.display());
+        }
+        eprint!("Continue? [y/N] ");
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            eprintln!("Not starting.");
+            exit(1);
+        }
     }
 
-    if !args.directory.join(".git").exists() {
-        eprintln!(
-            "Error: Directory is not a git repository: {:?}",
-            args.directory
-        );
-        eprintln!("Please navigate to a git repository or initialize one:");
-        eprintln!("   git init");
-        eprintln!("   git add .");
-        eprintln!("   git commit -m \"Initial commit\"");
-        exit(1);
+    if args.watch_only {
+        // Log changes until interrupted, without touching the terminal
+        if let Err(e) = handle.load_initial_state().await {
+            eprintln!("Error loading initial state: {e}");
+            exit(1);
+        }
+        if let Some(path) = handle.options.json_state.clone() {
+            tokio::spawn(write_state_file(handle.clone(), path));
+        }
+        let watcher_handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = setup_file_watcher(watcher_handle).await {
+                eprintln!("File watcher error: {e}");
+            }
+        });
+        for repo in handle.repos.iter() {
+            println!("Watching {}", repo.directory.display());
+        }
+        tokio::signal::ctrl_c().await?;
+        finish_session(&handle, args.socket.as_deref());
+        return Ok(());
     }
 
     // Setup terminal (only after validation)
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // Pastes come as one event instead of key presses, so pasted text can't
+    // trigger commands
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     // Set up panic handler to restore terminal
     std::panic::set_hook(Box::new(|_info| {
         let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste
+        );
         eprintln!("Application panicked! Terminal has been restored.");
         eprintln!("Please report this issue if it persists.");
     }));
 
-    // Create app
-    let mut app = App::new(args.directory.clone());
+    // The session scope diffs against HEAD as it is now
+    if let DiffMode::Git = handle.options.mode {
+        handle.record_session_bases().await;
+    }
 
     // Load initial state immediately
-    if let Err(e) = app.load_initial_state().await {
+    if let Err(e) = handle.load_initial_state().await {
         // Restore terminal before showing error
         disable_raw_mode()?;
         execute!(
             terminal.backend_mut(),
             LeaveAlternateScreen,
-            DisableMouseCapture
+            DisableMouseCapture,
+            DisableBracketedPaste
         )?;
         terminal.show_cursor()?;
 
@@ -985,12 +6629,16 @@ async fn main() -> Result<()> {
         exit(1);
     }
 
+    if let Some(path) = handle.options.json_state.clone() {
+        tokio::spawn(write_state_file(handle.clone(), path));
+    }
+
     // Start file watcher in background
-    let watcher_state = app.state.clone();
-    let watcher_directory = args.directory.clone();
+    let watcher_handle = handle.clone();
     tokio::spawn(async move {
-        if let Err(e) = setup_file_watcher(watcher_directory, watcher_state).await {
-            eprintln!("File watcher error: {e}");
+        if let Err(e) = setup_file_watcher(watcher_handle.clone()).await {
+            // Without a watcher nothing updates any more, so this has to be seen
+            watcher_handle.show_error(format!("File watcher error: {e}"));
         }
     });
 
@@ -998,66 +6646,430 @@ async fn main() -> Result<()> {
     let result = async {
         loop {
             terminal.draw(|f| app.render(f))?;
+            if app.image != app.shown_image {
+                app.draw_image(&mut terminal)?;
+            }
 
             // Handle input events
             if event::poll(Duration::from_millis(100))? {
-                if let Event::Key(key) = event::read()? {
-                    if key.kind == KeyEventKind::Press {
+                match event::read()? {
+                    // An open prompt takes all keys
+                    Event::Key(key)
+                        if key.kind == KeyEventKind::Press && handle.is_prompt_open() =>
+                    {
                         match key.code {
-                            KeyCode::Char('q') | KeyCode::Esc => {
+                            KeyCode::Enter => {
+                                if handle.submit_prompt() {
+                                    let handle = handle.clone();
+                                    tokio::spawn(async move {
+                                        handle.update_current_file_diff().await;
+                                    });
+                                }
+                            }
+                            KeyCode::Esc => {
+                                handle.cancel_prompt();
+                            }
+                            code => {
+                                handle.edit_prompt_input(code);
+                            }
+                        }
+                    }
+                    Event::Key(key)
+                        if key.kind == KeyEventKind::Press
+                            && handle.is_quit_confirmation_open() =>
+                    {
+                        match key.code {
+                            KeyCode::Char('y') | KeyCode::Char('Y') => {
                                 app.should_quit = true;
                             }
+                            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                                handle.cancel_quit();
+                            }
+                            _ => {}
+                        }
+                    }
+                    Event::Key(key)
+                        if key.kind == KeyEventKind::Press && handle.is_blame_open() =>
+                    {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('b') => {
+                                handle.close_blame();
+                            }
+                            KeyCode::Up => {
+                                handle.scroll_blame(-1);
+                            }
+                            KeyCode::Down => {
+                                handle.scroll_blame(1);
+                            }
+                            KeyCode::PageUp => {
+                                handle.scroll_blame(-10);
+                            }
+                            KeyCode::PageDown => {
+                                handle.scroll_blame(10);
+                            }
+                            _ => {}
+                        }
+                    }
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        match key.code {
+                            KeyCode::Esc if handle.dismiss_error() => {
+                                // Esc closes the error popup before it quits
+                            }
+                            KeyCode::Esc if handle.close_timeline() => {}
+                            KeyCode::Char('q') | KeyCode::Esc => {
+                                app.should_quit = !handle.request_quit_confirmation();
+                            }
                             KeyCode::Char('r') => {
                                 // Manual refresh
-                                let mut app_clone = App::new(app.directory.clone());
-                                app_clone.state = app.state.clone();
+                                let handle = handle.clone();
                                 tokio::spawn(async move {
-                                    if let Err(e) = app_clone.load_initial_state().await {
+                                    if let Err(e) = handle.reload().await {
                                         eprintln!("Error during manual refresh: {e}");
                                     }
                                 });
                             }
+                            KeyCode::Tab => {
+                                handle.toggle_focused_pane();
+                            }
+                            KeyCode::Up
+                            | KeyCode::Down
+                            | KeyCode::PageUp
+                            | KeyCode::PageDown
+                            | KeyCode::Home
+                            | KeyCode::End
+                                if handle.scroll_status_pane(key.code) => {}
+                            KeyCode::Up | KeyCode::Down if handle.is_tree_view() => {
+                                // Move through the tree, showing files as the cursor reaches them
+                                let rows = if key.code == KeyCode::Up { -1 } else { 1 };
+                                if handle.move_tree_cursor(rows) {
+                                    let handle = handle.clone();
+                                    tokio::spawn(async move {
+                                        handle.update_current_file_diff().await;
+                                    });
+                                }
+                            }
+                            KeyCode::Left
+                                if handle.is_tree_view()
+                                    && !key.modifiers.contains(KeyModifiers::SHIFT) =>
+                            {
+                                handle.collapse_tree_node();
+                            }
+                            KeyCode::Right
+                                if handle.is_tree_view()
+                                    && !key.modifiers.contains(KeyModifiers::SHIFT) =>
+                            {
+                                handle.expand_tree_node();
+                            }
+                            KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                                handle.scroll_horizontally(-HORIZONTAL_SCROLL_COLUMNS);
+                            }
+                            KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                                handle.scroll_horizontally(HORIZONTAL_SCROLL_COLUMNS);
+                            }
                             KeyCode::Left => {
-                                app.navigate_to_previous_file();
-                                let mut app_clone = App::new(app.directory.clone());
-                                app_clone.state = app.state.clone();
+                                handle.navigate_to_previous_file();
+                                let handle = handle.clone();
                                 tokio::spawn(async move {
-                                    app_clone.update_current_file_diff().await;
+                                    handle.update_current_file_diff().await;
                                 });
                             }
                             KeyCode::Right => {
-                                app.navigate_to_next_file();
-                                let mut app_clone = App::new(app.directory.clone());
-                                app_clone.state = app.state.clone();
+                                handle.navigate_to_next_file();
+                                let handle = handle.clone();
                                 tokio::spawn(async move {
-                                    app_clone.update_current_file_diff().await;
+                                    handle.update_current_file_diff().await;
                                 });
                             }
+                            KeyCode::Char(' ') if handle.toggle_replay_pause() => {}
                             KeyCode::Char(' ') => {
-                                app.scroll_down();
+                                handle.scroll_down();
+                            }
+                            KeyCode::Enter if handle.is_tree_view() && handle.toggle_tree_dir() => {
+                                // In the tree Enter toggles the folder under the cursor
+                            }
+                            KeyCode::Enter => {
+                                handle.expand_focused_fold();
+                            }
+                            KeyCode::Char('}') => {
+                                handle.jump_to_next_hunk();
+                            }
+                            KeyCode::Char('{') => {
+                                handle.jump_to_previous_hunk();
+                            }
+                            KeyCode::Char(']') if handle.move_timeline(1) => {}
+                            KeyCode::Char('[') if handle.move_timeline(-1) => {}
+                            KeyCode::Char(']') => {
+                                handle.jump_to_next_conflict();
+                            }
+                            KeyCode::Char('[') => {
+                                handle.jump_to_previous_conflict();
                             }
                             KeyCode::Up => {
-                                app.scroll_up_fast();
+                                handle.scroll_up_fast();
                             }
                             KeyCode::Down => {
-                                app.scroll_down_fast();
+                                handle.scroll_down_fast();
+                            }
+                            KeyCode::PageUp => {
+                                handle.page_up();
+                            }
+                            KeyCode::PageDown => {
+                                handle.page_down();
+                            }
+                            KeyCode::Home => {
+                                handle.scroll_to_top();
+                            }
+                            KeyCode::End => {
+                                handle.scroll_to_bottom();
+                            }
+                            KeyCode::Char('b') => {
+                                // Blame the current hunk in a popup
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.show_blame().await;
+                                });
+                            }
+                            KeyCode::Char('g') => {
+                                handle.open_jump_prompt();
+                            }
+                            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                handle.open_jump_prompt();
+                            }
+                            KeyCode::Char('p') => {
+                                // Pin or unpin the view to the current file
+                                handle.toggle_pin();
                             }
                             KeyCode::Char('c') => {
                                 // Clear diff history
-                                app.clear_diff_history();
+                                handle.clear_diff_history();
+                            }
+                            KeyCode::Char('s') => {
+                                // Toggle side-by-side diff layout
+                                handle.toggle_side_by_side();
+                            }
+                            KeyCode::Char('n') => {
+                                // Toggle the line number gutter
+                                handle.toggle_line_numbers();
+                            }
+                            KeyCode::Char('l') => {
+                                // Toggle syntax highlighting by file type
+                                handle.toggle_syntax_highlight();
+                            }
+                            KeyCode::Char('d') => {
+                                // Toggle word-level highlighting of changed lines
+                                handle.toggle_word_diff();
+                            }
+                            KeyCode::Char('e') => {
+                                // Toggle highlighting of whitespace errors in added lines
+                                handle.toggle_whitespace_errors();
+                            }
+                            KeyCode::Char('W') => {
+                                // Toggle wrapping long lines vs scrolling sideways
+                                handle.toggle_wrap();
+                            }
+                            KeyCode::Char('+') | KeyCode::Char('-') => {
+                                // More or less context around changes; re-fetch the diff
+                                let delta = if key.code == KeyCode::Char('+') {
+                                    1
+                                } else {
+                                    -1
+                                };
+                                handle.adjust_context_lines(delta);
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.update_current_file_diff().await;
+                                });
+                            }
+                            KeyCode::Char('w') => {
+                                // Toggle ignoring whitespace-only changes and re-diff
+                                handle.toggle_ignore_whitespace();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.refresh_git_stat().await;
+                                    handle.update_current_file_diff().await;
+                                });
+                            }
+                            KeyCode::Char('m') => {
+                                // Toggle rename detection; renames change the file list,
+                                // so reload everything
+                                handle.toggle_detect_renames();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = handle.reload().await {
+                                        eprintln!("Error reloading after rename toggle: {e}");
+                                    }
+                                });
+                            }
+                            KeyCode::Char('t') if matches!(handle.options.mode, DiffMode::Git) => {
+                                // Cycle working tree / staged / all; the file list changes
+                                // with the scope, so reload everything
+                                handle.cycle_diff_scope();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = handle.reload().await {
+                                        eprintln!("Error reloading after scope change: {e}");
+                                    }
+                                });
+                            }
+                            KeyCode::Char('T') => {
+                                handle.toggle_tree_view();
+                            }
+                            KeyCode::Char('C') => {
+                                handle.toggle_compact();
+                            }
+                            KeyCode::Char('<') => {
+                                handle.resize_split(-SPLIT_STEP_PERCENT);
+                            }
+                            KeyCode::Char('>') => {
+                                handle.resize_split(SPLIT_STEP_PERCENT);
+                            }
+                            KeyCode::Char('B') if matches!(handle.options.mode, DiffMode::Git) => {
+                                // Start the session scope over from the current HEAD
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = handle.reset_session_bases().await {
+                                        eprintln!("Error reloading after session base reset: {e}");
+                                    }
+                                });
+                            }
+                            KeyCode::Char('a') | KeyCode::Char('u') => {
+                                let stage = key.code == KeyCode::Char('a');
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = handle.stage_current_file(stage).await {
+                                        handle
+                                            .state
+                                            .lock()
+                                            .unwrap()
+                                            .set_error(format!("Staging failed: {e}"));
+                                    }
+                                });
+                            }
+                            KeyCode::Char('o') => {
+                                // Cycle the file order; the selected file stays the same
+                                handle.cycle_sort_mode();
+                            }
+                            KeyCode::Char('f') => {
+                                handle.open_filter_prompt();
+                            }
+                            KeyCode::Char('S') => {
+                                // All, staged-only or unstaged-only files
+                                handle.cycle_status_filter();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.update_current_file_diff().await;
+                                });
+                            }
+                            KeyCode::Char('E') => {
+                                handle.open_export_prompt();
+                            }
+                            KeyCode::Char('F') => {
+                                // Whole file with changes in place, or hunks only
+                                handle.toggle_whole_file();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.update_current_file_diff().await;
+                                });
+                            }
+                            KeyCode::Char('X') if handle.load_full_diff() => {
+                                // Load the rest of a truncated diff
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.update_current_file_diff().await;
+                                });
                             }
                             KeyCode::Char('h') => {
                                 // Toggle history view
-                                app.toggle_history_view();
-                                let mut app_clone = App::new(app.directory.clone());
-                                app_clone.state = app.state.clone();
+                                handle.toggle_history_view();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.refresh_display().await;
+                                });
+                            }
+                            KeyCode::Char('O') => {
+                                handle.toggle_history_order();
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.refresh_display().await;
+                                });
+                            }
+                            KeyCode::Char('H') => {
+                                handle.toggle_timeline();
+                            }
+                            KeyCode::Char('Y') => {
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.copy_file_path().await;
+                                });
+                            }
+                            KeyCode::Char('y') => {
+                                let handle = handle.clone();
+                                tokio::spawn(async move {
+                                    handle.copy_current_hunk().await;
+                                });
+                            }
+                            KeyCode::Char('R') => {
+                                handle.toggle_replay();
+                            }
+                            KeyCode::Char('v') => {
+                                // Cycle file view, history view and dashboard
+                                handle.cycle_view();
+                                let handle = handle.clone();
                                 tokio::spawn(async move {
-                                    app_clone.refresh_display().await;
+                                    handle.refresh_display().await;
                                 });
                             }
                             _ => {}
                         }
                     }
+                    Event::Paste(text) => {
+                        handle.paste_into_prompt(&text);
+                    }
+                    Event::Resize(_, _) => {
+                        app.handle_resize(&mut terminal)?;
+                    }
+                    Event::Mouse(mouse) => {
+                        let (status_pane, diff_pane) = handle.state.lock().unwrap().pane_areas;
+                        let position = Rect::new(mouse.column, mouse.row, 1, 1);
+                        match mouse.kind {
+                            MouseEventKind::ScrollDown if diff_pane.intersects(position) => {
+                                handle.scroll_down_by(MOUSE_SCROLL_LINES);
+                            }
+                            MouseEventKind::ScrollUp if diff_pane.intersects(position) => {
+                                handle.scroll_up_by(MOUSE_SCROLL_LINES);
+                            }
+                            MouseEventKind::ScrollDown if status_pane.intersects(position) => {
+                                handle.scroll_status_pane_by(MOUSE_SCROLL_LINES as i32);
+                            }
+                            MouseEventKind::ScrollUp if status_pane.intersects(position) => {
+                                handle.scroll_status_pane_by(-(MOUSE_SCROLL_LINES as i32));
+                            }
+                            MouseEventKind::Down(MouseButton::Left)
+                                if handle.start_split_drag(mouse.column) =>
+                            {
+                                // Dragging the border between the panes resizes them
+                            }
+                            MouseEventKind::Drag(MouseButton::Left)
+                                if handle.drag_split(mouse.column) => {}
+                            MouseEventKind::Up(MouseButton::Left) => {
+                                handle.stop_split_drag();
+                            }
+                            MouseEventKind::Down(MouseButton::Left)
+                                if status_pane.intersects(position) =>
+                            {
+                                // Rows start below the pane's top border
+                                let row = mouse.row.saturating_sub(status_pane.y + 1);
+                                if handle.select_file_at_status_row(row as usize) {
+                                    let handle = handle.clone();
+                                    tokio::spawn(async move {
+                                        handle.update_current_file_diff().await;
+                                    });
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
                 }
             }
 
@@ -1074,10 +7086,13 @@ async fn main() -> Result<()> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
+    finish_session(&handle, args.socket.as_deref());
+
     // Handle any errors that occurred during the main loop
     if let Err(e) = result {
         eprintln!("Application error: {e}");
@@ -1087,3 +7102,400 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A git repository in a fresh temporary directory.
+    fn git_repository(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("watchhound-main-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        git_in(&directory, &["init", "--quiet"]);
+        // Commits shouldn't depend on the user's git config
+        git_in(&directory, &["config", "user.name", "WatchHound"]);
+        git_in(
+            &directory,
+            &["config", "user.email", "watchhound@example.com"],
+        );
+        git_in(&directory, &["config", "commit.gpgsign", "false"]);
+        directory
+    }
+
+    fn git_in(directory: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(directory)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    /// The options WatchHound starts with.
+    fn default_options() -> Options {
+        Options {
+            git_path: PathBuf::from("git"),
+            detect_renames: true,
+            context_lines: differ::DEFAULT_CONTEXT_LINES,
+            ..Options::default()
+        }
+    }
+
+    /// A handle watching `directory` with the options WatchHound starts with.
+    fn handle_for(directory: &Path) -> AppHandle {
+        handle_with(directory, default_options())
+    }
+
+    fn handle_with(directory: &Path, options: Options) -> AppHandle {
+        App::new(
+            repos_for_directories(&[directory.to_path_buf()]),
+            options,
+            None,
+        )
+        .handle
+    }
+
+    #[tokio::test]
+    async fn repository_without_commits_shows_staged_files() {
+        let directory = git_repository("unborn");
+        fs::write(directory.join("staged.txt"), "first line\n").unwrap();
+        fs::write(directory.join("untracked.txt"), "not added\n").unwrap();
+        git_in(&directory, &["add", "staged.txt"]);
+        let handle = handle_for(&directory);
+
+        let stat = handle.run_git_diff_stat().await.unwrap();
+        assert!(stat.contains("staged.txt"), "{stat}");
+        assert!(handle
+            .state
+            .lock()
+            .unwrap()
+            .unborn_repos
+            .contains(&directory));
+
+        let files = handle.get_changed_files().await.unwrap();
+        assert!(files.contains(&"staged.txt".to_string()), "{files:?}");
+        assert!(files.contains(&"untracked.txt".to_string()), "{files:?}");
+
+        let diff = handle.run_git_diff_for_file("staged.txt").await.unwrap();
+        assert!(diff.contains("+first line"), "{diff}");
+    }
+
+    /// Feeds `events` to a debounce map the way the watcher does, one every
+    /// `interval`, and returns the largest size the map reached.
+    fn largest_debounce_map(
+        events: impl Iterator<Item = PathBuf>,
+        interval: Duration,
+        debounce: Duration,
+    ) -> usize {
+        let mut debounce_map = HashMap::new();
+        let mut now = Instant::now();
+        let mut largest = 0;
+        for (count, path) in events.enumerate() {
+            now += interval;
+            debounce_map.insert(path, now);
+            if (count + 1) % DEBOUNCE_PRUNE_EVERY == 0 {
+                prune_debounce_map(&mut debounce_map, now, debounce * 10);
+            }
+            largest = largest.max(debounce_map.len());
+        }
+        largest
+    }
+
+    #[test]
+    fn debounce_map_holds_one_entry_for_a_file_touched_repeatedly() {
+        let events = (0..10_000).map(|_| PathBuf::from("src/main.rs"));
+        let largest = largest_debounce_map(events, Duration::from_secs(2), Duration::from_secs(1));
+        assert_eq!(largest, 1);
+    }
+
+    #[test]
+    fn debounce_map_stays_bounded_over_a_long_session() {
+        // A build touching a new file every 50ms for well over an hour
+        let debounce = Duration::from_secs(1);
+        let interval = Duration::from_millis(50);
+        let events = (0..100_000).map(|n| PathBuf::from(format!("target/out-{n}.o")));
+        let largest = largest_debounce_map(events, interval, debounce);
+        // Entries live for 10 debounce windows, plus the events until the next sweep
+        let window = (debounce * 10).as_millis() / interval.as_millis();
+        assert!(
+            largest <= window as usize + DEBOUNCE_PRUNE_EVERY,
+            "map grew to {largest}"
+        );
+    }
+
+    #[test]
+    fn flooding_the_watcher_channel_drops_events_without_blocking() {
+        let (tx, mut rx) = mpsc::channel(WATCHER_CHANNEL_CAPACITY);
+        let dropped = AtomicUsize::new(0);
+        // A checkout touching thousands of files while the loop is busy
+        for n in 0..5_000 {
+            let event = NotifyEvent::new(notify::EventKind::Any)
+                .add_path(PathBuf::from(format!("src/file-{n}.rs")));
+            forward_watcher_event(&tx, &dropped, event);
+        }
+
+        assert_eq!(
+            dropped.load(Ordering::Relaxed),
+            5_000 - WATCHER_CHANNEL_CAPACITY
+        );
+        let mut queued = 0;
+        while rx.try_recv().is_ok() {
+            queued += 1;
+        }
+        assert_eq!(queued, WATCHER_CHANNEL_CAPACITY);
+    }
+
+    #[test]
+    fn dropped_events_in_one_burst_are_coalesced_into_one_reload() {
+        let delay = OVERFLOW_RELOAD_DELAY;
+        let start = Instant::now();
+        let mut overflow = None;
+
+        let reloads = [0, 100, 200, 450]
+            .into_iter()
+            .map(|ms| {
+                let now = start + Duration::from_millis(ms);
+                coalesce_dropped_events(&mut overflow, 300, now, delay)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reloads,
+            [(true, 300), (false, 600), (false, 900), (false, 1200)]
+        );
+
+        // Once the burst has settled, the next one reloads again
+        let later = start + delay + Duration::from_millis(1);
+        assert_eq!(
+            coalesce_dropped_events(&mut overflow, 7, later, delay),
+            (true, 7)
+        );
+    }
+
+    #[test]
+    fn resizing_the_panes_remembers_the_width() {
+        let directory = git_repository("config");
+        let config_file = directory.join("config").join("watchhound.json");
+        let handle = App::new(
+            repos_for_directories(&[directory]),
+            Options {
+                split_percent: DEFAULT_SPLIT_PERCENT,
+                config_file: Some(config_file.clone()),
+                ..Options::default()
+            },
+            None,
+        )
+        .handle;
+        handle.resize_split(5);
+        handle.resize_split(5);
+        assert_eq!(config::load(&config_file).unwrap().split_percent, Some(50));
+
+        // Ending a drag saves the width it left behind
+        {
+            let mut state = handle.state.lock().unwrap();
+            state.dragging_split = true;
+            state.split_percent = 27;
+        }
+        handle.stop_split_drag();
+        assert_eq!(config::load(&config_file).unwrap().split_percent, Some(27));
+    }
+
+    #[tokio::test]
+    async fn once_loads_every_diff_without_the_startup_delay() {
+        let directory = git_repository("once");
+        fs::write(directory.join("notes.txt"), "first\n").unwrap();
+        git_in(&directory, &["add", "notes.txt"]);
+        git_in(&directory, &["commit", "--quiet", "-m", "Add notes"]);
+        fs::write(directory.join("notes.txt"), "first\nsecond\n").unwrap();
+        let handle = handle_with(
+            &directory,
+            Options {
+                once: true,
+                ..default_options()
+            },
+        );
+
+        assert_eq!(startup_delay(&handle.options), Duration::ZERO);
+        assert!(!startup_delay(&default_options()).is_zero());
+        handle.load_initial_state().await.unwrap();
+        let full_diff = handle.build_full_diff().await.unwrap();
+        assert!(full_diff.contains("+second"), "{full_diff}");
+    }
+
+    #[tokio::test]
+    async fn a_file_git_fails_to_diff_fails_the_full_diff() {
+        let directory = git_repository("once-error");
+        let handle = handle_with(
+            &directory,
+            Options {
+                git_path: directory.join("no-such-git"),
+                once: true,
+                ..default_options()
+            },
+        );
+        handle.state.lock().unwrap().changed_files = vec!["notes.txt".to_string()];
+
+        let error = handle.build_full_diff().await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Failed to get diff for notes.txt"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_zero_replay_interval_still_steps_at_the_minimum() {
+        let directory = git_repository("replay");
+        let handle = handle_with(
+            &directory,
+            Options {
+                replay_interval: Duration::ZERO,
+                ..default_options()
+            },
+        );
+        for n in 0..100 {
+            handle.add_diff_to_history(format!("+line {n}\n"), format!("file{n}.txt"));
+        }
+
+        handle.toggle_replay();
+        sleep(MIN_REPLAY_INTERVAL * 3).await;
+        {
+            let state = handle.state.lock().unwrap();
+            assert!(state.replay.is_some(), "replay ran to the end at once");
+            assert!(state.history_cursor.is_some_and(|cursor| cursor < 10));
+        }
+        handle.toggle_replay();
+    }
+
+    #[test]
+    fn jumping_goes_to_the_highlighted_file_despite_surrounding_spaces() {
+        let directory = git_repository("jump");
+        let handle = handle_for(&directory);
+        let files = ["src/main.rs", "src/lib.rs", "docs/rust.md", "README.md"];
+        let prompt = Prompt {
+            kind: PromptKind::Jump,
+            input: " rs ".to_string(),
+            selected: 1,
+        };
+        {
+            let mut state = handle.state.lock().unwrap();
+            state.changed_files = files.iter().map(|file| file.to_string()).collect();
+            state.prompt = Some(prompt.clone());
+        }
+
+        let list = App::format_jump_list(&prompt, &handle.state.lock().unwrap().changed_files, 10);
+        let highlighted = list
+            .lines
+            .iter()
+            .find(|line| {
+                line.spans
+                    .iter()
+                    .all(|span| span.style.add_modifier.contains(Modifier::REVERSED))
+            })
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+            })
+            .unwrap();
+
+        handle.submit_prompt();
+        let state = handle.state.lock().unwrap();
+        assert_eq!(state.changed_files[state.current_file_index], highlighted);
+    }
+
+    #[tokio::test]
+    async fn auto_commits_are_recorded_in_the_history() {
+        let directory = git_repository("autocommit");
+        fs::write(directory.join("notes.txt"), "first\n").unwrap();
+        let handle = handle_with(
+            &directory,
+            Options {
+                autocommit: true,
+                ..default_options()
+            },
+        );
+
+        handle.autocommit().await.unwrap();
+        let sha = std::process::Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .current_dir(&directory)
+            .output()
+            .unwrap();
+        let sha = String::from_utf8_lossy(&sha.stdout).trim().to_string();
+
+        let state = handle.state.lock().unwrap();
+        let entry = state.diff_history.back().unwrap();
+        assert_eq!(entry.file_name, format!("{AUTOCOMMIT_ENTRY} {sha}"));
+        assert!(
+            entry
+                .diff_content
+                .starts_with(&format!("Auto-committed {sha} \"wip: ")),
+            "{}",
+            entry.diff_content
+        );
+        assert!(entry.diff_content.contains("notes.txt"));
+    }
+
+    #[tokio::test]
+    async fn reloading_keeps_the_diff_on_screen_until_the_new_one_is_in() {
+        let directory = git_repository("reload");
+        fs::write(directory.join("notes.txt"), "first\n").unwrap();
+        git_in(&directory, &["add", "notes.txt"]);
+        git_in(&directory, &["commit", "--quiet", "-m", "Add notes"]);
+        fs::write(directory.join("notes.txt"), "first\nsecond\n").unwrap();
+        let handle = handle_for(&directory);
+        handle.state.lock().unwrap().git_diff = "diff on screen".to_string();
+
+        let reload = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.reload().await }
+        });
+        // Whatever is on screen stays until the reload has something new
+        for _ in 0..20 {
+            let git_diff = handle.state.lock().unwrap().git_diff.clone();
+            assert!(
+                git_diff == "diff on screen" || git_diff.contains("+second"),
+                "{git_diff}"
+            );
+            if reload.is_finished() {
+                break;
+            }
+            sleep(Duration::from_millis(5)).await;
+        }
+        reload.await.unwrap().unwrap();
+
+        let state = handle.state.lock().unwrap();
+        assert_eq!(state.changed_files, ["notes.txt"]);
+        assert!(state.git_diff.contains("+second"), "{}", state.git_diff);
+    }
+
+    #[test]
+    fn jumping_to_a_hunk_below_wrapped_lines_counts_the_wrapped_rows() {
+        let directory = git_repository("wrapped-jump");
+        let handle = handle_for(&directory);
+        // Words too long to share a row, so each line wraps to six rows
+        // although its characters would fit in four
+        let long_line = format!("+{}", ["abcdefghijk"; 6].join(" "));
+        {
+            let mut state = handle.state.lock().unwrap();
+            state.git_diff = format!(
+                "@@ -1,0 +1,2 @@\n{long_line}\n{long_line}\n@@ -9,3 +11,3 @@\n a\n b\n c\n"
+            );
+            state.wrap = true;
+            state.show_line_numbers = false;
+            state.side_by_side = false;
+            state.diff_view_size = (20, 3);
+            state.scroll_position = 0;
+        }
+
+        handle.jump_to_next_hunk();
+        assert_eq!(
+            handle.state.lock().unwrap().scroll_position,
+            13 - HUNK_CONTEXT_LINES
+        );
+    }
+}
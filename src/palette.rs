@@ -0,0 +1,238 @@
+//! Colors brought down to what the terminal can show. The interface is drawn
+//! with full colors, and the finished frame is mapped to 256 or 16 colors, or
+//! to none at all, before it goes out.
+
+use ratatui::{
+    buffer::Buffer,
+    style::{Color, Modifier},
+};
+
+/// The 16 basic colors with the RGB values terminals commonly give them.
+const BASIC_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (128, 0, 0)),
+    (Color::Green, (0, 128, 0)),
+    (Color::Yellow, (128, 128, 0)),
+    (Color::Blue, (0, 0, 128)),
+    (Color::Magenta, (128, 0, 128)),
+    (Color::Cyan, (0, 128, 128)),
+    (Color::Gray, (192, 192, 192)),
+    (Color::DarkGray, (128, 128, 128)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (0, 0, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// How many colors the terminal shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorLevel {
+    /// No colors or text attributes, for dumb terminals
+    None,
+    /// The 16 basic colors
+    Basic,
+    /// The 256 color palette
+    Indexed,
+    /// Any color, drawn as it is
+    #[default]
+    TrueColor,
+}
+
+impl ColorLevel {
+    /// The level the terminal supports going by `NO_COLOR`, `COLORTERM` and
+    /// `TERM`, with 16 colors when nothing says otherwise.
+    pub fn detect(var: impl Fn(&str) -> Option<String>) -> Self {
+        if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Self::None;
+        }
+        if matches!(var("COLORTERM").as_deref(), Some("truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+        match var("TERM").as_deref() {
+            Some("dumb") => Self::None,
+            Some(term) if term.contains("256color") => Self::Indexed,
+            _ => Self::Basic,
+        }
+    }
+
+    /// `color`, or the closest one this level has.
+    pub fn adapt(self, color: Color) -> Color {
+        match (self, color) {
+            (Self::TrueColor, color) | (_, color @ Color::Reset) => color,
+            (Self::None, _) => Color::Reset,
+            (Self::Indexed, Color::Rgb(r, g, b)) => Color::Indexed(indexed_color(r, g, b)),
+            (Self::Basic, color) => match rgb(color) {
+                Some(rgb) => basic_role_color(closest_basic_color(rgb)),
+                None => basic_role_color(color),
+            },
+            (_, color) => color,
+        }
+    }
+
+    /// Maps the colors of every cell in `buffer` to this level. Without colors
+    /// only reversed video is kept, so the selection in lists stays visible.
+    pub fn apply(self, buffer: &mut Buffer) {
+        if self == Self::TrueColor {
+            return;
+        }
+        for cell in buffer.content.iter_mut() {
+            cell.fg = self.adapt(cell.fg);
+            cell.bg = self.adapt(cell.bg);
+            if self == Self::None {
+                cell.modifier &= Modifier::REVERSED;
+            }
+        }
+    }
+}
+
+/// RGB value of an RGB or 256 palette color, `None` for the named ones.
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Indexed(index @ 0..=15) => Some(BASIC_COLORS[index as usize].1),
+        Color::Indexed(index @ 16..=231) => {
+            // A 6x6x6 cube of levels 0, 95, 135, 175, 215 and 255
+            let level = |i: u8| if i == 0 { 0 } else { 55 + 40 * i };
+            let i = index - 16;
+            Some((level(i / 36), level(i / 6 % 6), level(i % 6)))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + 10 * (index - 232);
+            Some((gray, gray, gray))
+        }
+        _ => None,
+    }
+}
+
+/// Many terminals draw Gray and White of the 16 colors nearly alike, so gray
+/// text such as the status bar and the `renamed` notes is dimmed to DarkGray to
+/// stay apart from the white file names and diff text.
+fn basic_role_color(color: Color) -> Color {
+    match color {
+        Color::Gray => Color::DarkGray,
+        color => color,
+    }
+}
+
+fn closest_basic_color((r, g, b): (u8, u8, u8)) -> Color {
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    BASIC_COLORS
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+/// Index of the 256 palette color closest to an RGB value: a gray from the
+/// grayscale ramp, or a color from the cube.
+fn indexed_color(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            gray => 232 + ((gray - 8) / 10).min(23),
+        };
+    }
+    let level = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        v => (v - 35) / 40,
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{layout::Rect, style::Style};
+
+    fn detect(vars: &[(&str, &str)]) -> ColorLevel {
+        ColorLevel::detect(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn detects_the_level_from_the_environment() {
+        assert_eq!(detect(&[("COLORTERM", "truecolor")]), ColorLevel::TrueColor);
+        assert_eq!(detect(&[("COLORTERM", "24bit")]), ColorLevel::TrueColor);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), ColorLevel::Indexed);
+        assert_eq!(detect(&[("TERM", "xterm")]), ColorLevel::Basic);
+        assert_eq!(detect(&[]), ColorLevel::Basic);
+        assert_eq!(detect(&[("TERM", "dumb")]), ColorLevel::None);
+        let no_color = [("NO_COLOR", "1"), ("COLORTERM", "truecolor")];
+        assert_eq!(detect(&no_color), ColorLevel::None);
+        let empty_no_color = [("NO_COLOR", ""), ("TERM", "xterm-256color")];
+        assert_eq!(detect(&empty_no_color), ColorLevel::Indexed);
+    }
+
+    #[test]
+    fn gray_and_white_stay_apart_with_16_colors() {
+        let level = ColorLevel::Basic;
+        assert_eq!(level.adapt(Color::Gray), Color::DarkGray);
+        assert_eq!(level.adapt(Color::White), Color::White);
+        // Light grays from the other palettes are dimmed the same way
+        assert_eq!(level.adapt(Color::Rgb(190, 190, 190)), Color::DarkGray);
+        assert_eq!(level.adapt(Color::Indexed(250)), Color::DarkGray);
+        assert_eq!(level.adapt(Color::Rgb(250, 250, 250)), Color::White);
+    }
+
+    #[test]
+    fn maps_colors_to_the_closest_basic_one() {
+        let level = ColorLevel::Basic;
+        assert_eq!(level.adapt(Color::Rgb(230, 20, 20)), Color::LightRed);
+        assert_eq!(level.adapt(Color::Rgb(0, 120, 0)), Color::Green);
+        assert_eq!(level.adapt(Color::Indexed(196)), Color::LightRed);
+        assert_eq!(level.adapt(Color::Reset), Color::Reset);
+        assert_eq!(level.adapt(Color::Yellow), Color::Yellow);
+    }
+
+    #[test]
+    fn maps_rgb_colors_into_the_256_palette() {
+        let level = ColorLevel::Indexed;
+        assert_eq!(level.adapt(Color::Rgb(255, 0, 0)), Color::Indexed(196));
+        assert_eq!(level.adapt(Color::Rgb(128, 128, 128)), Color::Indexed(244));
+        assert_eq!(level.adapt(Color::Gray), Color::Gray);
+    }
+
+    #[test]
+    fn without_colors_only_the_selection_stays_visible() {
+        let area = Rect::new(0, 0, 2, 1);
+        let mut buffer = Buffer::empty(area);
+        buffer.set_string(
+            0,
+            0,
+            "a",
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        );
+        buffer.set_string(
+            1,
+            0,
+            "b",
+            Style::default()
+                .bg(Color::Blue)
+                .add_modifier(Modifier::REVERSED),
+        );
+        ColorLevel::None.apply(&mut buffer);
+
+        let (plain, selected) = (buffer.get(0, 0), buffer.get(1, 0));
+        assert_eq!(
+            (plain.fg, plain.modifier),
+            (Color::Reset, Modifier::empty())
+        );
+        assert_eq!(
+            (selected.bg, selected.modifier),
+            (Color::Reset, Modifier::REVERSED)
+        );
+    }
+}
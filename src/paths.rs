@@ -0,0 +1,102 @@
+//! Paths of watched files in the form git reports them.
+
+use std::{fs, path::Path};
+
+/// Converts a watcher path into the `/`-separated, repo-relative form git reports.
+///
+/// The watcher may report absolute or `./`-prefixed paths and the repo root may be
+/// given relative to the working directory, so both sides are made absolute
+/// before the root is stripped.
+pub fn repo_relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => {
+            let root = fs::canonicalize(root).ok()?;
+            // The file itself may have been deleted, so resolve its parent instead
+            let parent = fs::canonicalize(path.parent()?).ok()?;
+            parent
+                .join(path.file_name()?)
+                .strip_prefix(&root)
+                .ok()?
+                .to_path_buf()
+        }
+    };
+
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory under the system temp directory for one test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("watchhound-paths-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn same_file_names_in_different_directories_stay_apart() {
+        let root = Path::new("/work/repo");
+        assert_eq!(
+            repo_relative_path(root, &root.join("src/app/mod.rs")).as_deref(),
+            Some("src/app/mod.rs")
+        );
+        assert_eq!(
+            repo_relative_path(root, &root.join("src/ui/mod.rs")).as_deref(),
+            Some("src/ui/mod.rs")
+        );
+    }
+
+    #[test]
+    fn paths_outside_the_repository_have_no_key() {
+        let root = temp_dir("outside");
+        let other = temp_dir("outside-other");
+        fs::write(other.join("mod.rs"), "").unwrap();
+        assert_eq!(repo_relative_path(&root, &other.join("mod.rs")), None);
+    }
+
+    /// A repository reached through a symlink, as the watcher may report the
+    /// paths below it with the link resolved. Returns the link and the real
+    /// directory.
+    #[cfg(unix)]
+    fn linked_repository(name: &str) -> (PathBuf, PathBuf) {
+        let dir = temp_dir(name);
+        let real = dir.join("real");
+        fs::create_dir_all(real.join("src/app")).unwrap();
+        fs::create_dir_all(real.join("src/ui")).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        (link, real)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_a_root_given_through_a_symlink() {
+        let (root, real) = linked_repository("symlink");
+        fs::write(real.join("src/app/mod.rs"), "").unwrap();
+        assert_eq!(
+            repo_relative_path(&root, &real.join("src/app/mod.rs")).as_deref(),
+            Some("src/app/mod.rs")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_deleted_files() {
+        let (root, real) = linked_repository("deleted");
+        // The file is gone, so only its parent can be resolved
+        assert_eq!(
+            repo_relative_path(&root, &real.join("src/ui/mod.rs")).as_deref(),
+            Some("src/ui/mod.rs")
+        );
+    }
+}
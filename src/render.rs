@@ -0,0 +1,448 @@
+//! Styling of diff text for the diff pane: colors per line kind, syntax
+//! highlighting, word diffs, whitespace errors and line number gutters.
+
+use crate::{
+    diff::{
+        conflict_marker, diff_header_path, diff_line_numbers, diff_lines, word_diff_segments,
+        FOLD_MARKER, INVALID_UTF8_MARKER, TRUNCATION_MARKER,
+    },
+    highlight::Language,
+    whitespace::WhitespaceRules,
+};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use std::{collections::HashMap, ops::Range};
+
+pub fn parse_diff_line(line: &str) -> Line<'static> {
+    let spans = if conflict_marker(line).is_some() {
+        // Leftover <<<<<<< / ======= / >>>>>>> markers of a merge conflict
+        vec![Span::styled(
+            line.to_string(),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        )]
+    } else if line.starts_with("@@") {
+        // Context header (cyan)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Cyan),
+        )]
+    } else if line.starts_with("+++") || line.starts_with("---") {
+        // File headers (white/gray)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Gray),
+        )]
+    } else if line.starts_with('+') {
+        // Added lines (green)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Green),
+        )]
+    } else if line.starts_with('-') {
+        // Removed lines (red)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Red),
+        )]
+    } else if let Some(old) = line.strip_prefix("rename from ") {
+        // Rename headers read as "old → new" across the two lines
+        vec![
+            Span::styled("renamed ".to_string(), Style::default().fg(Color::Gray)),
+            Span::styled(old.to_string(), Style::default().fg(Color::Cyan)),
+        ]
+    } else if let Some(new) = line.strip_prefix("rename to ") {
+        vec![
+            Span::styled("      → ".to_string(), Style::default().fg(Color::Cyan)),
+            Span::styled(new.to_string(), Style::default().fg(Color::Cyan)),
+        ]
+    } else if line.starts_with(FOLD_MARKER) || line.starts_with(TRUNCATION_MARKER) {
+        // Folded run of unchanged lines, or the end of a truncated diff
+        vec![Span::styled(
+            line.to_string(),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+        )]
+    } else if line.starts_with(INVALID_UTF8_MARKER) {
+        // The diff had to be decoded lossily, so it may not match the file exactly
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Yellow),
+        )]
+    } else if line.starts_with("Binary file") {
+        // Binary changes are summarized by size since there's no text to show
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Magenta),
+        )]
+    } else if line == "deleted file" || line.starts_with("deleted file mode ") {
+        // Every line below is a removal, so say up front that the file is gone
+        let mut spans = vec![Span::styled(
+            " file deleted ".to_string(),
+            Style::default()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
+        )];
+        if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            spans.push(Span::styled(
+                format!(" (mode {mode})"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        spans
+    } else if let Some(mode) = ["old mode ", "new mode ", "new file mode "]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+    {
+        // Permission changes, e.g. chmod +x, which have no content diff of their own
+        let mut spans = vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Magenta),
+        )];
+        if let Some(description) = file_mode_description(line, mode) {
+            spans.push(Span::styled(
+                format!(" ({description})"),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        spans
+    } else if line.starts_with("index ")
+        || line.starts_with("diff --git")
+        || line.starts_with("similarity index ")
+    {
+        // Git metadata (gray)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::Gray),
+        )]
+    } else {
+        // Context lines (white)
+        vec![Span::styled(
+            line.to_string(),
+            Style::default().fg(Color::White),
+        )]
+    };
+
+    Line::from(spans)
+}
+
+/// Like `parse_diff_line`, but colours the code after the `+`/`-`/` ` prefix
+/// using `language`, with added and removed lines tinted by background instead.
+pub fn highlight_diff_line(line: &str, language: Option<&Language>) -> Line<'static> {
+    let Some(language) = language else {
+        return parse_diff_line(line);
+    };
+    if line.starts_with("+++") || line.starts_with("---") || conflict_marker(line).is_some() {
+        return parse_diff_line(line);
+    }
+
+    let (prefix_color, base) = match line.chars().next() {
+        Some('+') => (Color::Green, Style::default().bg(Color::Rgb(0, 48, 0))),
+        Some('-') => (Color::Red, Style::default().bg(Color::Rgb(64, 0, 0))),
+        Some(' ') => (Color::White, Style::default()),
+        _ => return parse_diff_line(line),
+    };
+
+    let (prefix, code) = line.split_at(1);
+    let mut spans = vec![Span::styled(prefix.to_string(), base.fg(prefix_color))];
+    spans.extend(language.highlight(code, base.fg(Color::White)));
+    Line::from(spans)
+}
+
+/// Renders a removed or added line with only its changed words emphasized.
+pub fn word_diff_line(line: &str, segments: &[(String, bool)]) -> Line<'static> {
+    let (unchanged, changed) = if line.starts_with('+') {
+        (Color::Green, Color::LightGreen)
+    } else {
+        (Color::Red, Color::LightRed)
+    };
+
+    let mut spans = vec![Span::styled(
+        line[..1].to_string(),
+        Style::default().fg(unchanged),
+    )];
+    spans.extend(segments.iter().map(|(text, is_changed)| {
+        if *is_changed {
+            Span::styled(
+                text.clone(),
+                Style::default().fg(changed).add_modifier(Modifier::BOLD),
+            )
+        } else {
+            Span::styled(
+                text.clone(),
+                Style::default().fg(unchanged).add_modifier(Modifier::DIM),
+            )
+        }
+    }));
+    Line::from(spans)
+}
+
+/// Gives the parts of `rendered` at the byte `ranges` of its text a red
+/// background, splitting spans where a range starts or ends inside them.
+pub fn mark_whitespace_errors(
+    mut rendered: Line<'static>,
+    ranges: &[Range<usize>],
+) -> Line<'static> {
+    if ranges.is_empty() {
+        return rendered;
+    }
+
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for span in rendered.spans {
+        let text = span.content.as_ref();
+        let end = offset + text.len();
+        let mut cuts = vec![0, text.len()];
+        for range in ranges {
+            for cut in [range.start, range.end] {
+                if cut > offset && cut < end {
+                    cuts.push(cut - offset);
+                }
+            }
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for piece in cuts.windows(2) {
+            let start = offset + piece[0];
+            let style = if ranges.iter().any(|range| range.contains(&start)) {
+                span.style.bg(Color::Red)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(text[piece[0]..piece[1]].to_string(), style));
+        }
+        offset = end;
+    }
+    rendered.spans = spans;
+    rendered
+}
+
+/// Renders diff text for the right pane. `syntax_file` enables syntax
+/// highlighting, starting with that file's language; `diff --git` headers inside
+/// the text switch languages as the diff moves between files. With `word_diff`,
+/// paired removed/added lines emphasize just the words that changed. The
+/// `focused_line` is highlighted, used for the fold Enter would expand.
+/// `whitespace` marks whitespace errors in added lines. Lines in
+/// `split_markers` get a staged or unstaged marker in front.
+pub fn format_diff_text(
+    diff_text: &str,
+    show_line_numbers: bool,
+    syntax_file: Option<&str>,
+    word_diff: bool,
+    focused_line: Option<usize>,
+    whitespace: Option<&WhitespaceRules>,
+    split_markers: &HashMap<usize, bool>,
+) -> Text<'static> {
+    let numbers = if show_line_numbers {
+        diff_line_numbers(diff_text)
+    } else {
+        Vec::new()
+    };
+    let word_segments = if word_diff {
+        word_diff_segments(diff_text)
+    } else {
+        HashMap::new()
+    };
+    let width = line_number_digits(&numbers);
+    let mut language = syntax_file.and_then(Language::from_path);
+
+    let lines: Vec<Line> = diff_lines(diff_text)
+        .enumerate()
+        .map(|(i, line)| {
+            if syntax_file.is_some() {
+                if let Some(path) = diff_header_path(line) {
+                    language = Language::from_path(path);
+                }
+            }
+            let mut rendered = match word_segments.get(&i) {
+                Some(segments) if conflict_marker(line).is_none() => word_diff_line(line, segments),
+                _ => highlight_diff_line(line, language),
+            };
+            if let Some(rules) = whitespace {
+                rendered = mark_whitespace_errors(rendered, &rules.errors(line));
+            }
+            if focused_line == Some(i) {
+                rendered.patch_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            if let Some((old, new)) = numbers.get(i) {
+                rendered
+                    .spans
+                    .insert(0, line_number_gutter(*old, *new, width));
+            }
+            if !split_markers.is_empty() {
+                rendered
+                    .spans
+                    .insert(0, split_marker(split_markers.get(&i)));
+            }
+            rendered
+        })
+        .collect();
+
+    Text::from(lines)
+}
+
+/// Splits a unified diff into old (left) and new (right) columns. Runs of removed
+/// and added lines are paired row by row, padding the shorter side with blanks;
+/// context and header lines appear in both columns.
+pub fn format_side_by_side(
+    diff_text: &str,
+    show_line_numbers: bool,
+    syntax_file: Option<&str>,
+    whitespace: Option<&WhitespaceRules>,
+) -> (Text<'static>, Text<'static>) {
+    let numbers = diff_line_numbers(diff_text);
+    let width = line_number_digits(&numbers);
+    let language = syntax_file.and_then(Language::from_path);
+    // Each column only carries its own side's line number
+    let numbered = |line: &str, number: Option<usize>| {
+        let mut rendered = highlight_diff_line(line, language);
+        if let Some(rules) = whitespace {
+            rendered = mark_whitespace_errors(rendered, &rules.errors(line));
+        }
+        if show_line_numbers {
+            rendered.spans.insert(
+                0,
+                Span::styled(
+                    format!(
+                        "{:>width$} │ ",
+                        number.map(|n| n.to_string()).unwrap_or_default()
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            );
+        }
+        rendered
+    };
+
+    let rows = side_by_side_rows(diff_text);
+    let total = side_by_side_row_count(&rows);
+    let mut old_lines = vec![Line::from(""); total];
+    let mut new_lines = vec![Line::from(""); total];
+    for ((line, (old, new)), row) in diff_lines(diff_text).zip(numbers).zip(rows) {
+        let is_removed = line.starts_with('-') && !line.starts_with("---");
+        let is_added = line.starts_with('+') && !line.starts_with("+++");
+        if !is_added {
+            old_lines[row] = numbered(line, old);
+        }
+        if !is_removed {
+            new_lines[row] = numbered(line, new);
+        }
+    }
+
+    (Text::from(old_lines), Text::from(new_lines))
+}
+
+/// Row each line of `diff_text` lands on in the side-by-side columns. Removed
+/// and added lines of a change block share rows, pairing up in order; any
+/// other line takes a row of its own in both columns.
+pub fn side_by_side_rows(diff_text: &str) -> Vec<usize> {
+    let mut rows = Vec::new();
+    // First row of the change block being paired and its lines so far
+    let (mut block, mut removed, mut added) = (0, 0, 0);
+    for line in diff_lines(diff_text) {
+        if line.starts_with('-') && !line.starts_with("---") {
+            // A removal after additions starts a new change block
+            if added > 0 {
+                block += removed.max(added);
+                (removed, added) = (0, 0);
+            }
+            rows.push(block + removed);
+            removed += 1;
+        } else if line.starts_with('+') && !line.starts_with("+++") {
+            rows.push(block + added);
+            added += 1;
+        } else {
+            block += removed.max(added);
+            (removed, added) = (0, 0);
+            rows.push(block);
+            block += 1;
+        }
+    }
+    rows
+}
+
+/// Rows the side-by-side columns take up, given `side_by_side_rows`.
+pub fn side_by_side_row_count(rows: &[usize]) -> usize {
+    rows.iter().max().map_or(0, |row| row + 1)
+}
+
+/// What a mode line of a diff means, for modes git uses: `mode` is the line's
+/// octal mode after its `old mode ` (or similar) prefix.
+fn file_mode_description(line: &str, mode: &str) -> Option<&'static str> {
+    match (line.starts_with("new mode "), mode) {
+        (true, "100755") => Some("execute bit added"),
+        (true, "100644") => Some("execute bit removed"),
+        (_, "100755") => Some("executable"),
+        (_, "100644") => Some("regular file"),
+        (_, "120000") => Some("symlink"),
+        (_, "160000") => Some("submodule"),
+        _ => None,
+    }
+}
+
+/// Gutter marking a changed line as staged or unstaged, blank for other lines.
+fn split_marker(staged: Option<&bool>) -> Span<'static> {
+    match staged {
+        Some(true) => Span::styled("▌", Style::default().fg(Color::LightGreen)),
+        Some(false) => Span::styled("▌", Style::default().fg(Color::Yellow)),
+        None => Span::raw(" "),
+    }
+}
+
+/// Digits needed for the largest line number in the diff.
+pub fn line_number_digits(numbers: &[(Option<usize>, Option<usize>)]) -> usize {
+    numbers
+        .iter()
+        .flat_map(|(old, new)| [*old, *new])
+        .flatten()
+        .max()
+        .unwrap_or(0)
+        .to_string()
+        .len()
+}
+
+/// Columns taken up by the `old new │ ` gutter.
+pub fn line_number_gutter_width(numbers: &[(Option<usize>, Option<usize>)]) -> usize {
+    line_number_digits(numbers) * 2 + 4
+}
+
+fn line_number_gutter(old: Option<usize>, new: Option<usize>, width: usize) -> Span<'static> {
+    let format_number = |number: Option<usize>| number.map(|n| n.to_string()).unwrap_or_default();
+    Span::styled(
+        format!(
+            "{:>width$} {:>width$} │ ",
+            format_number(old),
+            format_number(new)
+        ),
+        Style::default().fg(Color::DarkGray),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_by_side_rows_pair_removals_with_additions() {
+        let diff = "@@ -1,4 +1,3 @@\n ctx\n-a\n-b\n+A\n-c\n+C\n+D\n ctx\n";
+        assert_eq!(side_by_side_rows(diff), vec![0, 1, 2, 3, 2, 4, 4, 5, 6]);
+
+        let (old, new) = format_side_by_side(diff, false, None, None);
+        assert_eq!(old.lines.len(), 7);
+        assert_eq!(new.lines.len(), 7);
+        let text = |line: &Line| {
+            line.spans
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect::<String>()
+        };
+        assert_eq!(text(&old.lines[4]), "-c");
+        assert_eq!(text(&new.lines[4]), "+C");
+        assert_eq!(text(&new.lines[3]), "");
+    }
+}
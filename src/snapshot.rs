@@ -0,0 +1,204 @@
+use crate::{
+    diff::invalid_utf8_line,
+    differ::{unified_diff, DEFAULT_CONTEXT_LINES},
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Files larger than this are left out of the snapshot.
+const MAX_SNAPSHOT_FILE_SIZE: u64 = 1024 * 1024;
+
+/// File contents captured at startup, used as the baseline when watching a
+/// directory that is not a git repository.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    root: PathBuf,
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl Snapshot {
+    pub fn capture(root: &Path) -> io::Result<Self> {
+        let files = read_tree(root)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            files,
+        })
+    }
+
+    /// Paths (relative to the root, `/`-separated) that differ from the snapshot,
+    /// including files created or deleted since it was taken.
+    pub fn changed_files(&self) -> io::Result<Vec<String>> {
+        let current = read_tree(&self.root)?;
+        let paths: BTreeSet<&String> = self.files.keys().chain(current.keys()).collect();
+
+        Ok(paths
+            .into_iter()
+            .filter(|path| self.files.get(*path) != current.get(*path))
+            .cloned()
+            .collect())
+    }
+
+    /// Diff of `file` against the snapshot with `context` lines around changes.
+    pub fn diff_file(&self, file: &str, context: usize) -> String {
+        let old = self.files.get(file);
+        let new = fs::read(self.root.join(file)).ok();
+
+        let mut diff = format!("diff --snapshot a/{file} b/{file}\n");
+        match (old, &new) {
+            (None, None) => return String::new(),
+            (Some(old), Some(new)) if old == new => return String::new(),
+            (None, Some(_)) => diff.push_str("new file\n"),
+            (Some(_), None) => diff.push_str("deleted file\n"),
+            _ => {}
+        }
+
+        let old_bytes = old.map(|b| b.as_slice()).unwrap_or_default();
+        let new_bytes = new.as_deref().unwrap_or_default();
+        if old_bytes.contains(&0) || new_bytes.contains(&0) {
+            diff.push_str(&format!("Binary files a/{file} and b/{file} differ\n"));
+            return diff;
+        }
+
+        if std::str::from_utf8(old_bytes).is_err() || std::str::from_utf8(new_bytes).is_err() {
+            diff.insert_str(0, &invalid_utf8_line());
+        }
+        let old_text = String::from_utf8_lossy(old_bytes);
+        let new_text = String::from_utf8_lossy(new_bytes);
+        let old_lines: Vec<&str> = old_text.lines().collect();
+        let new_lines: Vec<&str> = new_text.lines().collect();
+
+        diff.push_str(&format!("--- a/{file}\n+++ b/{file}\n"));
+        diff.push_str(&unified_diff(&old_lines, &new_lines, context));
+        diff
+    }
+
+    /// Insertions and deletions of each changed file, leaving out binary files.
+    pub fn file_changes(&self) -> io::Result<HashMap<String, (usize, usize)>> {
+        Ok(self
+            .changed_files()?
+            .into_iter()
+            .filter_map(|file| {
+                let diff = self.diff_file(&file, 0);
+                let binary = diff.contains("\nBinary files ");
+                (!binary).then(|| (file, count_changes(&diff)))
+            })
+            .collect())
+    }
+
+    /// A `git diff --stat`-style summary of everything that changed.
+    pub fn diff_stat(&self) -> io::Result<String> {
+        let files = self.changed_files()?;
+        if files.is_empty() {
+            return Ok(String::new());
+        }
+
+        let mut rows = Vec::new();
+        let (mut total_insertions, mut total_deletions) = (0, 0);
+        for file in &files {
+            let diff = self.diff_file(file, DEFAULT_CONTEXT_LINES);
+            let binary = diff.contains("\nBinary files ");
+            let (insertions, deletions) = count_changes(&diff);
+            total_insertions += insertions;
+            total_deletions += deletions;
+            rows.push((file, binary, insertions, deletions));
+        }
+
+        let name_width = files.iter().map(|f| f.len()).max().unwrap_or(0);
+        let mut stat = String::new();
+        for (file, binary, insertions, deletions) in rows {
+            if binary {
+                // Same form git uses for binary files
+                stat.push_str(&format!(
+                    " {file:<name_width$} | Bin {} -> {} bytes\n",
+                    self.files.get(file).map(Vec::len).unwrap_or(0),
+                    fs::metadata(self.root.join(file))
+                        .map(|m| m.len())
+                        .unwrap_or(0),
+                ));
+                continue;
+            }
+            stat.push_str(&format!(
+                " {file:<name_width$} | {:>4} {}{}\n",
+                insertions + deletions,
+                "+".repeat(insertions.min(40)),
+                "-".repeat(deletions.min(40)),
+            ));
+        }
+        stat.push_str(&format!(
+            " {} file{} changed, {} insertion{}(+), {} deletion{}(-)\n",
+            files.len(),
+            plural(files.len()),
+            total_insertions,
+            plural(total_insertions),
+            total_deletions,
+            plural(total_deletions),
+        ));
+        Ok(stat)
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+fn count_changes(diff: &str) -> (usize, usize) {
+    diff.lines().fold((0, 0), |(insertions, deletions), line| {
+        if line.starts_with('+') && !line.starts_with("+++") {
+            (insertions + 1, deletions)
+        } else if line.starts_with('-') && !line.starts_with("---") {
+            (insertions, deletions + 1)
+        } else {
+            (insertions, deletions)
+        }
+    })
+}
+
+fn read_tree(root: &Path) -> io::Result<HashMap<String, Vec<u8>>> {
+    let mut files = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        // Files come and go while the tree is read, so anything below the root
+        // that vanished or can't be read is left out instead of failing it all
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) if dir != root => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Skip hidden files and directories (.git, editor swap files, ...)
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file()
+                && entry
+                    .metadata()
+                    .is_ok_and(|metadata| metadata.len() <= MAX_SNAPSHOT_FILE_SIZE)
+            {
+                if let (Ok(relative), Ok(content)) = (path.strip_prefix(root), fs::read(&path)) {
+                    let relative: Vec<String> = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().to_string())
+                        .collect();
+                    files.insert(relative.join("/"), content);
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
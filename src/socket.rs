@@ -0,0 +1,71 @@
+//! Event stream for `--socket`: one JSON line per processed file change, sent
+//! to every client connected to a Unix domain socket.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+use tokio::sync::broadcast;
+
+/// Events buffered per client before a slow client starts missing some
+const EVENT_BUFFER: usize = 256;
+
+/// A file change as written to the socket.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub timestamp: DateTime<Utc>,
+    pub file: String,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+pub fn channel() -> broadcast::Sender<ChangeEvent> {
+    broadcast::channel(EVENT_BUFFER).0
+}
+
+/// Binds `path` and streams `events` to every client that connects. Clients can
+/// come and go at any time; a failing client is dropped without affecting the others.
+#[cfg(unix)]
+pub fn serve(path: &Path, events: &broadcast::Sender<ChangeEvent>) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::{io::AsyncWriteExt, net::UnixListener};
+
+    // A socket left behind by an earlier run would make bind fail
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+
+    let events = events.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut receiver = events.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    let event = match receiver.recv().await {
+                        Ok(event) => event,
+                        // The client fell behind; carry on with the newest events
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    let Ok(line) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+                    if stream
+                        .write_all(format!("{line}\n").as_bytes())
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _events: &broadcast::Sender<ChangeEvent>) -> Result<()> {
+    anyhow::bail!("--socket needs Unix domain sockets, which this platform doesn't have")
+}
@@ -0,0 +1,224 @@
+//! Reading the output of `git diff --stat` and `--numstat`: per-file counts,
+//! the bar graph and the summary line.
+
+use ratatui::{
+    style::{Color, Style},
+    text::Span,
+};
+
+/// Insertions and deletions of each file in the output of
+/// `git diff --numstat -z`, by path (the new one for renames). Binary files,
+/// which git counts as `-`, are left out.
+pub fn parse_numstat(output: &str) -> Vec<(String, (usize, usize))> {
+    let mut fields = output.split('\0');
+    let mut changes = Vec::new();
+    while let Some(field) = fields.next() {
+        let mut parts = field.splitn(3, '\t');
+        let (Some(insertions), Some(deletions), Some(path)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        // A rename leaves the path empty and is followed by the old and new paths
+        let path = if path.is_empty() {
+            match (fields.next(), fields.next()) {
+                (Some(_), Some(new)) => new,
+                _ => break,
+            }
+        } else {
+            path
+        };
+        if let (Ok(insertions), Ok(deletions)) = (insertions.parse(), deletions.parse()) {
+            changes.push((path.to_string(), (insertions, deletions)));
+        }
+    }
+    changes
+}
+
+/// Sizes in bytes before and after from the stats part of a `git diff --stat`
+/// line for a binary file, which reads "Bin 1024 -> 2048 bytes".
+pub fn binary_stat_sizes(stats: &str) -> Option<(u64, u64)> {
+    let sizes = stats.trim().strip_prefix("Bin ")?.strip_suffix(" bytes")?;
+    let (before, after) = sizes.split_once(" -> ")?;
+    Some((before.trim().parse().ok()?, after.trim().parse().ok()?))
+}
+
+/// The count and `+++---` bar from a `git diff --stat` line, colored like git
+/// does: the bar's `+` green and `-` red, with the count yellow for a mix of
+/// both. git scales the bar down for big changes and may leave it out when the
+/// terminal is narrow, so only the characters present are colored.
+pub fn stat_bar_spans(stats: &str) -> Vec<Span<'static>> {
+    let (count, bar) = stats.split_once(' ').unwrap_or((stats, ""));
+    let count_color = match (bar.contains('+'), bar.contains('-')) {
+        (true, true) => Color::Yellow,
+        (true, false) => Color::Green,
+        (false, true) => Color::Red,
+        (false, false) => Color::White,
+    };
+    let mut spans = vec![Span::styled(
+        count.to_string(),
+        Style::default().fg(count_color),
+    )];
+    if bar.is_empty() {
+        return spans;
+    }
+    spans.push(Span::raw(" "));
+    // One span per run of the same character
+    let mut rest = bar;
+    while let Some(c) = rest.chars().next() {
+        let run = rest.find(|other| other != c).unwrap_or(rest.len());
+        let color = match c {
+            '+' => Color::Green,
+            '-' => Color::Red,
+            _ => Color::White,
+        };
+        spans.push(Span::styled(
+            rest[..run].to_string(),
+            Style::default().fg(color),
+        ));
+        rest = &rest[run..];
+    }
+    spans
+}
+
+/// Files changed, insertions and deletions from the summary line ending a
+/// `git diff --stat`, e.g. "3 files changed, 10 insertions(+), 2 deletions(-)".
+/// git leaves out whichever of insertions and deletions is zero.
+pub fn parse_stat_summary(line: &str) -> Option<(usize, usize, usize)> {
+    let mut parts = line.trim().split(", ");
+    let (files, changed) = parts.next()?.split_once(' ')?;
+    if changed != "files changed" && changed != "file changed" {
+        return None;
+    }
+    let files = files.parse().ok()?;
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for part in parts {
+        let (count, kind) = part.split_once(' ')?;
+        let count = count.parse().ok()?;
+        if kind.starts_with("insertion") {
+            insertions = count;
+        } else if kind.starts_with("deletion") {
+            deletions = count;
+        }
+    }
+    Some((files, insertions, deletions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_exact_counts() {
+        // A change big enough for --stat to scale its bar down
+        let output = "1200\t37\tsrc/main.rs\x003\t0\tREADME.md\x00";
+        assert_eq!(
+            parse_numstat(output),
+            vec![
+                ("src/main.rs".to_string(), (1200, 37)),
+                ("README.md".to_string(), (3, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_renames_by_their_new_path() {
+        let output = "2\t1\t\x00src/old.rs\x00src/new.rs\x000\t4\tlib.rs\x00";
+        assert_eq!(
+            parse_numstat(output),
+            vec![
+                ("src/new.rs".to_string(), (2, 1)),
+                ("lib.rs".to_string(), (0, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_binary_files() {
+        let output = "-\t-\tlogo.png\x001\t1\tpaths with spaces.txt\x00";
+        assert_eq!(
+            parse_numstat(output),
+            vec![("paths with spaces.txt".to_string(), (1, 1))]
+        );
+    }
+
+    #[test]
+    fn empty_output_has_no_changes() {
+        assert!(parse_numstat("").is_empty());
+    }
+
+    #[test]
+    fn reads_binary_sizes() {
+        assert_eq!(
+            binary_stat_sizes(" Bin 1024 -> 2048 bytes"),
+            Some((1024, 2048))
+        );
+        assert_eq!(binary_stat_sizes("Bin 0 -> 377 bytes"), Some((0, 377)));
+    }
+
+    #[test]
+    fn text_stats_have_no_binary_sizes() {
+        assert_eq!(binary_stat_sizes(" 12 ++++----"), None);
+        assert_eq!(binary_stat_sizes("Bin"), None);
+    }
+
+    /// Text and color of each span.
+    fn colored(spans: &[Span]) -> Vec<(String, Option<Color>)> {
+        spans
+            .iter()
+            .map(|span| (span.content.to_string(), span.style.fg))
+            .collect()
+    }
+
+    #[test]
+    fn colors_the_bar_like_git() {
+        assert_eq!(
+            colored(&stat_bar_spans("12 +++++++-----")),
+            vec![
+                ("12".to_string(), Some(Color::Yellow)),
+                (" ".to_string(), None),
+                ("+++++++".to_string(), Some(Color::Green)),
+                ("-----".to_string(), Some(Color::Red)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colors_the_count_by_the_kind_of_change() {
+        assert_eq!(stat_bar_spans("3 +++")[0].style.fg, Some(Color::Green));
+        assert_eq!(stat_bar_spans("4 ----")[0].style.fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn a_count_without_a_bar_stays_on_its_own() {
+        // git drops the bar when the terminal is too narrow for it
+        assert_eq!(
+            colored(&stat_bar_spans("1200")),
+            vec![("1200".to_string(), Some(Color::White))]
+        );
+    }
+
+    #[test]
+    fn reads_the_summary_line() {
+        assert_eq!(
+            parse_stat_summary(" 3 files changed, 10 insertions(+), 2 deletions(-)"),
+            Some((3, 10, 2))
+        );
+        assert_eq!(
+            parse_stat_summary(" 1 file changed, 1 insertion(+)"),
+            Some((1, 1, 0))
+        );
+        assert_eq!(
+            parse_stat_summary(" 2 files changed, 5 deletions(-)"),
+            Some((2, 0, 5))
+        );
+    }
+
+    #[test]
+    fn other_lines_are_not_a_summary() {
+        assert_eq!(parse_stat_summary(" src/main.rs | 12 +++++++-----"), None);
+        assert_eq!(parse_stat_summary("No changes"), None);
+        assert_eq!(parse_stat_summary(""), None);
+    }
+}
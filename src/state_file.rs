@@ -0,0 +1,82 @@
+//! `--json-state`: a JSON file describing what WatchHound currently shows, for
+//! editor integrations to read. It is rewritten whenever the snapshot changes.
+
+use crate::{AppState, FileStatus};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file's contents. Kept separate from `AppState` so the format only
+/// changes on purpose.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateSnapshot {
+    pub files: Vec<FileState>,
+    /// Key of the file shown in the diff pane
+    pub selected: Option<String>,
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileState {
+    pub path: String,
+    /// unstaged, staged, both, untracked or conflicted
+    pub status: &'static str,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl StateSnapshot {
+    pub fn of(state: &AppState) -> Self {
+        let files = state
+            .changed_files
+            .iter()
+            .map(|file| {
+                let (insertions, deletions) =
+                    state.file_stats.get(file).copied().unwrap_or_default();
+                let status = match state.file_status.get(file) {
+                    None | Some(FileStatus::Unstaged) => "unstaged",
+                    Some(FileStatus::Staged) => "staged",
+                    Some(FileStatus::Both) => "both",
+                    Some(FileStatus::Untracked) => "untracked",
+                    Some(FileStatus::Conflicted) => "conflicted",
+                };
+                FileState {
+                    path: file.clone(),
+                    status,
+                    insertions,
+                    deletions,
+                }
+            })
+            .collect();
+        Self {
+            files,
+            selected: state.changed_files.get(state.current_file_index).cloned(),
+            last_update: state.last_update,
+        }
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    temp.into()
+}
+
+/// Whether a watcher event for `changed` comes from writing the state file at
+/// `path`, which mustn't count as a change when it lives in a watched directory.
+pub fn is_state_file(path: &Path, changed: &Path) -> bool {
+    changed == path || changed == temp_path(path)
+}
+
+/// Writes `snapshot` to `path` through a temporary file next to it, so readers
+/// never see a half-written file.
+pub fn write(path: &Path, snapshot: &StateSnapshot) -> Result<()> {
+    let temp = temp_path(path);
+    fs::write(&temp, serde_json::to_string(snapshot)? + "\n")?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
@@ -0,0 +1,271 @@
+//! Measuring text the way the panes lay it out, and shortening paths that
+//! don't fit.
+
+use std::collections::VecDeque;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const NBSP: &str = "\u{00a0}";
+
+/// Number of rows `text` occupies in a pane of the given width once wrapped
+/// by a paragraph with `Wrap { trim: true }`.
+pub fn wrapped_line_count(text: &str, width: u16) -> usize {
+    let width = width.max(1);
+    text.lines().map(|line| wrapped_rows(line, width)).sum()
+}
+
+/// Rows one line wraps to. This follows ratatui's word wrapper step by step,
+/// counting the rows it would fill instead of filling them: words move to the
+/// next row whole unless they are wider than the pane, and the whitespace a
+/// row breaks at is dropped. `max` must not be zero.
+pub fn wrapped_rows(line: &str, max: u16) -> usize {
+    let mut rows = 0;
+    // Graphemes and width of the row being filled
+    let (mut row_symbols, mut row_width) = (0, 0);
+    // Graphemes and width of the word not yet placed on a row
+    let (mut word_symbols, mut word_width) = (0, 0);
+    // Widths of the whitespace before that word
+    let (mut spaces, mut space_width) = (VecDeque::new(), 0);
+    let mut has_seen_non_whitespace = false;
+
+    for symbol in line.graphemes(true) {
+        let is_whitespace = symbol.chars().all(char::is_whitespace) && symbol != NBSP;
+        let symbol_width = symbol.width() as u16;
+        // Characters wider than the whole pane are never drawn
+        if symbol_width > max {
+            continue;
+        }
+
+        if has_seen_non_whitespace && is_whitespace
+            || word_width + symbol_width > max && row_symbols == 0
+            || space_width + symbol_width > max && row_symbols == 0
+        {
+            if row_symbols > 0 {
+                row_symbols += spaces.len();
+                row_width += space_width;
+            }
+            row_symbols += word_symbols;
+            row_width += word_width;
+            spaces.clear();
+            space_width = 0;
+            word_symbols = 0;
+            word_width = 0;
+        }
+
+        if row_width >= max || row_width + space_width + word_width >= max && symbol_width > 0 {
+            let mut remaining_width = max.saturating_sub(row_width);
+            rows += 1;
+            row_symbols = 0;
+            row_width = 0;
+
+            // The whitespace the row broke at doesn't carry over
+            let mut first_space = spaces.pop_front();
+            while let Some(width) = first_space {
+                space_width -= width;
+                if width > remaining_width {
+                    break;
+                }
+                remaining_width -= width;
+                first_space = spaces.pop_front();
+            }
+            if is_whitespace && first_space.is_none() {
+                continue;
+            }
+        }
+
+        if is_whitespace {
+            space_width += symbol_width;
+            spaces.push_back(symbol_width);
+        } else {
+            word_width += symbol_width;
+            word_symbols += 1;
+        }
+        has_seen_non_whitespace = !is_whitespace;
+    }
+
+    if word_symbols > 0 || !spaces.is_empty() {
+        if row_symbols == 0 && word_symbols == 0 {
+            rows += 1;
+        } else if row_symbols > 0 {
+            row_symbols += spaces.len();
+        }
+        row_symbols += word_symbols;
+    }
+    if row_symbols > 0 {
+        rows += 1;
+    }
+    rows.max(1)
+}
+
+/// Shortens `path` to at most `max` characters by replacing directories in the
+/// middle with `...`, keeping the first directory and the file name as long as
+/// they fit, e.g. `src/.../deep/file.rs`.
+pub fn truncate_middle(path: &str, max: usize) -> String {
+    let len = path.chars().count();
+    if len <= max {
+        return path.to_string();
+    }
+
+    let parts: Vec<&str> = path.split('/').collect();
+    if let [first, middle @ .., last] = parts.as_slice() {
+        // Keep as many trailing directories as fit after the first one
+        let mut tail = last.to_string();
+        for part in middle.iter().rev() {
+            let longer = format!("{part}/{tail}");
+            if first.chars().count() + "/.../".len() + longer.chars().count() > max {
+                break;
+            }
+            tail = longer;
+        }
+        for shortened in [format!("{first}/.../{tail}"), format!(".../{last}")] {
+            if shortened.chars().count() <= max {
+                return shortened;
+            }
+        }
+    }
+
+    // Not even the file name fits, so cut characters out of the middle
+    if max <= 3 {
+        return ".".repeat(max);
+    }
+    let chars: Vec<char> = path.chars().collect();
+    let front = (max - 3) / 2;
+    let back = max - 3 - front;
+    format!(
+        "{}...{}",
+        chars[..front].iter().collect::<String>(),
+        chars[len - back..].iter().collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{
+        buffer::Buffer,
+        layout::Rect,
+        widgets::{Paragraph, Widget, Wrap},
+    };
+
+    /// Rows of `text` as a paragraph wrapped in `width` columns draws them,
+    /// scrolled down by `scroll` rows.
+    fn rendered_rows(text: &str, width: u16, height: u16, scroll: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height);
+        let mut buffer = Buffer::empty(area);
+        Paragraph::new(text.to_string())
+            .wrap(Wrap { trim: true })
+            .scroll((scroll, 0))
+            .render(area, &mut buffer);
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Rows ratatui fills for `text`, found by drawing it into a pane tall
+    /// enough to hold all of it and counting up to the last row drawn on.
+    fn ratatui_row_count(text: &str, width: u16) -> usize {
+        let rows = rendered_rows(text, width, 200, 0);
+        rows.iter()
+            .rposition(|row| !row.is_empty())
+            .map_or(0, |last| last + 1)
+    }
+
+    #[test]
+    fn short_lines_take_a_row_each() {
+        assert_eq!(wrapped_line_count("one\ntwo\n\nfour", 20), 4);
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries() {
+        // "+let value" moves to the second row whole instead of being split
+        let line = "+    let first = compute(); let value = 1;";
+        assert_eq!(wrapped_line_count(line, 30), ratatui_row_count(line, 30));
+        assert_eq!(wrapped_line_count(line, 30), 2);
+    }
+
+    #[test]
+    fn splits_words_wider_than_the_pane() {
+        let line = "x".repeat(25);
+        assert_eq!(wrapped_line_count(&line, 10), 3);
+        assert_eq!(ratatui_row_count(&line, 10), 3);
+    }
+
+    #[test]
+    fn counts_display_width_of_wide_characters() {
+        // Ten CJK characters are two columns each
+        let line = "漢".repeat(10);
+        assert_eq!(wrapped_line_count(&line, 10), 2);
+        assert_eq!(ratatui_row_count(&line, 10), 2);
+    }
+
+    #[test]
+    fn matches_ratatui_on_a_diff() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs\n\
+                    index 1a2b3c4..5d6e7f8 100644\n\
+                    --- a/src/lib.rs\n\
+                    +++ b/src/lib.rs\n\
+                    @@ -1,3 +1,4 @@ fn main() {\n \
+                    fn main() {\n\
+                    -    println!(\"hello, world, this line is long enough to wrap\");\n\
+                    +    println!(\"hello,    wide    spaced    world — ünïcödé\");\n\
+                    +\tlet tabbed = \"value\";\n \
+                    }";
+        for width in [8, 13, 20, 31, 47, 80] {
+            assert_eq!(
+                wrapped_line_count(diff, width),
+                ratatui_row_count(diff, width),
+                "width {width}"
+            );
+        }
+    }
+
+    #[test]
+    fn scrolling_to_the_end_keeps_the_last_line_visible() {
+        let text = (1..=20)
+            .map(|n| format!("line {n} with a few words that wrap"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (width, height) = (16, 5);
+        let max_scroll = wrapped_line_count(&text, width) - height as usize;
+        let rows = rendered_rows(&text, width, height, max_scroll as u16);
+        assert_eq!(rows.last().unwrap(), "wrap");
+        assert!(rows.iter().all(|row| !row.is_empty()));
+    }
+
+    #[test]
+    fn short_paths_are_left_alone() {
+        assert_eq!(truncate_middle("src/main.rs", 20), "src/main.rs");
+        assert_eq!(truncate_middle("src/main.rs", 11), "src/main.rs");
+    }
+
+    #[test]
+    fn long_paths_lose_directories_from_the_middle() {
+        let path = "src/components/forms/inputs/deep/file.rs";
+        assert_eq!(truncate_middle(path, 30), "src/.../inputs/deep/file.rs");
+        assert_eq!(truncate_middle(path, 20), "src/.../deep/file.rs");
+        assert_eq!(truncate_middle(path, 12), ".../file.rs");
+    }
+
+    #[test]
+    fn long_file_names_are_cut_in_the_middle() {
+        let path = "src/a_really_long_file_name_for_a_module.rs";
+        let shortened = truncate_middle(path, 15);
+        assert_eq!(shortened, "src/a_...ule.rs");
+        assert_eq!(shortened.chars().count(), 15);
+        assert_eq!(truncate_middle(path, 3), "...");
+    }
+
+    #[test]
+    fn counts_characters_rather_than_bytes() {
+        let path = "dokumentit/äöü/ääkköset.txt";
+        assert_eq!(truncate_middle(path, 27), path);
+        assert_eq!(truncate_middle(path, 26), ".../ääkköset.txt");
+    }
+}
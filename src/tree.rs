@@ -0,0 +1,111 @@
+//! Directory tree of the changed files, for the status pane's tree view.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// One visible line of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub depth: usize,
+    pub name: String,
+    /// Full `/`-separated path: the directory's, or the file's key
+    pub path: String,
+    pub is_dir: bool,
+    /// Whether a directory's children are hidden
+    pub collapsed: bool,
+    /// Insertions and deletions, summed over everything below a directory
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug, Default)]
+struct Dir {
+    dirs: BTreeMap<String, Dir>,
+    files: BTreeMap<String, String>,
+}
+
+impl Dir {
+    fn insert(&mut self, components: &[&str], path: &str) {
+        match components {
+            [] => {}
+            [file] => {
+                self.files.insert(file.to_string(), path.to_string());
+            }
+            [dir, rest @ ..] => self
+                .dirs
+                .entry(dir.to_string())
+                .or_default()
+                .insert(rest, path),
+        }
+    }
+
+    fn changes(&self, stats: &HashMap<String, (usize, usize)>) -> (usize, usize) {
+        let files = self
+            .files
+            .values()
+            .map(|path| stats.get(path).copied().unwrap_or_default());
+        let dirs = self.dirs.values().map(|dir| dir.changes(stats));
+        files
+            .chain(dirs)
+            .fold((0, 0), |(insertions, deletions), (i, d)| {
+                (insertions + i, deletions + d)
+            })
+    }
+
+    fn push_rows(
+        &self,
+        prefix: &str,
+        depth: usize,
+        stats: &HashMap<String, (usize, usize)>,
+        collapsed: &HashSet<String>,
+        rows: &mut Vec<TreeRow>,
+    ) {
+        // Directories first, then files, each alphabetically
+        for (name, dir) in &self.dirs {
+            let path = format!("{prefix}{name}");
+            let (insertions, deletions) = dir.changes(stats);
+            let is_collapsed = collapsed.contains(&path);
+            rows.push(TreeRow {
+                depth,
+                name: name.clone(),
+                path: path.clone(),
+                is_dir: true,
+                collapsed: is_collapsed,
+                insertions,
+                deletions,
+            });
+            if !is_collapsed {
+                dir.push_rows(&format!("{path}/"), depth + 1, stats, collapsed, rows);
+            }
+        }
+        for (name, path) in &self.files {
+            let (insertions, deletions) = stats.get(path).copied().unwrap_or_default();
+            rows.push(TreeRow {
+                depth,
+                name: name.clone(),
+                path: path.clone(),
+                is_dir: false,
+                collapsed: false,
+                insertions,
+                deletions,
+            });
+        }
+    }
+}
+
+/// Rows of the tree built from `files`, skipping the contents of directories in
+/// `collapsed`. Counts come from `stats`; files missing there count as zero.
+pub fn tree_rows(
+    files: &[String],
+    stats: &HashMap<String, (usize, usize)>,
+    collapsed: &HashSet<String>,
+) -> Vec<TreeRow> {
+    let mut root = Dir::default();
+    for file in files {
+        let components: Vec<&str> = file.split('/').collect();
+        root.insert(&components, file);
+    }
+
+    let mut rows = Vec::new();
+    root.push_rows("", 0, stats, collapsed, &mut rows);
+    rows
+}
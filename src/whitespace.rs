@@ -0,0 +1,85 @@
+//! Detection of whitespace errors in added diff lines, like `git diff` does
+//! with `color.diff.whitespace`.
+
+use std::ops::Range;
+
+/// Which whitespace problems are flagged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WhitespaceRules {
+    /// Spaces or tabs at the end of a line
+    pub trailing_space: bool,
+    /// A space followed by a tab in the indentation
+    pub space_before_tab: bool,
+    /// Any tab in the indentation
+    pub tab_indent: bool,
+}
+
+impl Default for WhitespaceRules {
+    /// Git's defaults: trailing whitespace and spaces before tabs, but tab
+    /// indentation is fine.
+    fn default() -> Self {
+        Self {
+            trailing_space: true,
+            space_before_tab: true,
+            tab_indent: false,
+        }
+    }
+}
+
+impl WhitespaceRules {
+    /// Parses a comma separated list of `trailing-space`, `space-before-tab` and
+    /// `tab-indent`, or `none`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut rules = Self {
+            trailing_space: false,
+            space_before_tab: false,
+            tab_indent: false,
+        };
+        for rule in input.split(',').map(str::trim) {
+            match rule {
+                "trailing-space" => rules.trailing_space = true,
+                "space-before-tab" => rules.space_before_tab = true,
+                "tab-indent" => rules.tab_indent = true,
+                "none" | "" => {}
+                other => {
+                    return Err(format!(
+                        "unknown rule '{other}' (expected trailing-space, space-before-tab, tab-indent or none)"
+                    ))
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Byte ranges of `line` with whitespace errors. Only added lines are checked;
+    /// the ranges include the offset of the leading `+`.
+    pub fn errors(&self, line: &str) -> Vec<Range<usize>> {
+        let Some(content) = line.strip_prefix('+') else {
+            return Vec::new();
+        };
+        if content.starts_with("++") {
+            // "+++ b/file" header
+            return Vec::new();
+        }
+
+        let mut errors = Vec::new();
+        let indent_len = content.len() - content.trim_start_matches([' ', '\t']).len();
+        let indent = &content[..indent_len];
+        let trailing_start = content.trim_end_matches([' ', '\t']).len();
+
+        if self.space_before_tab {
+            if let (Some(first_space), Some(last_tab)) = (indent.find(' '), indent.rfind('\t')) {
+                if first_space < last_tab {
+                    errors.push(first_space + 1..last_tab + 2);
+                }
+            }
+        }
+        if self.tab_indent && indent.contains('\t') {
+            errors.push(1..indent_len + 1);
+        }
+        if self.trailing_space && trailing_start < content.len() {
+            errors.push(trailing_start + 1..content.len() + 1);
+        }
+        errors
+    }
+}